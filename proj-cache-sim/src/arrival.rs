@@ -0,0 +1,218 @@
+//! Transformations of the arrival times of a trace, keeping the order of its requests: a constant inter-request time
+//! as replayed by the toy CDN, so that simulations are comparable with emulation runs, and scaled or Poisson arrivals
+//! to study the sensitivity to the load.
+
+use proj_models::{OpRequestEvent, RequestEvent, TimeUnit};
+use rand::{Rng, SeedableRng};
+use rand_xorshift::XorShiftRng;
+
+/// How the arrival times of the requests are transformed. The order of the requests is kept. The timestamps of
+/// [`ConstantIrt`](Self::ConstantIrt), [`Scale`](Self::Scale) and [`Poisson`](Self::Poisson) start at 0, while
+/// [`Original`](Self::Original) keeps the timestamps of the trace.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum ArrivalTransform {
+    /// Keep the timestamps of the trace as they are.
+    #[default]
+    Original,
+    /// Requests `irt` apart, as the toy CDN replays a trace.
+    ConstantIrt(TimeUnit),
+    /// Multiply the time since the first request by the factor: 2 doubles the inter-request times, halving the load.
+    Scale(f64),
+    /// Poisson arrivals: the inter-request times are drawn from an exponential distribution with mean `mean_irt`.
+    Poisson { mean_irt: f64, seed: u64 },
+}
+
+/// A request whose arrival time can be transformed.
+pub trait Arrival {
+    fn timestamp_mut(&mut self) -> &mut TimeUnit;
+}
+
+impl Arrival for RequestEvent {
+    fn timestamp_mut(&mut self) -> &mut TimeUnit {
+        &mut self.timestamp
+    }
+}
+
+impl Arrival for OpRequestEvent {
+    fn timestamp_mut(&mut self) -> &mut TimeUnit {
+        &mut self.timestamp
+    }
+}
+
+impl ArrivalTransform {
+    /// Transform the arrival times of `requests`, which should be sorted by timestamp.
+    pub fn apply<I: IntoIterator>(&self, requests: I) -> Retimed<I::IntoIter>
+    where
+        I::Item: Arrival,
+    {
+        let seed = match *self {
+            Self::Poisson { seed, .. } => seed,
+            _ => 0,
+        };
+        Retimed {
+            requests: requests.into_iter(),
+            transform: *self,
+            index: 0,
+            first_timestamp: None,
+            arrival: 0.0,
+            rng: XorShiftRng::seed_from_u64(seed),
+        }
+    }
+}
+
+/// The requests of a trace with transformed arrival times (see [`ArrivalTransform::apply`]).
+pub struct Retimed<I> {
+    requests: I,
+    transform: ArrivalTransform,
+    /// Index of the next request.
+    index: u64,
+    first_timestamp: Option<TimeUnit>,
+    /// Arrival time of the last Poisson arrival, kept unrounded so that rounding errors do not accumulate.
+    arrival: f64,
+    rng: XorShiftRng,
+}
+
+impl<I: Iterator> Iterator for Retimed<I>
+where
+    I::Item: Arrival,
+{
+    type Item = I::Item;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let mut request = self.requests.next()?;
+        let timestamp = request.timestamp_mut();
+        *timestamp = match self.transform {
+            ArrivalTransform::Original => *timestamp,
+            ArrivalTransform::ConstantIrt(irt) => self.index * irt,
+            ArrivalTransform::Scale(factor) => {
+                let first = *self.first_timestamp.get_or_insert(*timestamp);
+                (timestamp.saturating_sub(first) as f64 * factor).round() as TimeUnit
+            }
+            ArrivalTransform::Poisson { mean_irt, .. } => {
+                if self.index > 0 {
+                    // 1 - u is in (0, 1], so its logarithm is finite
+                    let u: f64 = self.rng.gen();
+                    self.arrival -= mean_irt * (1.0 - u).ln();
+                }
+                self.arrival.round() as TimeUnit
+            }
+        };
+        self.index += 1;
+        Some(request)
+    }
+}
+
+impl std::fmt::Display for ArrivalTransform {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Original => write!(f, "original"),
+            Self::ConstantIrt(irt) => write!(f, "irt:{}", irt),
+            Self::Scale(factor) => write!(f, "scale:{}", factor),
+            Self::Poisson { mean_irt, seed } => write!(f, "poisson:{}:{}", mean_irt, seed),
+        }
+    }
+}
+
+impl std::str::FromStr for ArrivalTransform {
+    type Err = String;
+
+    /// `original`, `irt:<irt>`, `scale:<factor>`, or `poisson:<mean irt>[:<seed>]` (default seed: 0), where the
+    /// inter-request times may have units (e.g. `irt:3us`).
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let err = || {
+            format!(
+                "Invalid arrival transform {:?} (expected original, irt:<irt>, scale:<factor> or poisson:<mean irt>[:<seed>])",
+                s
+            )
+        };
+        let parse_irt = |irt: &str| crate::parse_time_unit(irt).map_err(|_| err());
+        let (kind, spec) = s.split_once(':').unwrap_or((s, ""));
+        match kind {
+            "original" if spec.is_empty() => Ok(Self::Original),
+            "irt" => Ok(Self::ConstantIrt(parse_irt(spec)?)),
+            "scale" => match spec.parse::<f64>() {
+                Ok(factor) if factor > 0.0 => Ok(Self::Scale(factor)),
+                _ => Err(err()),
+            },
+            "poisson" => {
+                let (mean_irt, seed) = match spec.split_once(':') {
+                    Some((mean_irt, seed)) => (mean_irt, seed.parse().map_err(|_| err())?),
+                    None => (spec, 0),
+                };
+                let mean_irt = parse_irt(mean_irt)?;
+                if mean_irt == 0 {
+                    return Err(err());
+                }
+                Ok(Self::Poisson {
+                    mean_irt: mean_irt as f64,
+                    seed,
+                })
+            }
+            _ => Err(err()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn trace() -> Vec<RequestEvent> {
+        [(1, 100), (2, 110), (1, 110), (3, 140)]
+            .iter()
+            .map(|&(key, timestamp)| RequestEvent { key, timestamp })
+            .collect()
+    }
+
+    fn timestamps(transform: &str) -> Vec<TimeUnit> {
+        let transform = transform.parse::<ArrivalTransform>().unwrap();
+        let requests = transform.apply(trace()).collect::<Vec<_>>();
+        // the keys are kept in order
+        assert_eq!(
+            requests.iter().map(|r| r.key).collect::<Vec<_>>(),
+            [1, 2, 1, 3]
+        );
+        requests.iter().map(|r| r.timestamp).collect()
+    }
+
+    #[test]
+    fn test_arrival_transforms() {
+        assert_eq!(timestamps("original"), [100, 110, 110, 140]);
+        assert_eq!(timestamps("irt:3us"), [0, 3000, 6000, 9000]);
+        assert_eq!(timestamps("scale:2"), [0, 20, 20, 80]);
+        assert_eq!(timestamps("scale:0.5"), [0, 5, 5, 20]);
+
+        let poisson = timestamps("poisson:10:7");
+        assert_eq!(poisson[0], 0);
+        assert!(poisson.windows(2).all(|w| w[0] <= w[1]));
+        // seeded
+        assert_eq!(timestamps("poisson:10:7"), poisson);
+
+        for s in ["irt:5", "scale:1.5", "poisson:10:7", "original"] {
+            assert_eq!(s.parse::<ArrivalTransform>().unwrap().to_string(), s);
+        }
+        for s in [
+            "irt",
+            "scale:0",
+            "scale:-1",
+            "poisson:0",
+            "poisson:1:x",
+            "fast",
+        ] {
+            assert!(s.parse::<ArrivalTransform>().is_err(), "{}", s);
+        }
+    }
+
+    #[test]
+    fn test_poisson_arrival_rate() {
+        let n = 100_000;
+        let requests = (0..n).map(|key| RequestEvent { key, timestamp: 0 });
+        let transform = ArrivalTransform::Poisson {
+            mean_irt: 1000.0,
+            seed: 0,
+        };
+        let last = transform.apply(requests).last().unwrap().timestamp;
+        let mean_irt = last as f64 / (n - 1) as f64;
+        assert!((mean_irt - 1000.0).abs() < 20.0, "{}", mean_irt);
+    }
+}
@@ -1,11 +1,19 @@
+use std::io::{Read, Write};
+
+use ahash::AHashMap;
 use linked_hash_map::LinkedHashMap;
-use proj_models::TimeUnit;
+use proj_models::{codec::Codec, TimeUnit};
 
 use super::{Cache, ObjectId};
+use crate::checkpoint::{
+    expect_param, expect_tag, invalid_data, read_entries, write_entries, write_tag,
+};
 
 pub struct LRU<K: ObjectId, V> {
     capacity: usize,
     store: LinkedHashMap<K, V, ahash::RandomState>,
+    /// Expiry timestamps of the cached keys written with a TTL.
+    expiries: AHashMap<K, TimeUnit>,
 }
 
 impl<K: ObjectId, V> LRU<K, V> {
@@ -14,6 +22,7 @@ impl<K: ObjectId, V> LRU<K, V> {
         Self {
             capacity,
             store: LinkedHashMap::with_capacity_and_hasher(capacity, Default::default()),
+            expiries: AHashMap::new(),
         }
     }
 }
@@ -21,35 +30,96 @@ impl<K: ObjectId, V> LRU<K, V> {
 impl<K: ObjectId, V> Cache<K, V> for LRU<K, V> {
     const NAME: &'static str = "lru";
     fn write(&mut self, key: K, value: V, _timestamp: TimeUnit) {
+        self.expiries.remove(&key);
         if self.store.contains_key(&key) {
             self.store.insert(key, value);
         } else {
             debug_assert!(self.store.len() <= self.capacity);
             if self.store.len() == self.capacity {
-                self.store.pop_front();
+                if let Some((evicted, _)) = self.store.pop_front() {
+                    self.expiries.remove(&evicted);
+                }
             }
             self.store.insert(key, value);
         }
     }
 
-    fn get(&mut self, key: &K, _timestamp: TimeUnit) -> Option<&V> {
+    fn write_with_ttl(&mut self, key: K, value: V, timestamp: TimeUnit, ttl: TimeUnit) {
+        self.write(key.clone(), value, timestamp);
+        self.expiries.insert(key, timestamp + ttl);
+    }
+
+    fn get(&mut self, key: &K, timestamp: TimeUnit) -> Option<&V> {
+        if self.is_expired(key, timestamp) {
+            self.invalidate(key);
+            return None;
+        }
+        self.store.get_refresh(key).map(|v| &*v)
+    }
+
+    fn get_stale(&mut self, key: &K, _timestamp: TimeUnit) -> Option<&V> {
         self.store.get_refresh(key).map(|v| &*v)
     }
 
+    fn is_expired(&self, key: &K, timestamp: TimeUnit) -> bool {
+        self.expiries
+            .get(key)
+            .is_some_and(|&expiry| expiry <= timestamp)
+    }
+
     fn contains(&self, key: &K) -> bool {
         self.store.contains_key(key)
     }
+
+    fn invalidate(&mut self, key: &K) -> bool {
+        self.expiries.remove(key);
+        self.store.remove(key).is_some()
+    }
+
+    /// The keys are saved from the least recently used.
+    fn save_state(&self, writer: &mut dyn Write) -> std::io::Result<()>
+    where
+        K: Codec,
+        V: Codec,
+    {
+        write_tag(writer, Self::NAME)?;
+        (self.capacity as u64).to_bytes(&mut *writer)?;
+        write_entries(writer, self.store.len(), self.store.iter())?;
+        write_entries(writer, self.expiries.len(), self.expiries.iter())
+    }
+
+    fn load_state(&mut self, reader: &mut dyn Read) -> std::io::Result<()>
+    where
+        K: Codec<Deserialized = K>,
+        V: Codec<Deserialized = V>,
+    {
+        expect_tag(reader, Self::NAME)?;
+        expect_param(reader, "capacity", self.capacity as u64)?;
+        let entries = read_entries::<K, V>(reader)?;
+        if entries.len() > self.capacity {
+            return Err(invalid_data(format!(
+                "{} keys saved, but the capacity is {}",
+                entries.len(),
+                self.capacity
+            )));
+        }
+        self.store.clear();
+        self.store.extend(entries);
+        self.expiries = read_entries(reader)?.into_iter().collect();
+        Ok(())
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use proj_models::RequestEvent;
 
-    use crate::simulator::{run_simulation, RequestResult};
+    use crate::simulator::{compute_statistics, run_simulation, RequestResult};
 
     use super::*;
 
     #[test]
+    #[allow(clippy::bool_assert_comparison)]
     fn test_lru() {
         let mut lru = LRU::new(2);
         lru.write(1, (), 0); // 1
@@ -122,5 +192,30 @@ mod tests {
             )
             .collect::<Vec<_>>()
         );
+
+        let stats = compute_statistics(&results);
+        assert_eq!(stats.num_hits, 4);
+        assert_eq!(stats.num_delayed_hits, 2);
+        assert_eq!(stats.num_misses, 5);
+        assert_eq!(stats.total_latency, 5 + 5 + 2 + 1 + 5 + 5 + 5);
+    }
+
+    #[test]
+    fn test_lru_ttl() {
+        let mut lru = LRU::new(2);
+        lru.write_with_ttl(1, (), 0, 10);
+        lru.write(2, (), 0);
+        assert!(!lru.is_expired(&1, 9));
+        assert_eq!(lru.get(&1, 9), Some(&()));
+        assert!(lru.is_expired(&1, 10));
+        assert_eq!(lru.get_stale(&1, 10), Some(&()));
+        assert!(lru.get(&1, 10).is_none());
+        assert!(!lru.contains(&1));
+        assert!(!lru.is_expired(&2, 100));
+
+        // a write without TTL clears the expiry
+        lru.write_with_ttl(3, (), 0, 1);
+        lru.write(3, (), 0);
+        assert!(!lru.is_expired(&3, 100));
     }
 }
@@ -1,7 +1,15 @@
+use std::io::{Read, Write};
+
 use ahash::AHashMap;
-use proj_models::TimeUnit;
+use proj_models::{
+    codec::{Codec, CodecSize},
+    TimeUnit,
+};
 
 use super::{Cache, ObjectId};
+use crate::checkpoint::{
+    expect_param, expect_tag, invalid_data, read_entries, write_entries, write_tag,
+};
 
 struct ObjectMetaData {
     /// Whether the metadata is not updated yet
@@ -17,6 +25,8 @@ struct ObjectMetaData {
     /// The timestamp of last access. Used to compute the TTNA. (TTNA = curr_timestamp - last_access_timestamp + 1)
     /// We need TTNA to compute the ranking function score = estimated aggregate delay / TTNA. Higher score means higher priority.
     last_access_timestamp: TimeUnit,
+    /// The cost of fetching this object, weighed against its delay by [`CostAwareLRUMinAD`].
+    fetch_cost: f64,
 }
 
 impl ObjectMetaData {
@@ -27,6 +37,7 @@ impl ObjectMetaData {
             cumulative_delay: 0,
             window_start_timestamp: 0,
             last_access_timestamp: 0,
+            fetch_cost: 1.0,
         }
     }
 
@@ -45,8 +56,14 @@ impl ObjectMetaData {
         self.new = false;
     }
 
-    fn score(&self, timestamp: TimeUnit) -> f64 {
-        let estimated_agg_delay = self.cumulative_delay as f64 / self.num_windows as f64;
+    /// `cost_weight` is the delay a unit of fetch cost is worth.
+    fn score(&self, timestamp: TimeUnit, cost_weight: f64) -> f64 {
+        if self.num_windows == 0 {
+            // never accessed (e.g. prefetched), so nothing is known about its delay
+            return 0.0;
+        }
+        let estimated_agg_delay =
+            self.cumulative_delay as f64 / self.num_windows as f64 + cost_weight * self.fetch_cost;
         debug_assert!(
             timestamp >= self.last_access_timestamp,
             "timestamp should be greater than or equal to last_access_timestamp"
@@ -56,27 +73,132 @@ impl ObjectMetaData {
     }
 }
 
+impl Codec for ObjectMetaData {
+    type Deserialized = Self;
+
+    const SIZE_IN_BYTES: CodecSize = CodecSize::Static(1 + 4 + 8 + 8 + 8 + 8);
+
+    fn size_in_bytes(&self) -> usize {
+        Self::SIZE_IN_BYTES.get_size_or_panic()
+    }
+
+    fn to_bytes<W: Write>(&self, mut writer: W) -> std::io::Result<()> {
+        (self.new as u8).to_bytes(&mut writer)?;
+        self.num_windows.to_bytes(&mut writer)?;
+        self.cumulative_delay.to_bytes(&mut writer)?;
+        self.window_start_timestamp.to_bytes(&mut writer)?;
+        self.last_access_timestamp.to_bytes(&mut writer)?;
+        self.fetch_cost.to_bytes(&mut writer)
+    }
+
+    fn from_bytes<R: Read>(mut reader: R) -> std::io::Result<Self::Deserialized> {
+        Ok(Self {
+            new: u8::from_bytes(&mut reader)? != 0,
+            num_windows: u32::from_bytes(&mut reader)?,
+            cumulative_delay: TimeUnit::from_bytes(&mut reader)?,
+            window_start_timestamp: TimeUnit::from_bytes(&mut reader)?,
+            last_access_timestamp: TimeUnit::from_bytes(&mut reader)?,
+            fetch_cost: f64::from_bytes(&mut reader)?,
+        })
+    }
+}
+
 pub struct LRUMinAD<K: ObjectId, V> {
     capacity: usize,
     value_store: AHashMap<K, V>,
     metadata_store: AHashMap<K, ObjectMetaData>,
+    /// Expiry timestamps of the cached keys written with a TTL.
+    expiries: AHashMap<K, TimeUnit>,
     estimated_miss_latency: TimeUnit,
+    /// The delay a unit of fetch cost is worth in the ranking, 0 to ignore the costs.
+    cost_weight: f64,
 }
 
 impl<K: ObjectId, V> LRUMinAD<K, V> {
+    /// Known limitation: with an `estimated_miss_latency` of 0, the aggregate delay of every object is 0, so the
+    /// ranking ignores the accesses and the cache evicts the key with the smallest hash, which does worse than LRU
+    /// on traces with reuse.
     pub fn new(capacity: usize, estimated_miss_latency: TimeUnit) -> Self {
         Self {
             capacity,
             value_store: AHashMap::new(),
             metadata_store: AHashMap::new(),
+            expiries: AHashMap::new(),
             estimated_miss_latency,
+            cost_weight: 0.0,
         }
     }
+
+    fn metadata_mut(&mut self, key: &K) -> &mut ObjectMetaData {
+        self.metadata_store
+            .entry(key.clone())
+            .or_insert_with(ObjectMetaData::new)
+    }
+
+    fn write_state(&self, writer: &mut dyn Write, tag: &str) -> std::io::Result<()>
+    where
+        K: Codec,
+        V: Codec,
+    {
+        write_tag(writer, tag)?;
+        (self.capacity as u64).to_bytes(&mut *writer)?;
+        self.estimated_miss_latency.to_bytes(&mut *writer)?;
+        self.cost_weight.to_bytes(&mut *writer)?;
+        write_entries(writer, self.value_store.len(), self.value_store.iter())?;
+        write_entries(
+            writer,
+            self.metadata_store.len(),
+            self.metadata_store.iter(),
+        )?;
+        write_entries(writer, self.expiries.len(), self.expiries.iter())
+    }
+
+    fn read_state(&mut self, reader: &mut dyn Read, tag: &str) -> std::io::Result<()>
+    where
+        K: Codec<Deserialized = K>,
+        V: Codec<Deserialized = V>,
+    {
+        expect_tag(reader, tag)?;
+        expect_param(reader, "capacity", self.capacity as u64)?;
+        expect_param(
+            reader,
+            "estimated miss latency",
+            self.estimated_miss_latency,
+        )?;
+        let cost_weight = f64::from_bytes(&mut *reader)?;
+        if cost_weight != self.cost_weight {
+            return Err(invalid_data(format!(
+                "the saved cost weight is {}, but the cache has {}",
+                cost_weight, self.cost_weight
+            )));
+        }
+        let values = read_entries::<K, V>(reader)?;
+        let metadata = read_entries::<K, ObjectMetaData>(reader)?;
+        if values.len() > self.capacity {
+            return Err(invalid_data(format!(
+                "{} keys saved, but the capacity is {}",
+                values.len(),
+                self.capacity
+            )));
+        }
+        self.value_store = values.into_iter().collect();
+        self.metadata_store = metadata.into_iter().collect();
+        if let Some(key) = self
+            .value_store
+            .keys()
+            .find(|k| !self.metadata_store.contains_key(k))
+        {
+            return Err(invalid_data(format!("no metadata saved for {:?}", key)));
+        }
+        self.expiries = read_entries(reader)?.into_iter().collect();
+        Ok(())
+    }
 }
 
 impl<K: ObjectId, V> Cache<K, V> for LRUMinAD<K, V> {
     const NAME: &'static str = "lru-mad";
     fn write(&mut self, key: K, value: V, timestamp: TimeUnit) {
+        self.expiries.remove(&key);
         if self.value_store.contains_key(&key) {
             self.value_store.insert(key, value);
         } else {
@@ -84,29 +206,215 @@ impl<K: ObjectId, V> Cache<K, V> for LRUMinAD<K, V> {
                 let key_to_evict = self
                     .value_store
                     .keys()
-                    .map(|k| (k, self.metadata_store.get(k).unwrap().score(timestamp)))
-                    .min_by(|(_, score1), (_, score2)| score1.partial_cmp(score2).unwrap())
+                    .map(|k| {
+                        let metadata = self.metadata_store.get(k).unwrap();
+                        (k, metadata.score(timestamp, self.cost_weight))
+                    })
+                    // ties are broken by the seeded hash, not by the iteration order of the map, so that runs are
+                    // reproducible
+                    .min_by(|(k1, score1), (k2, score2)| {
+                        score1
+                            .partial_cmp(score2)
+                            .unwrap()
+                            .then_with(|| k1.get_hash().cmp(&k2.get_hash()))
+                    })
                     .expect("value_store should not be empty")
                     .0
                     .clone();
                 self.value_store.remove(&key_to_evict);
+                self.expiries.remove(&key_to_evict);
                 // key is kept in metadata_store forever, at this point
             }
+            self.metadata_mut(&key);
             self.value_store.insert(key, value);
             debug_assert!(self.value_store.len() <= self.capacity);
         }
     }
 
+    fn write_with_ttl(&mut self, key: K, value: V, timestamp: TimeUnit, ttl: TimeUnit) {
+        self.write(key.clone(), value, timestamp);
+        self.expiries.insert(key, timestamp + ttl);
+    }
+
     fn get(&mut self, key: &K, timestamp: TimeUnit) -> Option<&V> {
-        self.metadata_store
-            .entry(key.clone())
-            .or_insert_with(ObjectMetaData::new)
-            .update(timestamp, self.estimated_miss_latency);
+        if self.is_expired(key, timestamp) {
+            self.invalidate(key);
+        }
+        self.get_stale(key, timestamp)
+    }
+
+    fn get_stale(&mut self, key: &K, timestamp: TimeUnit) -> Option<&V> {
+        let estimated_miss_latency = self.estimated_miss_latency;
+        self.metadata_mut(key)
+            .update(timestamp, estimated_miss_latency);
 
         self.value_store.get(key)
     }
 
+    fn is_expired(&self, key: &K, timestamp: TimeUnit) -> bool {
+        self.expiries
+            .get(key)
+            .is_some_and(|&expiry| expiry <= timestamp)
+    }
+
     fn contains(&self, key: &K) -> bool {
         self.value_store.contains_key(key)
     }
+
+    fn invalidate(&mut self, key: &K) -> bool {
+        // the metadata is kept, as for evicted keys
+        self.expiries.remove(key);
+        self.value_store.remove(key).is_some()
+    }
+
+    fn set_fetch_cost(&mut self, key: &K, cost: f64) {
+        self.metadata_mut(key).fetch_cost = cost;
+    }
+
+    /// The metadata of evicted keys is saved too.
+    fn save_state(&self, writer: &mut dyn Write) -> std::io::Result<()>
+    where
+        K: Codec,
+        V: Codec,
+    {
+        self.write_state(writer, Self::NAME)
+    }
+
+    fn load_state(&mut self, reader: &mut dyn Read) -> std::io::Result<()>
+    where
+        K: Codec<Deserialized = K>,
+        V: Codec<Deserialized = V>,
+    {
+        self.read_state(reader, Self::NAME)
+    }
+}
+
+/// LRU-MAD whose ranking weighs the aggregate delay of an object against the cost of fetching it (see
+/// [`Cache::set_fetch_cost`]): the estimated aggregate delay is increased by `cost_coefficient * fetch cost *
+/// estimated miss latency`, so that with a coefficient of 1, a unit of cost is worth a miss latency of delay. Costly
+/// objects are kept longer than cheap objects with the same delay.
+pub struct CostAwareLRUMinAD<K: ObjectId, V>(LRUMinAD<K, V>);
+
+impl<K: ObjectId, V> CostAwareLRUMinAD<K, V> {
+    pub fn new(capacity: usize, estimated_miss_latency: TimeUnit, cost_coefficient: f64) -> Self {
+        let mut cache = LRUMinAD::new(capacity, estimated_miss_latency);
+        cache.cost_weight = cost_coefficient * estimated_miss_latency as f64;
+        Self(cache)
+    }
+}
+
+impl<K: ObjectId, V> Cache<K, V> for CostAwareLRUMinAD<K, V> {
+    const NAME: &'static str = "lru-mad-cost";
+
+    fn write(&mut self, key: K, value: V, timestamp: TimeUnit) {
+        self.0.write(key, value, timestamp)
+    }
+
+    fn write_with_ttl(&mut self, key: K, value: V, timestamp: TimeUnit, ttl: TimeUnit) {
+        self.0.write_with_ttl(key, value, timestamp, ttl)
+    }
+
+    fn get(&mut self, key: &K, timestamp: TimeUnit) -> Option<&V> {
+        self.0.get(key, timestamp)
+    }
+
+    fn get_stale(&mut self, key: &K, timestamp: TimeUnit) -> Option<&V> {
+        self.0.get_stale(key, timestamp)
+    }
+
+    fn is_expired(&self, key: &K, timestamp: TimeUnit) -> bool {
+        self.0.is_expired(key, timestamp)
+    }
+
+    fn contains(&self, key: &K) -> bool {
+        self.0.contains(key)
+    }
+
+    fn invalidate(&mut self, key: &K) -> bool {
+        self.0.invalidate(key)
+    }
+
+    fn set_fetch_cost(&mut self, key: &K, cost: f64) {
+        self.0.set_fetch_cost(key, cost)
+    }
+
+    fn save_state(&self, writer: &mut dyn Write) -> std::io::Result<()>
+    where
+        K: Codec,
+        V: Codec,
+    {
+        self.0.write_state(writer, Self::NAME)
+    }
+
+    fn load_state(&mut self, reader: &mut dyn Read) -> std::io::Result<()>
+    where
+        K: Codec<Deserialized = K>,
+        V: Codec<Deserialized = V>,
+    {
+        self.0.read_state(reader, Self::NAME)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cache::DynCache;
+
+    #[test]
+    fn test_lru_mad_ttl() {
+        let mut cache = LRUMinAD::new(2, 10);
+        assert!(cache.get(&1, 0).is_none());
+        cache.write_with_ttl(1, (), 0, 10);
+        assert_eq!(cache.get(&1, 5), Some(&()));
+        assert!(cache.is_expired(&1, 10));
+        assert_eq!(cache.get_stale(&1, 10), Some(&()));
+        assert!(cache.get(&1, 11).is_none());
+        assert!(!cache.contains(&1));
+
+        // the expired key keeps its metadata, so it can be written and evicted again
+        cache.write(1, (), 11);
+        assert!(cache.get(&2, 12).is_none());
+        cache.write(2, (), 12);
+        assert!(cache.get(&3, 13).is_none());
+        cache.write(3, (), 13);
+        assert!(cache.contains(&3));
+        assert!(!cache.is_expired(&3, 100));
+    }
+
+    #[test]
+    fn test_lru_mad_write_without_access() {
+        // e.g. prefetched keys are written without being accessed, and are evicted first
+        let mut cache = LRUMinAD::new(2, 10);
+        assert!(cache.get(&1, 0).is_none());
+        cache.write(1, (), 0);
+        cache.write(2, (), 1);
+        cache.write(3, (), 2);
+        assert!(cache.contains(&1));
+        assert!(!cache.contains(&2));
+        assert!(cache.contains(&3));
+    }
+
+    #[test]
+    fn test_cost_aware_lru_mad_keeps_costly_objects() {
+        // 1 and 2 have the same delay, but 1 is ten times as costly to fetch
+        let mut plain = LRUMinAD::<u64, ()>::new(2, 10);
+        let mut cost_aware = CostAwareLRUMinAD::new(2, 10, 1.0);
+        for (key, cost, timestamp) in [(1, 10.0, 0), (2, 1.0, 1)] {
+            for cache in [&mut plain as &mut dyn DynCache<u64, ()>, &mut cost_aware] {
+                assert!(cache.dyn_get(&key, timestamp).is_none());
+                cache.dyn_set_fetch_cost(&key, cost);
+                cache.dyn_write(key, (), timestamp);
+            }
+        }
+        assert!(plain.get(&3, 2).is_none());
+        plain.write(3, (), 2);
+        assert!(!plain.contains(&1));
+        assert!(plain.contains(&2));
+
+        assert!(cost_aware.get(&3, 2).is_none());
+        cost_aware.set_fetch_cost(&3, 1.0);
+        cost_aware.write(3, (), 2);
+        assert!(cost_aware.contains(&1));
+        assert!(!cost_aware.contains(&2));
+    }
 }
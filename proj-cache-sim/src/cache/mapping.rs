@@ -0,0 +1,378 @@
+//! Set-index functions, mapping a key to the set (or the candidate sets) of a [`super::MultiCache`].
+
+use std::{
+    fmt::Display,
+    hash::{Hash, Hasher},
+    str::FromStr,
+};
+
+use super::ObjectId;
+
+/// How a [`super::MultiCache`] maps a key to its set.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SetMapping {
+    /// `ahash` of the key, modulo `k`.
+    #[default]
+    Hash,
+    /// The raw key, modulo `k`, like the index bits of a hardware cache.
+    Modulo,
+    /// Fibonacci (multiplicative) hashing of the raw key.
+    Multiplicative,
+    /// The xxHash64 avalanche of the raw key, modulo `k`.
+    XxHash,
+    /// Consistent hashing on a ring with `virtual_nodes` points per set, like a cluster of cache servers.
+    Consistent { virtual_nodes: usize },
+    /// Skewed-associative placement: a key can live in any of `ways` candidate sets, each chosen by a different
+    /// hash function. Lookups probe all candidates, and new keys go to the least loaded candidate.
+    Skewed { ways: usize },
+}
+
+impl Display for SetMapping {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Hash => write!(f, "hash"),
+            Self::Modulo => write!(f, "modulo"),
+            Self::Multiplicative => write!(f, "multiplicative"),
+            Self::XxHash => write!(f, "xxhash"),
+            Self::Consistent { virtual_nodes } => write!(f, "consistent:{}", virtual_nodes),
+            Self::Skewed { ways } => write!(f, "skewed:{}", ways),
+        }
+    }
+}
+
+impl FromStr for SetMapping {
+    type Err = String;
+
+    /// Parse `hash`, `modulo`, `multiplicative`, `xxhash`, `consistent[:<virtual_nodes>]` or `skewed[:<ways>]`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (name, param) = match s.split_once(':') {
+            Some((name, param)) => (
+                name,
+                Some(
+                    param
+                        .parse::<usize>()
+                        .map_err(|e| format!("Invalid parameter in {}: {}", s, e))?,
+                ),
+            ),
+            None => (s, None),
+        };
+        let mapping = match (name, param) {
+            ("hash", None) => Self::Hash,
+            ("modulo", None) => Self::Modulo,
+            ("multiplicative", None) => Self::Multiplicative,
+            ("xxhash", None) => Self::XxHash,
+            ("consistent", virtual_nodes) => Self::Consistent {
+                virtual_nodes: virtual_nodes.unwrap_or(100),
+            },
+            ("skewed", ways) => Self::Skewed {
+                ways: ways.unwrap_or(2),
+            },
+            _ => return Err(format!("Unknown set mapping: {}", s)),
+        };
+        match mapping {
+            Self::Consistent { virtual_nodes: 0 } | Self::Skewed { ways: 0 } => {
+                Err(format!("The parameter of {} should be positive", s))
+            }
+            mapping => Ok(mapping),
+        }
+    }
+}
+
+/// A hasher that keeps integers as they are, to recover the raw value of integer keys.
+#[derive(Default)]
+struct IdentityHasher(u64);
+
+impl Hasher for IdentityHasher {
+    fn finish(&self) -> u64 {
+        self.0
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        // not an integer key: fold the bytes with FNV-1a
+        for &byte in bytes {
+            self.0 = (self.0 ^ byte as u64).wrapping_mul(0x100000001b3);
+        }
+    }
+
+    fn write_u64(&mut self, i: u64) {
+        self.0 = i;
+    }
+
+    fn write_u32(&mut self, i: u32) {
+        self.0 = i as u64;
+    }
+
+    fn write_usize(&mut self, i: usize) {
+        self.0 = i as u64;
+    }
+}
+
+/// The raw value of an integer key.
+pub fn raw_key<K: Hash>(key: &K) -> u64 {
+    let mut hasher = IdentityHasher::default();
+    key.hash(&mut hasher);
+    hasher.finish()
+}
+
+/// The xxHash64 avalanche (finalization) step.
+pub fn xxhash_avalanche(mut h: u64) -> u64 {
+    h ^= h >> 33;
+    h = h.wrapping_mul(0xc2b2ae3d27d4eb4f);
+    h ^= h >> 29;
+    h = h.wrapping_mul(0x165667b19e3779f9);
+    h ^ (h >> 32)
+}
+
+/// A seeded `ahash` of the key. The seeds are fixed, so that set mappings are the same across runs.
+pub(crate) fn seeded_hash<K: Hash>(key: &K, seed: u64) -> u64 {
+    ahash::RandomState::with_seeds(
+        seed,
+        0x243f6a8885a308d3,
+        0x13198a2e03707344,
+        0xa4093822299f31d0,
+    )
+    .hash_one(key)
+}
+
+/// A consistent-hashing ring, where each node owns `virtual_nodes` points on the ring. A key belongs to the node of
+/// the first point at or after the hash of the key.
+#[derive(Debug, Clone)]
+pub struct ConsistentHashRing {
+    virtual_nodes: usize,
+    /// `(point, node)`, sorted by point
+    points: Vec<(u64, usize)>,
+}
+
+impl ConsistentHashRing {
+    /// A ring with nodes `0..num_nodes`.
+    pub fn new(num_nodes: usize, virtual_nodes: usize) -> Self {
+        assert!(virtual_nodes > 0, "virtual_nodes should be positive");
+        let mut ring = Self {
+            virtual_nodes,
+            points: Vec::new(),
+        };
+        (0..num_nodes).for_each(|node| ring.add_node(node));
+        ring
+    }
+
+    fn node_points(&self, node: usize) -> impl Iterator<Item = (u64, usize)> {
+        (0..self.virtual_nodes).map(move |v| (seeded_hash(&(node, v), 0x5eed), node))
+    }
+
+    /// Add a node to the ring. Only the keys on the arcs taken over by the new node are remapped.
+    pub fn add_node(&mut self, node: usize) {
+        assert!(
+            !self.contains_node(node),
+            "node {} is already in the ring",
+            node
+        );
+        let points = self.node_points(node).collect::<Vec<_>>();
+        self.points.extend(points);
+        self.points.sort_unstable();
+    }
+
+    /// Remove a node from the ring. Only the keys of the removed node are remapped.
+    pub fn remove_node(&mut self, node: usize) {
+        self.points.retain(|&(_, n)| n != node);
+    }
+
+    pub fn contains_node(&self, node: usize) -> bool {
+        self.points.iter().any(|&(_, n)| n == node)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.points.is_empty()
+    }
+
+    /// The node owning `hash`, or `None` if the ring is empty.
+    pub fn node_for_hash(&self, hash: u64) -> Option<usize> {
+        if self.points.is_empty() {
+            return None;
+        }
+        let idx = self.points.partition_point(|&(point, _)| point < hash);
+        Some(self.points[idx % self.points.len()].1)
+    }
+
+    /// The node owning `key`, or `None` if the ring is empty.
+    pub fn node_for<K: Hash>(&self, key: &K) -> Option<usize> {
+        self.node_for_hash(seeded_hash(key, 0))
+    }
+}
+
+/// The state needed to evaluate a [`SetMapping`] for `k` sets.
+pub(crate) struct SetIndexer {
+    k: usize,
+    mapping: SetMapping,
+    ring: Option<ConsistentHashRing>,
+}
+
+impl SetIndexer {
+    pub(crate) fn new(k: usize, mapping: SetMapping) -> Self {
+        assert!(k > 0, "number of sets should be positive");
+        let ring = match mapping {
+            SetMapping::Consistent { virtual_nodes } => {
+                Some(ConsistentHashRing::new(k, virtual_nodes))
+            }
+            _ => None,
+        };
+        Self { k, mapping, ring }
+    }
+
+    pub(crate) fn mapping(&self) -> SetMapping {
+        self.mapping
+    }
+
+    /// The consistent-hashing ring, if the mapping is [`SetMapping::Consistent`].
+    pub(crate) fn ring(&self) -> Option<&ConsistentHashRing> {
+        self.ring.as_ref()
+    }
+
+    pub(crate) fn ring_mut(&mut self) -> Option<&mut ConsistentHashRing> {
+        self.ring.as_mut()
+    }
+
+    /// The number of candidate sets of a key.
+    pub(crate) fn num_candidates(&self) -> usize {
+        match self.mapping {
+            SetMapping::Skewed { ways } => ways,
+            _ => 1,
+        }
+    }
+
+    /// The `i`-th candidate set of `key`, for `i < num_candidates()`.
+    pub(crate) fn candidate<K: ObjectId>(&self, key: &K, i: usize) -> usize {
+        let k = self.k as u64;
+        let idx = match self.mapping {
+            SetMapping::Hash => seeded_hash(key, 0) % k,
+            SetMapping::Modulo => raw_key(key) % k,
+            SetMapping::Multiplicative => {
+                let h = raw_key(key).wrapping_mul(0x9e3779b97f4a7c15);
+                ((h as u128 * k as u128) >> 64) as u64
+            }
+            SetMapping::XxHash => xxhash_avalanche(raw_key(key)) % k,
+            SetMapping::Consistent { .. } => {
+                self.ring.as_ref().unwrap().node_for(key).unwrap() as u64
+            }
+            SetMapping::Skewed { .. } => seeded_hash(key, i as u64 + 1) % k,
+        };
+        idx as usize
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        cache::{construct_k_way_cache_with_mapping, lru::LRU, lru_mad::LRUMinAD, Cache},
+        simulator::run_simulation,
+    };
+    use proj_models::RequestEvent;
+
+    #[test]
+    fn test_parse_set_mapping() {
+        for s in [
+            "hash",
+            "modulo",
+            "multiplicative",
+            "xxhash",
+            "consistent:10",
+            "skewed:4",
+        ] {
+            assert_eq!(s.parse::<SetMapping>().unwrap().to_string(), s);
+        }
+        assert_eq!(
+            "consistent".parse::<SetMapping>().unwrap(),
+            SetMapping::Consistent { virtual_nodes: 100 }
+        );
+        assert!("skewed:0".parse::<SetMapping>().is_err());
+        assert!("modulo:2".parse::<SetMapping>().is_err());
+        assert!("unknown".parse::<SetMapping>().is_err());
+    }
+
+    #[test]
+    fn test_modulo_uses_raw_key() {
+        let indexer = SetIndexer::new(8, SetMapping::Modulo);
+        for key in 0u64..100 {
+            assert_eq!(indexer.candidate(&key, 0), key as usize % 8);
+        }
+    }
+
+    #[test]
+    fn test_mappings_are_balanced() {
+        let k = 16;
+        for mapping in [
+            SetMapping::Hash,
+            SetMapping::Multiplicative,
+            SetMapping::XxHash,
+            SetMapping::Consistent { virtual_nodes: 200 },
+            SetMapping::Skewed { ways: 2 },
+        ] {
+            let indexer = SetIndexer::new(k, mapping);
+            let mut counts = vec![0; k];
+            for key in 0u64..160_000 {
+                counts[indexer.candidate(&(key * 64), 0)] += 1;
+            }
+            // every set gets a fair share, even for strided keys
+            assert!(
+                counts.iter().all(|&c| c > 5_000 && c < 15_000),
+                "{}: {:?}",
+                mapping,
+                counts
+            );
+        }
+    }
+
+    #[test]
+    fn test_skewed_placement() {
+        let mut cache =
+            construct_k_way_cache_with_mapping(8, SetMapping::Skewed { ways: 2 }, |_| LRU::new(4));
+        for key in 0u64..16 {
+            assert!(cache.get(&key, key).is_none());
+            cache.write(key, (), key);
+        }
+        // 16 keys fit in 32 lines when new keys go to the least loaded candidate
+        for key in 0u64..16 {
+            assert!(cache.contains(&key), "key {} is evicted", key);
+        }
+        // rewriting a key does not duplicate it in another candidate
+        cache.write(0, (), 16);
+        assert_eq!(cache.num_insertions.iter().sum::<usize>(), 16);
+
+        // a missed key is written to the set where it was accessed, which LRU-MAD relies on for its metadata
+        let events = (0..10_000)
+            .map(|i| RequestEvent {
+                key: (i * 7919) % 1000,
+                timestamp: i,
+            })
+            .collect::<Vec<_>>();
+        let mut cache =
+            construct_k_way_cache_with_mapping(8, SetMapping::Skewed { ways: 2 }, |_| {
+                LRUMinAD::new(16, 50)
+            });
+        run_simulation(&mut cache, events, 50);
+        assert!(cache.pending_placements.is_empty());
+    }
+
+    #[test]
+    fn test_consistent_hash_ring_remaps_only_affected_keys() {
+        let mut ring = ConsistentHashRing::new(8, 100);
+        let before = (0u64..10_000)
+            .map(|key| ring.node_for(&key).unwrap())
+            .collect::<Vec<_>>();
+
+        ring.remove_node(3);
+        for (key, &node) in (0u64..10_000).zip(&before) {
+            let after = ring.node_for(&key).unwrap();
+            assert_ne!(after, 3);
+            if node != 3 {
+                assert_eq!(after, node);
+            }
+        }
+
+        ring.add_node(3);
+        for (key, &node) in (0u64..10_000).zip(&before) {
+            assert_eq!(ring.node_for(&key).unwrap(), node);
+        }
+        assert!(ConsistentHashRing::new(0, 10).node_for(&1u64).is_none());
+    }
+}
@@ -1,19 +1,26 @@
 pub mod lru;
 pub mod lru_mad;
+pub mod mapping;
+#[cfg(test)]
+mod properties;
+pub mod registry;
 
 use std::{
     fmt::Debug,
-    hash::{Hash, Hasher as _},
+    hash::Hash,
+    io::{Read, Write},
 };
 
-use ahash::AHasher;
-use proj_models::TimeUnit;
+use ahash::AHashMap;
+use mapping::{SetIndexer, SetMapping};
+use proj_models::{codec::Codec, TimeUnit};
+
+use crate::checkpoint::{expect_param, expect_tag, write_tag};
 
 pub trait ObjectId: Hash + Eq + PartialEq + Clone + Debug {
+    /// A hash with fixed seeds, so that it is the same across runs.
     fn get_hash(&self) -> u64 {
-        let mut hasher = AHasher::default();
-        self.hash(&mut hasher);
-        hasher.finish()
+        mapping::seeded_hash(self, 0)
     }
 }
 
@@ -30,48 +37,431 @@ pub trait Cache<K: ObjectId, V> {
     /// `timestamp` is only used for heuristics for the eviction policy (to compute the estimated TTNA)
     fn write(&mut self, key: K, value: V, timestamp: TimeUnit);
 
+    /// Same as [`Cache::write`], but the key expires `ttl` after `timestamp`. Keys written by [`Cache::write`] never
+    /// expire.
+    fn write_with_ttl(&mut self, key: K, value: V, timestamp: TimeUnit, ttl: TimeUnit);
+
     /// Get the value of a key in the cache, and the cache might update its internal state corresponding to the access.
+    /// An expired key is a miss, and is removed from the cache.
     fn get(&mut self, key: &K, timestamp: TimeUnit) -> Option<&V>;
 
+    /// Same as [`Cache::get`], but an expired key is still a hit, e.g. to serve a stale object while it is
+    /// revalidated.
+    fn get_stale(&mut self, key: &K, timestamp: TimeUnit) -> Option<&V>;
+
+    /// Check if the key is in the cache but has expired at `timestamp`.
+    fn is_expired(&self, key: &K, timestamp: TimeUnit) -> bool;
+
     /// Check if the key is in the cache.
     fn contains(&self, key: &K) -> bool;
+
+    /// Remove a key from the cache, e.g. when the object is deleted or updated in the backing store. Return whether
+    /// the key was in the cache.
+    fn invalidate(&mut self, key: &K) -> bool;
+
+    /// Record the cost of fetching `key` from the backing store (see [`crate::cost`]), before the key is written.
+    /// Ignored by policies that are not cost-aware.
+    fn set_fetch_cost(&mut self, _key: &K, _cost: f64) {}
+
+    /// Write the cached keys with their values, eviction order and policy metadata (see [`crate::checkpoint`]).
+    /// Unsupported by default.
+    fn save_state(&self, _writer: &mut dyn Write) -> std::io::Result<()>
+    where
+        K: Codec,
+        V: Codec,
+    {
+        Err(std::io::Error::new(
+            std::io::ErrorKind::Unsupported,
+            format!("{} does not support checkpoints", Self::NAME),
+        ))
+    }
+
+    /// Replace the state of the cache with a state written by [`Cache::save_state`] of a cache with the same policy
+    /// and parameters. Unsupported by default.
+    fn load_state(&mut self, _reader: &mut dyn Read) -> std::io::Result<()>
+    where
+        K: Codec<Deserialized = K>,
+        V: Codec<Deserialized = V>,
+    {
+        Err(std::io::Error::new(
+            std::io::ErrorKind::Unsupported,
+            format!("{} does not support checkpoints", Self::NAME),
+        ))
+    }
+}
+
+/// An object-safe counterpart of [`Cache`], implemented for every [`Cache`], so that policies can be chosen at
+/// runtime and stored as `Box<dyn DynCache>` (see [`BoxedCache`]).
+pub trait DynCache<K: ObjectId, V> {
+    /// See [`Cache::NAME`].
+    fn name(&self) -> &'static str;
+    /// See [`Cache::write`].
+    fn dyn_write(&mut self, key: K, value: V, timestamp: TimeUnit);
+    /// See [`Cache::write_with_ttl`].
+    fn dyn_write_with_ttl(&mut self, key: K, value: V, timestamp: TimeUnit, ttl: TimeUnit);
+    /// See [`Cache::get`].
+    fn dyn_get(&mut self, key: &K, timestamp: TimeUnit) -> Option<&V>;
+    /// See [`Cache::get_stale`].
+    fn dyn_get_stale(&mut self, key: &K, timestamp: TimeUnit) -> Option<&V>;
+    /// See [`Cache::is_expired`].
+    fn dyn_is_expired(&self, key: &K, timestamp: TimeUnit) -> bool;
+    /// See [`Cache::contains`].
+    fn dyn_contains(&self, key: &K) -> bool;
+    /// See [`Cache::invalidate`].
+    fn dyn_invalidate(&mut self, key: &K) -> bool;
+    /// See [`Cache::set_fetch_cost`].
+    fn dyn_set_fetch_cost(&mut self, key: &K, cost: f64);
+    /// See [`Cache::save_state`].
+    fn dyn_save_state(&self, writer: &mut dyn Write) -> std::io::Result<()>
+    where
+        K: Codec,
+        V: Codec;
+    /// See [`Cache::load_state`].
+    fn dyn_load_state(&mut self, reader: &mut dyn Read) -> std::io::Result<()>
+    where
+        K: Codec<Deserialized = K>,
+        V: Codec<Deserialized = V>;
+}
+
+impl<K: ObjectId, V, C: Cache<K, V>> DynCache<K, V> for C {
+    fn name(&self) -> &'static str {
+        C::NAME
+    }
+
+    fn dyn_write(&mut self, key: K, value: V, timestamp: TimeUnit) {
+        self.write(key, value, timestamp)
+    }
+
+    fn dyn_write_with_ttl(&mut self, key: K, value: V, timestamp: TimeUnit, ttl: TimeUnit) {
+        self.write_with_ttl(key, value, timestamp, ttl)
+    }
+
+    fn dyn_get(&mut self, key: &K, timestamp: TimeUnit) -> Option<&V> {
+        self.get(key, timestamp)
+    }
+
+    fn dyn_get_stale(&mut self, key: &K, timestamp: TimeUnit) -> Option<&V> {
+        self.get_stale(key, timestamp)
+    }
+
+    fn dyn_is_expired(&self, key: &K, timestamp: TimeUnit) -> bool {
+        self.is_expired(key, timestamp)
+    }
+
+    fn dyn_contains(&self, key: &K) -> bool {
+        self.contains(key)
+    }
+
+    fn dyn_invalidate(&mut self, key: &K) -> bool {
+        self.invalidate(key)
+    }
+
+    fn dyn_set_fetch_cost(&mut self, key: &K, cost: f64) {
+        self.set_fetch_cost(key, cost)
+    }
+
+    fn dyn_save_state(&self, writer: &mut dyn Write) -> std::io::Result<()>
+    where
+        K: Codec,
+        V: Codec,
+    {
+        self.save_state(writer)
+    }
+
+    fn dyn_load_state(&mut self, reader: &mut dyn Read) -> std::io::Result<()>
+    where
+        K: Codec<Deserialized = K>,
+        V: Codec<Deserialized = V>,
+    {
+        self.load_state(reader)
+    }
+}
+
+/// A type-erased cache. It implements [`Cache`] itself, so it can be simulated or used as the sets of a
+/// [`MultiCache`] with different policies in different sets.
+pub struct BoxedCache<K: ObjectId, V>(Box<dyn DynCache<K, V> + Send>);
+
+impl<K: ObjectId, V> BoxedCache<K, V> {
+    pub fn new<C: Cache<K, V> + Send + 'static>(cache: C) -> Self {
+        Self(Box::new(cache))
+    }
+
+    /// The name of the boxed policy, unlike [`Cache::NAME`] of `BoxedCache` which is always `"dyn"`.
+    pub fn name(&self) -> &'static str {
+        self.0.name()
+    }
+}
+
+impl<K: ObjectId, V> From<Box<dyn DynCache<K, V> + Send>> for BoxedCache<K, V> {
+    fn from(cache: Box<dyn DynCache<K, V> + Send>) -> Self {
+        Self(cache)
+    }
+}
+
+impl<K: ObjectId, V> Cache<K, V> for BoxedCache<K, V> {
+    const NAME: &'static str = "dyn";
+
+    fn write(&mut self, key: K, value: V, timestamp: TimeUnit) {
+        self.0.dyn_write(key, value, timestamp)
+    }
+
+    fn write_with_ttl(&mut self, key: K, value: V, timestamp: TimeUnit, ttl: TimeUnit) {
+        self.0.dyn_write_with_ttl(key, value, timestamp, ttl)
+    }
+
+    fn get(&mut self, key: &K, timestamp: TimeUnit) -> Option<&V> {
+        self.0.dyn_get(key, timestamp)
+    }
+
+    fn get_stale(&mut self, key: &K, timestamp: TimeUnit) -> Option<&V> {
+        self.0.dyn_get_stale(key, timestamp)
+    }
+
+    fn is_expired(&self, key: &K, timestamp: TimeUnit) -> bool {
+        self.0.dyn_is_expired(key, timestamp)
+    }
+
+    fn contains(&self, key: &K) -> bool {
+        self.0.dyn_contains(key)
+    }
+
+    fn invalidate(&mut self, key: &K) -> bool {
+        self.0.dyn_invalidate(key)
+    }
+
+    fn set_fetch_cost(&mut self, key: &K, cost: f64) {
+        self.0.dyn_set_fetch_cost(key, cost)
+    }
+
+    fn save_state(&self, writer: &mut dyn Write) -> std::io::Result<()>
+    where
+        K: Codec,
+        V: Codec,
+    {
+        self.0.dyn_save_state(writer)
+    }
+
+    fn load_state(&mut self, reader: &mut dyn Read) -> std::io::Result<()>
+    where
+        K: Codec<Deserialized = K>,
+        V: Codec<Deserialized = V>,
+    {
+        self.0.dyn_load_state(reader)
+    }
 }
 
 pub struct MultiCache<K: ObjectId, V, C: Cache<K, V>> {
     caches: Vec<C>,
+    indexer: SetIndexer,
+    /// Number of keys inserted into each set, used to place new keys under skewed-associative mapping.
+    num_insertions: Vec<usize>,
+    /// The sets chosen for keys missed but not yet written, so that a key is accessed and written in the same set
+    /// under skewed-associative mapping.
+    pending_placements: AHashMap<K, usize>,
     _phantom: std::marker::PhantomData<(K, V)>,
 }
 
 pub fn construct_k_way_cache<K: ObjectId, V, C: Cache<K, V>>(
     k: usize,
     constructor: impl Fn(usize) -> C,
+) -> MultiCache<K, V, C> {
+    construct_k_way_cache_with_mapping(k, SetMapping::Hash, constructor)
+}
+
+pub fn construct_k_way_cache_with_mapping<K: ObjectId, V, C: Cache<K, V>>(
+    k: usize,
+    mapping: SetMapping,
+    constructor: impl Fn(usize) -> C,
 ) -> MultiCache<K, V, C> {
     MultiCache {
         caches: (0..k).map(constructor).collect(),
+        indexer: SetIndexer::new(k, mapping),
+        num_insertions: vec![0; k],
+        pending_placements: AHashMap::new(),
         _phantom: std::marker::PhantomData,
     }
 }
 
-fn get_cache_idx<K: ObjectId>(k: usize, key: &K) -> usize {
+/// The set of `key` under the default [`SetMapping::Hash`].
+pub(crate) fn get_cache_idx<K: ObjectId>(k: usize, key: &K) -> usize {
     let hash = key.get_hash();
     hash as usize % k
 }
 
+impl<K: ObjectId, V, C: Cache<K, V>> MultiCache<K, V, C> {
+    pub fn set_mapping(&self) -> SetMapping {
+        self.indexer.mapping()
+    }
+
+    pub fn num_sets(&self) -> usize {
+        self.caches.len()
+    }
+
+    pub fn set(&self, idx: usize) -> &C {
+        &self.caches[idx]
+    }
+
+    /// The consistent-hashing ring, if the mapping is [`SetMapping::Consistent`].
+    pub fn ring(&self) -> Option<&mapping::ConsistentHashRing> {
+        self.indexer.ring()
+    }
+
+    pub fn set_mut(&mut self, idx: usize) -> &mut C {
+        &mut self.caches[idx]
+    }
+
+    /// Stop mapping keys to the set `idx`. Only supported by [`SetMapping::Consistent`], where only the keys of the
+    /// removed set are remapped.
+    pub fn remove_set(&mut self, idx: usize) {
+        self.indexer
+            .ring_mut()
+            .expect("only consistent hashing supports removing sets")
+            .remove_node(idx);
+    }
+
+    /// Add the set `idx` with the (usually empty) `cache`, replacing a removed set or appending a new set when
+    /// `idx == self.num_sets()`. Only supported by [`SetMapping::Consistent`].
+    pub fn add_set(&mut self, idx: usize, cache: C) {
+        assert!(idx <= self.caches.len(), "set {} is out of range", idx);
+        if idx == self.caches.len() {
+            self.caches.push(cache);
+            self.num_insertions.push(0);
+        } else {
+            self.caches[idx] = cache;
+        }
+        self.indexer
+            .ring_mut()
+            .expect("only consistent hashing supports adding sets")
+            .add_node(idx);
+    }
+
+    /// Whether each key maps to one set, whatever the cached keys are (unlike skewed-associative mapping).
+    pub fn has_fixed_sets(&self) -> bool {
+        self.indexer.num_candidates() == 1
+    }
+
+    /// The sets with their insertion counts, e.g. to simulate the sets independently.
+    pub(crate) fn sets_with_insertions_mut(
+        &mut self,
+    ) -> impl Iterator<Item = (&mut C, &mut usize)> {
+        self.caches.iter_mut().zip(self.num_insertions.iter_mut())
+    }
+
+    /// The set holding `key`, or the set where `key` would be inserted.
+    pub fn set_of(&self, key: &K) -> usize {
+        let num_candidates = self.indexer.num_candidates();
+        if num_candidates == 1 {
+            return self.indexer.candidate(key, 0);
+        }
+        let candidates = (0..num_candidates).map(|i| self.indexer.candidate(key, i));
+        candidates
+            .clone()
+            .find(|&idx| self.caches[idx].contains(key))
+            .or_else(|| self.pending_placements.get(key).copied())
+            .unwrap_or_else(|| {
+                candidates
+                    .min_by_key(|&idx| self.num_insertions[idx])
+                    .unwrap()
+            })
+    }
+
+    /// The set to access `key` in, remembering it for the write after a miss.
+    fn set_for_access(&mut self, key: &K) -> usize {
+        let idx = self.set_of(key);
+        if self.indexer.num_candidates() > 1 && !self.caches[idx].contains(key) {
+            self.pending_placements.insert(key.clone(), idx);
+        }
+        idx
+    }
+
+    /// The set to write `key` to, counting the insertion if `key` is not cached.
+    fn set_for_write(&mut self, key: &K) -> usize {
+        let idx = self.set_of(key);
+        if !self.caches[idx].contains(key) {
+            self.num_insertions[idx] += 1;
+            self.pending_placements.remove(key);
+        }
+        idx
+    }
+}
+
 impl<K: ObjectId, V, C: Cache<K, V>> Cache<K, V> for MultiCache<K, V, C> {
     const NAME: &'static str = C::NAME;
 
     fn write(&mut self, key: K, value: V, timestamp: TimeUnit) {
-        let idx = get_cache_idx(self.caches.len(), &key);
+        let idx = self.set_for_write(&key);
         self.caches[idx].write(key, value, timestamp);
     }
 
+    fn write_with_ttl(&mut self, key: K, value: V, timestamp: TimeUnit, ttl: TimeUnit) {
+        let idx = self.set_for_write(&key);
+        self.caches[idx].write_with_ttl(key, value, timestamp, ttl);
+    }
+
     fn get(&mut self, key: &K, timestamp: TimeUnit) -> Option<&V> {
-        let idx = get_cache_idx(self.caches.len(), key);
+        let idx = self.set_for_access(key);
         self.caches[idx].get(key, timestamp)
     }
 
+    fn get_stale(&mut self, key: &K, timestamp: TimeUnit) -> Option<&V> {
+        let idx = self.set_for_access(key);
+        self.caches[idx].get_stale(key, timestamp)
+    }
+
+    fn is_expired(&self, key: &K, timestamp: TimeUnit) -> bool {
+        let idx = self.set_of(key);
+        self.caches[idx].is_expired(key, timestamp)
+    }
+
     fn contains(&self, key: &K) -> bool {
-        let idx = get_cache_idx(self.caches.len(), key);
+        let idx = self.set_of(key);
         self.caches[idx].contains(key)
     }
+
+    fn invalidate(&mut self, key: &K) -> bool {
+        let idx = self.set_of(key);
+        self.caches[idx].invalidate(key)
+    }
+
+    fn set_fetch_cost(&mut self, key: &K, cost: f64) {
+        let idx = self.set_of(key);
+        self.caches[idx].set_fetch_cost(key, cost)
+    }
+
+    /// The set mapping is saved to be checked on loading, and the insertion counts of the sets are saved for
+    /// skewed-associative placement.
+    fn save_state(&self, writer: &mut dyn Write) -> std::io::Result<()>
+    where
+        K: Codec,
+        V: Codec,
+    {
+        write_tag(writer, &self.set_mapping().to_string())?;
+        (self.caches.len() as u64).to_bytes(&mut *writer)?;
+        let num_insertions = self
+            .num_insertions
+            .iter()
+            .map(|&n| n as u64)
+            .collect::<Vec<_>>();
+        num_insertions.to_bytes(&mut *writer)?;
+        for cache in &self.caches {
+            cache.save_state(writer)?;
+        }
+        Ok(())
+    }
+
+    fn load_state(&mut self, reader: &mut dyn Read) -> std::io::Result<()>
+    where
+        K: Codec<Deserialized = K>,
+        V: Codec<Deserialized = V>,
+    {
+        expect_tag(reader, &self.set_mapping().to_string())?;
+        expect_param(reader, "number of sets", self.caches.len() as u64)?;
+        let num_insertions = Vec::<u64>::from_bytes(&mut *reader)?;
+        self.num_insertions = num_insertions.into_iter().map(|n| n as usize).collect();
+        self.pending_placements.clear();
+        for cache in &mut self.caches {
+            cache.load_state(reader)?;
+        }
+        Ok(())
+    }
 }
@@ -0,0 +1,315 @@
+//! Property-based and differential tests of every [`Cache`] implementation, on random traces of seeded operations.
+
+use std::collections::VecDeque;
+
+use proj_models::TimeUnit;
+use rand::{Rng, SeedableRng};
+use rand_xorshift::XorShiftRng;
+
+use super::{
+    construct_k_way_cache_with_mapping,
+    lru::LRU,
+    lru_mad::{CostAwareLRUMinAD, LRUMinAD},
+    mapping::SetMapping,
+    registry::{construct_policy, PolicyParams, DEFAULT_COST_COEFFICIENT, POLICY_NAMES},
+    BoxedCache, Cache,
+};
+use crate::{cluster::CacheCluster, tenant::tag_key, tenant::PartitionedCache};
+
+const NUM_SEEDS: u64 = 20;
+const NUM_OPS: usize = 300;
+
+#[derive(Debug, Clone, Copy)]
+enum Op {
+    Get(u64),
+    Write(u64),
+    WriteWithTtl(u64, TimeUnit),
+    Invalidate(u64),
+}
+
+/// Random operations on keys of `keys`, with non-decreasing timestamps (several operations may share one).
+fn random_ops(seed: u64, keys: &[u64]) -> Vec<(Op, TimeUnit)> {
+    let mut rng = XorShiftRng::seed_from_u64(seed);
+    let mut timestamp = 0;
+    (0..NUM_OPS)
+        .map(|_| {
+            timestamp += rng.gen_range(0..3);
+            let key = keys[rng.gen_range(0..keys.len())];
+            let op = match rng.gen_range(0..10) {
+                0..=4 => Op::Get(key),
+                5..=6 => Op::Write(key),
+                7..=8 => Op::WriteWithTtl(key, rng.gen_range(1..10)),
+                _ => Op::Invalidate(key),
+            };
+            (op, timestamp)
+        })
+        .collect()
+}
+
+/// A cache under test, with its total capacity and the keys it can be accessed with.
+struct Subject {
+    name: String,
+    cache: BoxedCache<u64, ()>,
+    capacity: usize,
+    keys: Vec<u64>,
+}
+
+fn subjects() -> Vec<Subject> {
+    let keys = (0..12).collect::<Vec<_>>();
+    let mut subjects = Vec::new();
+    for &policy in POLICY_NAMES {
+        for cache_counts in [1, 4] {
+            for set_mapping in [
+                SetMapping::Hash,
+                SetMapping::Modulo,
+                SetMapping::Skewed { ways: 2 },
+            ] {
+                let params = PolicyParams {
+                    cache_counts,
+                    cache_capacity: 3,
+                    estimated_miss_latency: 5,
+                    set_mapping,
+                    cost_coefficient: DEFAULT_COST_COEFFICIENT,
+                };
+                subjects.push(Subject {
+                    name: format!("{} k={} {}", policy, cache_counts, set_mapping),
+                    cache: construct_policy(policy, &params).unwrap(),
+                    capacity: cache_counts * 3,
+                    keys: keys.clone(),
+                });
+            }
+        }
+    }
+    subjects.push(Subject {
+        name: "cluster".to_string(),
+        cache: BoxedCache::new(
+            CacheCluster::new(4, 16, 10, Vec::new(), |_| LRUMinAD::new(2, 5)).unwrap(),
+        ),
+        capacity: 4 * 2,
+        keys: keys.clone(),
+    });
+    subjects.push(Subject {
+        name: "partitioned".to_string(),
+        cache: BoxedCache::new(PartitionedCache::new(vec![
+            BoxedCache::new(LRU::new(3)),
+            BoxedCache::new(CostAwareLRUMinAD::new(2, 5, 0.5)),
+        ])),
+        capacity: 3 + 2,
+        keys: keys
+            .iter()
+            .map(|&key| tag_key(key as usize % 2, key))
+            .collect(),
+    });
+    subjects
+}
+
+#[test]
+fn test_cache_invariants() {
+    for seed in 0..NUM_SEEDS {
+        for Subject {
+            name,
+            mut cache,
+            capacity,
+            keys,
+        } in subjects()
+        {
+            for (i, (op, timestamp)) in random_ops(seed, &keys).into_iter().enumerate() {
+                let context = || format!("{} (seed {}, op {}: {:?})", name, seed, i, op);
+                match op {
+                    Op::Get(key) => {
+                        let expected = cache.contains(&key) && !cache.is_expired(&key, timestamp);
+                        assert_eq!(
+                            cache.get(&key, timestamp).is_some(),
+                            expected,
+                            "{}",
+                            context()
+                        );
+                        // an expired key is dropped when it is accessed
+                        assert_eq!(cache.contains(&key), expected, "{}", context());
+                    }
+                    Op::Write(key) => {
+                        cache.write(key, (), timestamp);
+                        assert!(cache.contains(&key), "{}", context());
+                        assert!(!cache.is_expired(&key, TimeUnit::MAX), "{}", context());
+                    }
+                    Op::WriteWithTtl(key, ttl) => {
+                        cache.write_with_ttl(key, (), timestamp, ttl);
+                        assert!(cache.contains(&key), "{}", context());
+                        assert!(
+                            !cache.is_expired(&key, timestamp + ttl - 1),
+                            "{}",
+                            context()
+                        );
+                        assert!(cache.is_expired(&key, timestamp + ttl), "{}", context());
+                    }
+                    Op::Invalidate(key) => {
+                        let contained = cache.contains(&key);
+                        assert_eq!(cache.invalidate(&key), contained, "{}", context());
+                        assert!(!cache.contains(&key), "{}", context());
+                    }
+                }
+                let num_cached = keys.iter().filter(|key| cache.contains(key)).count();
+                assert!(num_cached <= capacity, "{}", context());
+            }
+        }
+    }
+}
+
+/// A naive LRU: the keys from the most recently used, without TTLs.
+struct ReferenceLRU {
+    capacity: usize,
+    keys: VecDeque<u64>,
+}
+
+impl ReferenceLRU {
+    fn position(&self, key: u64) -> Option<usize> {
+        self.keys.iter().position(|&k| k == key)
+    }
+
+    fn get(&mut self, key: u64) -> bool {
+        let Some(position) = self.position(key) else {
+            return false;
+        };
+        self.keys.remove(position);
+        self.keys.push_front(key);
+        true
+    }
+
+    /// Writing a cached key refreshes it.
+    fn write(&mut self, key: u64) {
+        if !self.invalidate(key) && self.keys.len() == self.capacity {
+            self.keys.pop_back();
+        }
+        self.keys.push_front(key);
+    }
+
+    fn invalidate(&mut self, key: u64) -> bool {
+        self.position(key)
+            .map(|position| self.keys.remove(position))
+            .is_some()
+    }
+}
+
+#[test]
+fn test_lru_matches_reference() {
+    let keys = (0..12).collect::<Vec<_>>();
+    for seed in 0..NUM_SEEDS {
+        for cache_counts in [1, 3] {
+            // with the modulo mapping, set `i` holds the keys equal to `i` modulo `cache_counts`
+            let mut cache =
+                construct_k_way_cache_with_mapping(cache_counts, SetMapping::Modulo, |_| {
+                    LRU::new(2)
+                });
+            let mut reference = (0..cache_counts)
+                .map(|_| ReferenceLRU {
+                    capacity: 2,
+                    keys: VecDeque::new(),
+                })
+                .collect::<Vec<_>>();
+            for (op, timestamp) in random_ops(seed, &keys) {
+                let set = |key: u64| key as usize % cache_counts;
+                match op {
+                    Op::Get(key) => assert_eq!(
+                        cache.get(&key, timestamp).is_some(),
+                        reference[set(key)].get(key)
+                    ),
+                    Op::Write(key) | Op::WriteWithTtl(key, _) => {
+                        // TTLs are not modelled, so every key is written without one
+                        cache.write(key, (), timestamp);
+                        reference[set(key)].write(key);
+                    }
+                    Op::Invalidate(key) => {
+                        assert_eq!(cache.invalidate(&key), reference[set(key)].invalidate(key))
+                    }
+                }
+                for &key in &keys {
+                    assert_eq!(
+                        cache.contains(&key),
+                        reference[set(key)].position(key).is_some(),
+                        "seed {}, k={}, key {}",
+                        seed,
+                        cache_counts,
+                        key
+                    );
+                }
+            }
+        }
+    }
+}
+
+/// Requests a random key at each distinct timestamp, and writes it right after a miss, as the simulator does when
+/// misses take no time.
+fn assert_same_hits<A: Cache<u64, ()>, B: Cache<u64, ()>>(seed: u64, a: &mut A, b: &mut B) {
+    let mut rng = XorShiftRng::seed_from_u64(seed);
+    let mut timestamp = 0;
+    for _ in 0..NUM_OPS {
+        timestamp += rng.gen_range(1..5);
+        let key = rng.gen_range(0..12);
+        let hit = a.get(&key, timestamp).is_some();
+        assert_eq!(
+            b.get(&key, timestamp).is_some(),
+            hit,
+            "seed {}, key {} at {}",
+            seed,
+            key,
+            timestamp
+        );
+        if !hit {
+            a.write(key, (), timestamp);
+            b.write(key, (), timestamp);
+        }
+    }
+}
+
+/// Number of hits of `cache` on a reuse-heavy trace: most requests go to a few hot keys, and the others to keys
+/// requested once. A miss writes the key right away, as the simulator does when misses take no time.
+fn hits_with_reuse<C: Cache<u64, ()>>(seed: u64, cache: &mut C) -> usize {
+    let mut rng = XorShiftRng::seed_from_u64(seed);
+    let mut timestamp = 0;
+    let mut num_hits = 0;
+    for i in 0..NUM_OPS as u64 {
+        timestamp += rng.gen_range(1..5);
+        let key = if rng.gen_bool(0.7) {
+            rng.gen_range(0..3)
+        } else {
+            100 + i
+        };
+        if cache.get(&key, timestamp).is_some() {
+            num_hits += 1;
+        } else {
+            cache.write(key, (), timestamp);
+        }
+    }
+    num_hits
+}
+
+/// LRU-MAD keeps the hot keys as LRU does. With an estimated miss latency of 0, it does not (see
+/// [`LRUMinAD::new`]).
+#[test]
+fn test_lru_mad_keeps_reused_keys() {
+    for seed in 0..NUM_SEEDS {
+        let lru = hits_with_reuse(seed, &mut LRU::new(4));
+        for estimated_miss_latency in [1, 5] {
+            let lru_mad = hits_with_reuse(seed, &mut LRUMinAD::new(4, estimated_miss_latency));
+            assert!(
+                lru_mad >= lru,
+                "seed {}, latency {}: {} hits, {} with LRU",
+                seed,
+                estimated_miss_latency,
+                lru_mad,
+                lru
+            );
+        }
+    }
+}
+
+#[test]
+fn test_cost_aware_lru_mad_without_costs_matches_lru_mad() {
+    for seed in 0..NUM_SEEDS {
+        assert_same_hits(
+            seed,
+            &mut LRUMinAD::new(4, 10),
+            &mut CostAwareLRUMinAD::new(4, 10, 0.0),
+        );
+    }
+}
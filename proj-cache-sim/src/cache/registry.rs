@@ -0,0 +1,185 @@
+//! A registry of the cache policies, so that binaries can select policies by name at runtime.
+
+use proj_models::TimeUnit;
+
+use super::{
+    construct_k_way_cache_with_mapping,
+    lru::LRU,
+    lru_mad::{CostAwareLRUMinAD, LRUMinAD},
+    mapping::SetMapping,
+    BoxedCache, Cache, ObjectId,
+};
+
+/// Names of all registered policies, as given by [`Cache::NAME`].
+pub const POLICY_NAMES: &[&str] = &[
+    LRU::<u64, ()>::NAME,
+    LRUMinAD::<u64, ()>::NAME,
+    CostAwareLRUMinAD::<u64, ()>::NAME,
+];
+
+/// The default [`PolicyParams::cost_coefficient`]: a unit of fetch cost is worth a miss latency of delay.
+pub const DEFAULT_COST_COEFFICIENT: f64 = 1.0;
+
+/// Parameters used to construct a policy. Policies ignore the parameters they do not need.
+#[derive(Debug, Clone, Copy)]
+pub struct PolicyParams {
+    /// number of caches (k for k-way set-associative cache)
+    pub cache_counts: usize,
+    /// capacity of each cache
+    pub cache_capacity: usize,
+    /// the miss latency estimated by latency-aware policies
+    pub estimated_miss_latency: TimeUnit,
+    /// how keys are mapped to the caches
+    pub set_mapping: SetMapping,
+    /// the weight of fetch costs against delays in cost-aware policies (see [`CostAwareLRUMinAD`])
+    pub cost_coefficient: f64,
+}
+
+/// Resolve a policy name to its registered name. Can be used as a clap value parser.
+pub fn parse_policy_name(name: &str) -> Result<&'static str, String> {
+    POLICY_NAMES
+        .iter()
+        .find(|&&n| n == name)
+        .copied()
+        .ok_or_else(|| {
+            format!(
+                "Unknown policy: {} (available: {})",
+                name,
+                POLICY_NAMES.join(", ")
+            )
+        })
+}
+
+/// Construct an empty `k`-way set associative cache of the policy `name`.
+pub fn construct_policy<K: ObjectId + Send + 'static, V: Send + 'static>(
+    name: &str,
+    params: &PolicyParams,
+) -> Result<BoxedCache<K, V>, String> {
+    let PolicyParams {
+        cache_counts,
+        cache_capacity,
+        estimated_miss_latency,
+        set_mapping,
+        cost_coefficient,
+    } = *params;
+    // sets of the same policy are not boxed individually, to avoid a second dynamic dispatch
+    match parse_policy_name(name)? {
+        n if n == LRU::<K, V>::NAME => Ok(BoxedCache::new(construct_k_way_cache_with_mapping(
+            cache_counts,
+            set_mapping,
+            |_| LRU::new(cache_capacity),
+        ))),
+        n if n == LRUMinAD::<K, V>::NAME => Ok(BoxedCache::new(
+            construct_k_way_cache_with_mapping(cache_counts, set_mapping, |_| {
+                LRUMinAD::new(cache_capacity, estimated_miss_latency)
+            }),
+        )),
+        n if n == CostAwareLRUMinAD::<K, V>::NAME => Ok(BoxedCache::new(
+            construct_k_way_cache_with_mapping(cache_counts, set_mapping, |_| {
+                CostAwareLRUMinAD::new(cache_capacity, estimated_miss_latency, cost_coefficient)
+            }),
+        )),
+        n => unreachable!("policy {} is registered but cannot be constructed", n),
+    }
+}
+
+/// Construct a single empty cache (one set) of the policy `name`, e.g. to mix policies in a
+/// [`super::MultiCache`], or to build a [`super::MultiCache`] whose sets can be simulated in parallel (see
+/// [`crate::parallel`]). `params.cache_counts` and `params.set_mapping` are ignored.
+pub fn construct_single_policy<K: ObjectId + Send + 'static, V: Send + 'static>(
+    name: &str,
+    params: &PolicyParams,
+) -> Result<BoxedCache<K, V>, String> {
+    let PolicyParams {
+        cache_capacity,
+        estimated_miss_latency,
+        cost_coefficient,
+        ..
+    } = *params;
+    match parse_policy_name(name)? {
+        n if n == LRU::<K, V>::NAME => Ok(BoxedCache::new(LRU::new(cache_capacity))),
+        n if n == LRUMinAD::<K, V>::NAME => Ok(BoxedCache::new(LRUMinAD::new(
+            cache_capacity,
+            estimated_miss_latency,
+        ))),
+        n if n == CostAwareLRUMinAD::<K, V>::NAME => Ok(BoxedCache::new(CostAwareLRUMinAD::new(
+            cache_capacity,
+            estimated_miss_latency,
+            cost_coefficient,
+        ))),
+        n => unreachable!("policy {} is registered but cannot be constructed", n),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cache::construct_k_way_cache;
+    use crate::simulator::{compute_statistics, run_simulation};
+    use proj_models::RequestEvent;
+
+    #[test]
+    fn test_construct_registered_policies() {
+        let params = PolicyParams {
+            cache_counts: 2,
+            cache_capacity: 4,
+            estimated_miss_latency: 10,
+            set_mapping: SetMapping::Hash,
+            cost_coefficient: DEFAULT_COST_COEFFICIENT,
+        };
+        for &name in POLICY_NAMES {
+            let cache = construct_policy::<u64, ()>(name, &params).unwrap();
+            assert_eq!(cache.name(), name);
+        }
+        assert!(construct_policy::<u64, ()>("unknown", &params).is_err());
+        assert!(parse_policy_name("unknown").is_err());
+    }
+
+    #[test]
+    fn test_policy_cache_matches_concrete_cache() {
+        let events = (0..1000)
+            .map(|i| RequestEvent {
+                key: (i * 7919) % 37,
+                timestamp: i * 3,
+            })
+            .collect::<Vec<_>>();
+        let params = PolicyParams {
+            cache_counts: 1,
+            cache_capacity: 8,
+            estimated_miss_latency: 20,
+            set_mapping: SetMapping::Hash,
+            cost_coefficient: DEFAULT_COST_COEFFICIENT,
+        };
+
+        let mut dynamic = construct_policy(LRUMinAD::<u64, ()>::NAME, &params).unwrap();
+        let mut concrete = construct_k_way_cache(1, |_| LRUMinAD::new(8, 20));
+        let dynamic = run_simulation(&mut dynamic, events.clone(), 20);
+        let concrete = run_simulation(&mut concrete, events, 20);
+        assert_eq!(
+            compute_statistics(&dynamic.results).total_latency,
+            compute_statistics(&concrete.results).total_latency
+        );
+        assert_eq!(dynamic.num_of_loads, concrete.num_of_loads);
+    }
+
+    #[test]
+    fn test_mixed_policies_in_multi_cache() {
+        let params = PolicyParams {
+            cache_counts: 1,
+            cache_capacity: 2,
+            estimated_miss_latency: 10,
+            set_mapping: SetMapping::Hash,
+            cost_coefficient: DEFAULT_COST_COEFFICIENT,
+        };
+        let policies = [LRU::<u64, ()>::NAME, LRUMinAD::<u64, ()>::NAME];
+        let mut cache = construct_k_way_cache(2, |i| {
+            construct_single_policy::<u64, ()>(policies[i], &params).unwrap()
+        });
+        for key in 0..4 {
+            assert!(cache.get(&key, key).is_none());
+            cache.write(key, (), key);
+        }
+        assert!(cache.contains(&3));
+        assert_eq!(cache.get(&3, 4), Some(&()));
+    }
+}
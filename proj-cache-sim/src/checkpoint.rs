@@ -0,0 +1,210 @@
+//! Checkpoints of warmed caches, so that experiments can start from an identical warm state without replaying the
+//! warmup requests.
+//!
+//! A checkpoint file is a [`CheckpointHeader`] followed by the state written by [`Cache::save_state`]. The state
+//! includes the eviction order and policy metadata, but not the fetches in flight: a checkpoint is taken after all
+//! warmup requests are fulfilled.
+
+use std::{
+    fs::File,
+    io::{BufReader, BufWriter, Read, Write},
+};
+
+use proj_models::{codec::Codec, impl_codec, TimeUnit};
+
+use crate::cache::Cache;
+
+/// Where the checkpoint was taken in the trace.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CheckpointHeader {
+    /// Number of warmup reads simulated before the checkpoint. Writes and deletes are not counted.
+    pub num_requests: u64,
+    /// Timestamp of the last warmup event. Requests after the checkpoint should not be earlier.
+    pub last_event_timestamp: TimeUnit,
+}
+
+impl_codec!(
+    CheckpointHeader,
+    num_requests,
+    u64,
+    last_event_timestamp,
+    TimeUnit
+);
+
+/// Save the state of `cache` to the file at `path`.
+pub fn save_checkpoint<C: Cache<u64, ()>>(
+    path: &str,
+    cache: &C,
+    header: &CheckpointHeader,
+) -> std::io::Result<()> {
+    let mut writer = BufWriter::new(File::create(path)?);
+    header.to_bytes(&mut writer)?;
+    cache.save_state(&mut writer)?;
+    writer.flush()
+}
+
+/// Restore the state of `cache` from the file at `path`. `cache` should be constructed with the same policy and
+/// parameters as the saved cache.
+pub fn load_checkpoint<C: Cache<u64, ()>>(
+    path: &str,
+    cache: &mut C,
+) -> std::io::Result<CheckpointHeader> {
+    let mut reader = BufReader::new(File::open(path)?);
+    let header = CheckpointHeader::from_bytes(&mut reader)?;
+    cache.load_state(&mut reader)?;
+    Ok(header)
+}
+
+pub(crate) fn invalid_data(message: String) -> std::io::Error {
+    std::io::Error::new(std::io::ErrorKind::InvalidData, message)
+}
+
+/// Write a tag, e.g. the policy name, checked by [`expect_tag`] when the state is loaded.
+pub(crate) fn write_tag(writer: &mut dyn Write, tag: &str) -> std::io::Result<()> {
+    tag.as_bytes().to_bytes(writer)
+}
+
+/// Read a tag written by [`write_tag`], and fail if it is not `expected`.
+pub(crate) fn expect_tag(reader: &mut dyn Read, expected: &str) -> std::io::Result<()> {
+    let tag = Vec::<u8>::from_bytes(reader)?;
+    if tag != expected.as_bytes() {
+        return Err(invalid_data(format!(
+            "expected a state of {:?}, found {:?}",
+            expected,
+            String::from_utf8_lossy(&tag)
+        )));
+    }
+    Ok(())
+}
+
+/// Write `len` entries of a map, e.g. the cached keys with their values, in the order of `entries`.
+pub(crate) fn write_entries<'a, K, T>(
+    writer: &mut dyn Write,
+    len: usize,
+    entries: impl Iterator<Item = (&'a K, &'a T)>,
+) -> std::io::Result<()>
+where
+    K: Codec + 'a,
+    T: Codec + 'a,
+{
+    (len as u64).to_bytes(&mut *writer)?;
+    for (key, value) in entries {
+        key.to_bytes(&mut *writer)?;
+        value.to_bytes(&mut *writer)?;
+    }
+    Ok(())
+}
+
+/// Read the entries written by [`write_entries`], in order.
+pub(crate) fn read_entries<K, T>(reader: &mut dyn Read) -> std::io::Result<Vec<(K, T)>>
+where
+    K: Codec<Deserialized = K>,
+    T: Codec<Deserialized = T>,
+{
+    let len = u64::from_bytes(&mut *reader)?;
+    (0..len)
+        .map(|_| Ok((K::from_bytes(&mut *reader)?, T::from_bytes(&mut *reader)?)))
+        .collect()
+}
+
+/// Read a parameter written with the state, and fail if it differs from the parameter of the cache loading it.
+pub(crate) fn expect_param(
+    reader: &mut dyn Read,
+    name: &str,
+    expected: u64,
+) -> std::io::Result<()> {
+    let value = u64::from_bytes(reader)?;
+    if value != expected {
+        return Err(invalid_data(format!(
+            "the saved {} is {}, but the cache has {}",
+            name, value, expected
+        )));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use proj_models::RequestEvent;
+
+    use super::*;
+    use crate::cache::{
+        construct_k_way_cache_with_mapping,
+        mapping::SetMapping,
+        registry::{construct_policy, PolicyParams, DEFAULT_COST_COEFFICIENT, POLICY_NAMES},
+    };
+    use crate::simulator::run_simulation;
+
+    /// Requests `i` in `range`, with a gap longer than the miss latency before request 1000, so that the fetches of
+    /// the first 1000 requests complete before the next request.
+    fn events(range: std::ops::Range<u64>) -> Vec<RequestEvent> {
+        range
+            .map(|i| RequestEvent {
+                key: (i * 7919) % 53,
+                timestamp: i * 3 + if i >= 1000 { 100 } else { 0 },
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_warm_start_matches_continuous_run() {
+        let params = PolicyParams {
+            cache_counts: 3,
+            cache_capacity: 6,
+            estimated_miss_latency: 20,
+            set_mapping: SetMapping::Skewed { ways: 2 },
+            cost_coefficient: DEFAULT_COST_COEFFICIENT,
+        };
+        for &name in POLICY_NAMES {
+            let mut warm = construct_policy::<u64, ()>(name, &params).unwrap();
+            run_simulation(&mut warm, events(0..1000), 20);
+            let mut state = Vec::new();
+            warm.save_state(&mut state).unwrap();
+
+            let mut restored = construct_policy::<u64, ()>(name, &params).unwrap();
+            restored.load_state(&mut state.as_slice()).unwrap();
+            let actual = run_simulation(&mut restored, events(1000..2000), 20);
+            let mut continuous = construct_policy::<u64, ()>(name, &params).unwrap();
+            let expected = run_simulation(&mut continuous, events(0..2000), 20);
+            assert_eq!(actual.results, expected.results[1000..], "{}", name);
+        }
+    }
+
+    #[test]
+    fn test_load_mismatched_state() {
+        let mut cache = construct_k_way_cache_with_mapping(2, SetMapping::Hash, |_| {
+            crate::cache::lru::LRU::<u64, ()>::new(4)
+        });
+        run_simulation(&mut cache, events(0..100), 20);
+        let mut state = Vec::new();
+        cache.save_state(&mut state).unwrap();
+
+        let params = PolicyParams {
+            cache_counts: 2,
+            cache_capacity: 4,
+            estimated_miss_latency: 20,
+            set_mapping: SetMapping::Hash,
+            cost_coefficient: DEFAULT_COST_COEFFICIENT,
+        };
+        let mut other_policy = construct_policy::<u64, ()>("lru-mad", &params).unwrap();
+        assert!(other_policy.load_state(&mut state.as_slice()).is_err());
+        let mut other_capacity = construct_policy::<u64, ()>(
+            "lru",
+            &PolicyParams {
+                cache_capacity: 8,
+                ..params
+            },
+        )
+        .unwrap();
+        assert!(other_capacity.load_state(&mut state.as_slice()).is_err());
+        let mut other_mapping = construct_policy::<u64, ()>(
+            "lru",
+            &PolicyParams {
+                set_mapping: SetMapping::Modulo,
+                ..params
+            },
+        )
+        .unwrap();
+        assert!(other_mapping.load_state(&mut state.as_slice()).is_err());
+    }
+}
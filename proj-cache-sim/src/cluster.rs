@@ -0,0 +1,546 @@
+//! A cluster of cache servers addressed by consistent hashing, with servers joining, leaving and failing over time.
+//!
+//! Each server is a set of a [`MultiCache`] with [`SetMapping::Consistent`]. Membership changes are scheduled at
+//! trace timestamps, and are applied lazily when the cluster is accessed at or after their timestamps, so a
+//! [`CacheCluster`] can be simulated by [`crate::simulator::run_simulation`] like any other cache.
+
+use std::{fmt::Display, str::FromStr};
+
+use ahash::{AHashMap, AHashSet};
+use proj_models::TimeUnit;
+
+use crate::{
+    cache::{construct_k_way_cache_with_mapping, mapping::SetMapping, Cache, MultiCache, ObjectId},
+    simulator::{compute_statistics, RequestResult, Statistics},
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MembershipChange {
+    /// A server joins with an empty cache, taking over its arcs of the ring.
+    Join,
+    /// A server leaves gracefully: it is removed from the ring immediately, and its objects are lost.
+    Leave,
+    /// A server crashes: its objects are lost, and requests routed to it miss until the failure is detected and the
+    /// server is removed from the ring. It can join again after the detection.
+    Fail,
+}
+
+/// A membership change of `server` at `timestamp`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ClusterEvent {
+    pub timestamp: TimeUnit,
+    pub server: usize,
+    pub change: MembershipChange,
+}
+
+impl Display for ClusterEvent {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let change = match self.change {
+            MembershipChange::Join => "join",
+            MembershipChange::Leave => "leave",
+            MembershipChange::Fail => "fail",
+        };
+        write!(f, "{}:{}@{}", change, self.server, self.timestamp)
+    }
+}
+
+impl FromStr for ClusterEvent {
+    type Err = String;
+
+    /// Parse `<join|leave|fail>:<server>@<timestamp>`, where the timestamp may have a unit (e.g. `fail:3@2s`).
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let err = || {
+            format!(
+                "Invalid cluster event {:?}, expected <join|leave|fail>:<server>@<timestamp>",
+                s
+            )
+        };
+        let (change, rest) = s.split_once(':').ok_or_else(err)?;
+        let (server, timestamp) = rest.split_once('@').ok_or_else(err)?;
+        let change = match change {
+            "join" => MembershipChange::Join,
+            "leave" => MembershipChange::Leave,
+            "fail" => MembershipChange::Fail,
+            _ => return Err(err()),
+        };
+        Ok(Self {
+            timestamp: crate::parse_time_unit(timestamp).map_err(|_| err())?,
+            server: server.parse().map_err(|_| err())?,
+            change,
+        })
+    }
+}
+
+/// Sort the membership changes by timestamp in reverse, so that the next one is at the end, keeping the given order
+/// of the changes at the same timestamp.
+fn schedule(mut events: Vec<ClusterEvent>) -> Vec<ClusterEvent> {
+    events.sort_by_key(|e| e.timestamp);
+    events.reverse();
+    events
+}
+
+/// Insert the failure detection of `event` into `schedule`, after the changes at the same timestamp.
+fn schedule_detection(
+    schedule: &mut Vec<ClusterEvent>,
+    event: &ClusterEvent,
+    failure_detection_delay: TimeUnit,
+) {
+    // the detection is scheduled like a graceful leave
+    let detection = ClusterEvent {
+        timestamp: event.timestamp + failure_detection_delay,
+        server: event.server,
+        change: MembershipChange::Leave,
+    };
+    let idx = schedule.partition_point(|e| e.timestamp > detection.timestamp);
+    schedule.insert(idx, detection);
+}
+
+/// Check that the membership changes of a cluster with servers `0..num_servers` initially can be applied in order:
+/// - a new server joins with the next id, and a server that left may join again, but not before its failure is
+///   detected;
+/// - a server fails only if it is up, and leaving has no effect if the server is not in the cluster;
+/// - the last server does not leave.
+pub fn check_schedule(
+    num_servers: usize,
+    failure_detection_delay: TimeUnit,
+    events: &[ClusterEvent],
+) -> Result<(), String> {
+    check_schedule_in_order(
+        num_servers,
+        failure_detection_delay,
+        schedule(events.to_vec()),
+    )
+}
+
+/// See [`check_schedule`], where `schedule` is sorted by [`schedule`].
+fn check_schedule_in_order(
+    num_servers: usize,
+    failure_detection_delay: TimeUnit,
+    mut schedule: Vec<ClusterEvent>,
+) -> Result<(), String> {
+    #[derive(Clone, Copy, PartialEq, Eq)]
+    enum State {
+        Up,
+        /// Failed, and removed from the ring when the failure is detected.
+        Down,
+        /// Not in the ring.
+        Out,
+    }
+    let mut states = vec![State::Up; num_servers];
+    while let Some(event) = schedule.pop() {
+        let server = event.server;
+        let err = |reason: &str| Err(format!("{}: server {} {}", event, server, reason));
+        match (event.change, states.get(server).copied()) {
+            (MembershipChange::Join, None) if server == states.len() => states.push(State::Up),
+            (MembershipChange::Join, Some(State::Out)) => states[server] = State::Up,
+            (MembershipChange::Join, Some(State::Up)) => return err("is already in the cluster"),
+            (MembershipChange::Join, Some(State::Down)) => {
+                return err("joins before its failure is detected")
+            }
+            (MembershipChange::Leave, Some(State::Up | State::Down)) => {
+                if states.iter().filter(|&&s| s != State::Out).count() == 1 {
+                    return err("is the last server and cannot leave");
+                }
+                states[server] = State::Out;
+            }
+            // e.g. the failure of a server is detected after it already left
+            (MembershipChange::Leave, Some(State::Out)) => {}
+            (MembershipChange::Fail, Some(State::Up)) => {
+                states[server] = State::Down;
+                schedule_detection(&mut schedule, &event, failure_detection_delay);
+            }
+            (MembershipChange::Fail, Some(State::Down)) => return err("fails while it is down"),
+            (MembershipChange::Fail, Some(State::Out)) => return err("is not in the cluster"),
+            (_, None) => {
+                return err(&format!(
+                    "does not exist, the next new server is {}",
+                    states.len()
+                ))
+            }
+        }
+    }
+    Ok(())
+}
+
+/// A cache cluster with servers `0..num_servers` initially.
+pub struct CacheCluster<K: ObjectId, V, C: Cache<K, V>, F: Fn(usize) -> C> {
+    servers: MultiCache<K, V, C>,
+    constructor: F,
+    /// Pending membership changes, sorted by timestamp in reverse, so that the next one is at the end.
+    schedule: Vec<ClusterEvent>,
+    /// Time from a failure until the server is removed from the ring.
+    failure_detection_delay: TimeUnit,
+    /// Failed servers that are still in the ring.
+    down: AHashSet<usize>,
+    /// The server that issued the fetch of each missed key, where the key is written on completion.
+    fetching_servers: AHashMap<K, usize>,
+    /// Fetched objects not written because the fetching server left or failed.
+    pub num_dropped_writes: usize,
+    /// Requests routed to a failed server before the failure is detected.
+    pub num_requests_to_down_servers: usize,
+}
+
+impl<K: ObjectId, V, C: Cache<K, V>, F: Fn(usize) -> C> CacheCluster<K, V, C, F> {
+    /// - `constructor`: constructs the empty cache of a server, given the server id.
+    ///
+    /// Return an error if the membership changes cannot be applied (see [`check_schedule`]).
+    pub fn new(
+        num_servers: usize,
+        virtual_nodes: usize,
+        failure_detection_delay: TimeUnit,
+        events: Vec<ClusterEvent>,
+        constructor: F,
+    ) -> Result<Self, String> {
+        let events = schedule(events);
+        check_schedule_in_order(num_servers, failure_detection_delay, events.clone())?;
+        Ok(Self {
+            servers: construct_k_way_cache_with_mapping(
+                num_servers,
+                SetMapping::Consistent { virtual_nodes },
+                &constructor,
+            ),
+            constructor,
+            schedule: events,
+            failure_detection_delay,
+            down: AHashSet::new(),
+            fetching_servers: AHashMap::new(),
+            num_dropped_writes: 0,
+            num_requests_to_down_servers: 0,
+        })
+    }
+
+    /// Whether the server is in the ring and not failed. A server that left is not in the ring.
+    fn is_up(&self, server: usize) -> bool {
+        server < self.servers.num_sets() && !self.down.contains(&server) && self.in_ring(server)
+    }
+
+    /// Forget the fetches issued by a server that lost its objects, so that they are not written to its new cache.
+    fn drop_fetches(&mut self, server: usize) {
+        self.fetching_servers
+            .values_mut()
+            .filter(|s| **s == server)
+            .for_each(|s| *s = usize::MAX);
+    }
+
+    /// The server to write `key` to: the server that fetched it, if any. Return `None` (and count a dropped write)
+    /// if the server is down or has lost its objects since the fetch.
+    fn server_for_write(&mut self, key: &K, timestamp: TimeUnit) -> Option<usize> {
+        self.apply_changes(timestamp);
+        let server = self
+            .fetching_servers
+            .remove(key)
+            .unwrap_or_else(|| self.servers.set_of(key));
+        if self.is_up(server) {
+            Some(server)
+        } else {
+            self.num_dropped_writes += 1;
+            None
+        }
+    }
+
+    /// The server to access `key` in, remembering it for the write after a miss. Return `None` if the server is
+    /// down.
+    fn server_for_access(&mut self, key: &K, timestamp: TimeUnit) -> Option<usize> {
+        self.apply_changes(timestamp);
+        let server = self.servers.set_of(key);
+        if self.down.contains(&server) {
+            self.num_requests_to_down_servers += 1;
+            self.fetching_servers.insert(key.clone(), server);
+            return None;
+        }
+        if !self.servers.set(server).contains(key) {
+            self.fetching_servers.insert(key.clone(), server);
+        }
+        Some(server)
+    }
+
+    /// The server whose cache holds `key`: while `key` is fetched, the server that will write it on completion, even
+    /// if `key` maps to another server since, and otherwise the server `key` maps to. Return `None` if the server is
+    /// down or has lost its objects since the fetch.
+    fn server_of(&self, key: &K) -> Option<usize> {
+        let server = self
+            .fetching_servers
+            .get(key)
+            .copied()
+            .unwrap_or_else(|| self.servers.set_of(key));
+        (server != usize::MAX && !self.down.contains(&server)).then_some(server)
+    }
+
+    fn in_ring(&self, server: usize) -> bool {
+        self.servers
+            .ring()
+            .is_some_and(|ring| ring.contains_node(server))
+    }
+
+    /// Apply the membership changes scheduled at or before `timestamp`. The schedule is checked by
+    /// [`check_schedule`], so the assertions only guard against bugs.
+    fn apply_changes(&mut self, timestamp: TimeUnit) {
+        while self
+            .schedule
+            .last()
+            .is_some_and(|e| e.timestamp <= timestamp)
+        {
+            let event = self.schedule.pop().unwrap();
+            let server = event.server;
+            match event.change {
+                MembershipChange::Join => {
+                    assert!(!self.in_ring(server), "server {} joins twice", server);
+                    self.servers.add_set(server, (self.constructor)(server));
+                }
+                MembershipChange::Leave => {
+                    self.remove_server(server);
+                }
+                MembershipChange::Fail => {
+                    assert!(self.is_up(server), "server {} fails while not up", server);
+                    // objects are lost immediately, but the server is removed from the ring only after detection
+                    *self.servers.set_mut(server) = (self.constructor)(server);
+                    self.drop_fetches(server);
+                    self.down.insert(server);
+                    schedule_detection(&mut self.schedule, &event, self.failure_detection_delay);
+                }
+            }
+        }
+    }
+
+    fn remove_server(&mut self, server: usize) {
+        self.down.remove(&server);
+        if !self.in_ring(server) {
+            // e.g. the failure of a server is detected after it already left
+            return;
+        }
+        let remaining = (0..self.servers.num_sets())
+            .filter(|&s| s != server && self.in_ring(s))
+            .count();
+        assert!(remaining > 0, "the last server {} cannot leave", server);
+        self.servers.remove_set(server);
+        *self.servers.set_mut(server) = (self.constructor)(server);
+        self.drop_fetches(server);
+    }
+}
+
+impl<K: ObjectId, V, C: Cache<K, V>, F: Fn(usize) -> C> Cache<K, V> for CacheCluster<K, V, C, F> {
+    const NAME: &'static str = C::NAME;
+
+    fn write(&mut self, key: K, value: V, timestamp: TimeUnit) {
+        if let Some(server) = self.server_for_write(&key, timestamp) {
+            self.servers.set_mut(server).write(key, value, timestamp);
+        }
+    }
+
+    fn write_with_ttl(&mut self, key: K, value: V, timestamp: TimeUnit, ttl: TimeUnit) {
+        if let Some(server) = self.server_for_write(&key, timestamp) {
+            self.servers
+                .set_mut(server)
+                .write_with_ttl(key, value, timestamp, ttl);
+        }
+    }
+
+    fn get(&mut self, key: &K, timestamp: TimeUnit) -> Option<&V> {
+        let server = self.server_for_access(key, timestamp)?;
+        self.servers.set_mut(server).get(key, timestamp)
+    }
+
+    fn get_stale(&mut self, key: &K, timestamp: TimeUnit) -> Option<&V> {
+        let server = self.server_for_access(key, timestamp)?;
+        self.servers.set_mut(server).get_stale(key, timestamp)
+    }
+
+    fn is_expired(&self, key: &K, timestamp: TimeUnit) -> bool {
+        self.server_of(key)
+            .is_some_and(|server| self.servers.set(server).is_expired(key, timestamp))
+    }
+
+    fn contains(&self, key: &K) -> bool {
+        self.server_of(key)
+            .is_some_and(|server| self.servers.set(server).contains(key))
+    }
+
+    fn invalidate(&mut self, key: &K) -> bool {
+        let server = self.servers.set_of(key);
+        !self.down.contains(&server) && self.servers.set_mut(server).invalidate(key)
+    }
+
+    fn set_fetch_cost(&mut self, key: &K, cost: f64) {
+        let server = self.servers.set_of(key);
+        if !self.down.contains(&server) {
+            self.servers.set_mut(server).set_fetch_cost(key, cost)
+        }
+    }
+}
+
+/// Statistics of the requests shortly before and after a membership change.
+#[derive(Debug, Clone)]
+pub struct ChurnImpact {
+    pub event: ClusterEvent,
+    /// requests in `[event.timestamp - window, event.timestamp)`
+    pub before: Statistics,
+    pub num_requests_before: usize,
+    /// requests in `[event.timestamp, event.timestamp + window)`
+    pub after: Statistics,
+    pub num_requests_after: usize,
+}
+
+/// Compare the statistics in the windows before and after each event. `results` should be sorted by request
+/// timestamp, as returned by [`crate::simulator::run_simulation`].
+pub fn churn_impact(
+    results: &[RequestResult],
+    events: &[ClusterEvent],
+    window: TimeUnit,
+) -> Vec<ChurnImpact> {
+    let slice = |start: TimeUnit, end: TimeUnit| {
+        let lo = results.partition_point(|r| r.request_timestamp < start);
+        let hi = results.partition_point(|r| r.request_timestamp < end);
+        &results[lo..hi]
+    };
+    events
+        .iter()
+        .map(|&event| {
+            let before = slice(event.timestamp.saturating_sub(window), event.timestamp);
+            let after = slice(event.timestamp, event.timestamp.saturating_add(window));
+            ChurnImpact {
+                event,
+                before: compute_statistics(before),
+                num_requests_before: before.len(),
+                after: compute_statistics(after),
+                num_requests_after: after.len(),
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use proj_models::RequestEvent;
+
+    use super::*;
+    use crate::{
+        cache::{lru::LRU, lru_mad::LRUMinAD},
+        simulator::run_simulation,
+    };
+
+    /// Cycle over `num_keys` keys, one request every 10ns.
+    fn cyclic_trace(len: u64, num_keys: u64) -> Vec<RequestEvent> {
+        (0..len)
+            .map(|i| RequestEvent {
+                key: i % num_keys,
+                timestamp: i * 10,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_parse_cluster_event() {
+        let event = "fail:3@2ms".parse::<ClusterEvent>().unwrap();
+        assert_eq!(
+            event,
+            ClusterEvent {
+                timestamp: 2_000_000,
+                server: 3,
+                change: MembershipChange::Fail,
+            }
+        );
+        assert_eq!(event.to_string().parse::<ClusterEvent>().unwrap(), event);
+        assert!("crash:3@2ms".parse::<ClusterEvent>().is_err());
+        assert!("fail:3".parse::<ClusterEvent>().is_err());
+    }
+
+    #[test]
+    fn test_cluster_without_churn_matches_multi_cache() {
+        let events = cyclic_trace(10_000, 100);
+        let mut cluster = CacheCluster::new(4, 50, 0, vec![], |_| LRU::new(40)).unwrap();
+        let mut multi = construct_k_way_cache_with_mapping(
+            4,
+            SetMapping::Consistent { virtual_nodes: 50 },
+            |_| LRU::new(40),
+        );
+        let cluster =
+            compute_statistics(&run_simulation(&mut cluster, events.clone(), 100).results);
+        let multi = compute_statistics(&run_simulation(&mut multi, events, 100).results);
+        assert_eq!(cluster.total_latency, multi.total_latency);
+        assert_eq!(cluster.num_misses, multi.num_misses);
+    }
+
+    #[test]
+    fn test_churn_causes_misses() {
+        // all 100 keys fit in the cluster, so there are only cold misses without churn
+        let events = cyclic_trace(20_000, 100);
+        let churn = vec![
+            "leave:1@50000".parse().unwrap(),
+            "join:4@100000".parse().unwrap(),
+            "fail:2@150000".parse().unwrap(),
+        ];
+        let mut cluster =
+            CacheCluster::new(4, 50, 1000, churn.clone(), |_| LRUMinAD::new(100, 100)).unwrap();
+        let results = run_simulation(&mut cluster, events, 100).results;
+        assert!(cluster.num_requests_to_down_servers > 0);
+
+        let impacts = churn_impact(&results, &churn, 10_000);
+        for impact in &impacts {
+            // a full cycle before each event hits
+            assert_eq!(impact.before.num_misses, 0, "{}", impact.event);
+            assert!(impact.after.num_misses > 0, "{}", impact.event);
+            assert_eq!(impact.num_requests_after, 1000);
+        }
+        // keys are cached again after a full cycle
+        let last = compute_statistics(&results[results.len() - 100..]);
+        assert_eq!(last.num_misses, 0);
+    }
+
+    #[test]
+    fn test_leave_during_fetch() {
+        // the keys taken over by server 2 miss at 11, and it leaves while they are fetched, so they map back to the
+        // servers that still hold them
+        let mut trace = (0..200)
+            .map(|key| RequestEvent { key, timestamp: 0 })
+            .chain((0..200).map(|key| RequestEvent { key, timestamp: 11 }))
+            .collect::<Vec<_>>();
+        // a request after the leave applies it before the fetches complete
+        trace.push(RequestEvent {
+            key: 200,
+            timestamp: 13,
+        });
+        let churn = vec!["join:2@10".parse().unwrap(), "leave:2@12".parse().unwrap()];
+        let mut cluster = CacheCluster::new(2, 50, 0, churn, |_| LRU::new(200)).unwrap();
+        let results = run_simulation(&mut cluster, trace, 5).results;
+
+        let remapped = results[200..400]
+            .iter()
+            .filter(|r| r.completion_timestamp > r.request_timestamp)
+            .count();
+        assert!(remapped > 0);
+        // the fetched objects are not written to the server that left
+        assert_eq!(cluster.num_dropped_writes, remapped);
+        // the keys are still cached in the servers they map back to
+        assert!((0..200).all(|key| cluster.contains(&key)));
+    }
+
+    #[test]
+    fn test_invalid_schedules() {
+        let check = |events: &[&str]| {
+            let events = events
+                .iter()
+                .map(|e| e.parse().unwrap())
+                .collect::<Vec<_>>();
+            check_schedule(2, 100, &events)
+        };
+        assert!(check(&["join:2@10", "leave:0@20", "fail:1@30", "join:1@200"]).is_ok());
+        // changes at the same timestamp are applied in the given order
+        assert!(check(&["leave:1@10", "join:1@10"]).is_ok());
+        assert!(check(&["join:1@10", "leave:1@10"]).is_err());
+        // the failure is detected at 130, after which leaving has no effect
+        assert!(check(&["fail:1@30", "leave:1@150"]).is_ok());
+
+        assert!(check(&["join:3@10"]).is_err());
+        assert!(check(&["leave:5@10"]).is_err());
+        assert!(check(&["fail:1@10", "fail:1@20"]).is_err());
+        assert!(check(&["leave:1@10", "fail:1@20"]).is_err());
+        assert!(check(&["fail:1@10", "join:1@50"]).is_err());
+        assert!(check(&["leave:0@10", "leave:1@20"]).is_err());
+        assert!(
+            CacheCluster::new(2, 50, 100, vec!["join:3@10".parse().unwrap()], |_| {
+                LRU::<u64, ()>::new(1)
+            })
+            .is_err()
+        );
+    }
+}
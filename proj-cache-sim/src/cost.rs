@@ -0,0 +1,194 @@
+//! Monetary cost of fetches from the backing store, e.g. the egress cost of the origin, as an objective alongside
+//! latency.
+
+use ahash::AHashMap;
+use proj_models::RequestId;
+
+use crate::tenant::tenant_of;
+
+/// The cost of fetching each key from the backing store. Only fetches that bring the object are charged: failed
+/// attempts are free.
+#[derive(Debug, Clone, PartialEq)]
+pub enum CostModel {
+    /// Every fetch costs the same.
+    Uniform(f64),
+    /// The cost of each key (e.g. proportional to the object size), and `default` for the keys not listed.
+    PerKey {
+        costs: AHashMap<RequestId, f64>,
+        default: f64,
+    },
+    /// The cost of each origin, the origin of a key being its tenant (see [`crate::tenant`]). All keys of a trace
+    /// without tenants are from origin 0. The number of costs is checked by [`CostModel::check_origins`].
+    PerOrigin(Vec<f64>),
+}
+
+impl Default for CostModel {
+    /// Each fetch costs 1, so that the total cost is the number of fetches.
+    fn default() -> Self {
+        Self::Uniform(1.0)
+    }
+}
+
+impl CostModel {
+    /// The cost of fetching `key`.
+    pub fn cost(&self, key: RequestId) -> f64 {
+        match self {
+            Self::Uniform(cost) => *cost,
+            Self::PerKey { costs, default } => costs.get(&key).copied().unwrap_or(*default),
+            Self::PerOrigin(costs) => {
+                let origin = tenant_of(key);
+                *costs
+                    .get(origin)
+                    .unwrap_or_else(|| panic!("no cost is given for origin {}", origin))
+            }
+        }
+    }
+
+    /// Check that a cost is given for each of `num_origins` origins, so that [`CostModel::cost`] does not fail
+    /// halfway through a simulation.
+    pub fn check_origins(&self, num_origins: usize) -> Result<(), String> {
+        match self {
+            Self::PerOrigin(costs) if costs.len() != num_origins => Err(format!(
+                "{} origin costs are given, but there are {} origins",
+                costs.len(),
+                num_origins
+            )),
+            _ => Ok(()),
+        }
+    }
+
+    /// Load the costs of keys from a file with a `<key>,<cost>` line per key. Other keys cost `default`.
+    pub fn load_per_key(path: &str, default: f64) -> Result<Self, String> {
+        let content =
+            std::fs::read_to_string(path).map_err(|e| format!("Failed to read {}: {}", path, e))?;
+        let costs = content
+            .lines()
+            .filter(|line| !line.trim().is_empty())
+            .map(|line| {
+                let (key, cost) = line
+                    .split_once(',')
+                    .ok_or_else(|| format!("Invalid line in {}: {}", path, line))?;
+                let key = key
+                    .trim()
+                    .parse()
+                    .map_err(|_| format!("Invalid key: {}", key))?;
+                Ok((key, parse_cost(cost.trim())?))
+            })
+            .collect::<Result<_, String>>()?;
+        Ok(Self::PerKey { costs, default })
+    }
+}
+
+fn parse_cost(s: &str) -> Result<f64, String> {
+    match s.parse::<f64>() {
+        Ok(cost) if cost >= 0.0 => Ok(cost),
+        _ => Err(format!("Invalid fetch cost: {}", s)),
+    }
+}
+
+impl std::fmt::Display for CostModel {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Uniform(cost) => write!(f, "{}", cost),
+            Self::PerKey { costs, default } => {
+                write!(f, "per-key ({} keys, default {})", costs.len(), default)
+            }
+            Self::PerOrigin(costs) => {
+                let costs = costs.iter().map(ToString::to_string).collect::<Vec<_>>();
+                write!(f, "origins:{}", costs.join(","))
+            }
+        }
+    }
+}
+
+impl std::str::FromStr for CostModel {
+    type Err = String;
+
+    /// `<cost>` for a uniform cost, `origins:<cost>,<cost>,...` for the costs of the origins, or
+    /// `keys:<path>[:<default>]` for the costs of keys listed in a file (default: 1).
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if let Some(costs) = s.strip_prefix("origins:") {
+            return costs
+                .split(',')
+                .map(parse_cost)
+                .collect::<Result<_, _>>()
+                .map(Self::PerOrigin);
+        }
+        if let Some(spec) = s.strip_prefix("keys:") {
+            return match spec.rsplit_once(':') {
+                Some((path, default)) if default.parse::<f64>().is_ok() => {
+                    Self::load_per_key(path, parse_cost(default)?)
+                }
+                _ => Self::load_per_key(spec, 1.0),
+            };
+        }
+        parse_cost(s).map(Self::Uniform).map_err(|_| {
+            format!(
+                "Invalid fetch cost model: {} (expected <cost>, origins:<cost>,... or keys:<path>[:<default>])",
+                s
+            )
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tenant::tag_key;
+
+    #[test]
+    fn test_parse_cost_model() {
+        assert_eq!("2.5".parse(), Ok(CostModel::Uniform(2.5)));
+        let origins = "origins:1,0.5".parse::<CostModel>().unwrap();
+        assert_eq!(origins.to_string(), "origins:1,0.5");
+        assert_eq!(origins.cost(tag_key(1, 7)), 0.5);
+        assert!(origins.check_origins(2).is_ok());
+        assert!(origins.check_origins(1).is_err());
+        assert!(origins.check_origins(3).is_err());
+        assert!(CostModel::Uniform(2.5).check_origins(3).is_ok());
+        assert!("-1".parse::<CostModel>().is_err());
+        assert!("keys:/nonexistent".parse::<CostModel>().is_err());
+
+        let path = std::env::temp_dir().join("proj-cache-sim-test-costs.csv");
+        std::fs::write(&path, "1,3\n2, 0.25\n").unwrap();
+        let per_key = format!("keys:{}:2", path.display())
+            .parse::<CostModel>()
+            .unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!([1, 2, 3].map(|key| per_key.cost(key)), [3.0, 0.25, 2.0]);
+    }
+
+    #[test]
+    fn test_simulation_fetch_cost() {
+        use crate::{
+            cache::lru::LRU,
+            simulator::{run_simulation_with_options, SimulationOptions},
+            tenant::{MergedTenants, TenantTransform},
+        };
+        use proj_models::RequestEvent;
+
+        let trace = |keys: &[u64]| {
+            keys.iter()
+                .enumerate()
+                .map(|(i, &key)| RequestEvent {
+                    key,
+                    timestamp: i as u64 * 10,
+                })
+                .collect::<Vec<_>>()
+                .into_iter()
+        };
+        // tenant 0 misses 2 keys twice each, tenant 1 misses 1 key once
+        let requests = MergedTenants::new([
+            (trace(&[0, 1, 0, 1]), TenantTransform::default()),
+            (trace(&[5, 5, 5, 5]), TenantTransform::default()),
+        ])
+        .collect::<Vec<_>>();
+        let options = SimulationOptions {
+            fetch_cost: CostModel::PerOrigin(vec![1.0, 10.0]),
+            ..Default::default()
+        };
+        let result = run_simulation_with_options(&mut LRU::new(2), requests, 5, &options);
+        assert_eq!(result.num_of_loads, 5);
+        assert_eq!(result.total_fetch_cost, 14.0);
+    }
+}
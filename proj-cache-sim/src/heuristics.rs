@@ -3,7 +3,9 @@
 use std::fmt::Display;
 
 use ahash::{AHashMap, AHashSet};
-use proj_models::RequestEvent;
+use proj_models::{RequestEvent, TimeUnit};
+
+use crate::cache::get_cache_idx;
 
 /// Get the maximum number of overlapping live ranges of objects in the workload at time. A live range is
 /// an interval from the first access to the last access of an object.
@@ -66,6 +68,12 @@ pub struct TimingStatistics {
     pub count: u64,
 }
 
+impl Default for TimingStatistics {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl TimingStatistics {
     pub fn new() -> Self {
         Self {
@@ -118,6 +126,329 @@ pub fn get_irt(events: &[RequestEvent]) -> TimingStatistics {
     irt
 }
 
+/// A Fenwick tree over event positions, used to count distinct objects between two accesses.
+struct FenwickTree {
+    tree: Vec<i64>,
+}
+
+impl FenwickTree {
+    fn new(len: usize) -> Self {
+        Self {
+            tree: vec![0; len + 1],
+        }
+    }
+
+    fn add(&mut self, idx: usize, delta: i64) {
+        let mut i = idx + 1;
+        while i < self.tree.len() {
+            self.tree[i] += delta;
+            i += i & i.wrapping_neg();
+        }
+    }
+
+    /// Sum of values in `[0, idx)`.
+    fn prefix_sum(&self, idx: usize) -> i64 {
+        let mut i = idx;
+        let mut sum = 0;
+        while i > 0 {
+            sum += self.tree[i];
+            i -= i & i.wrapping_neg();
+        }
+        sum
+    }
+}
+
+/// Get the LRU stack distance of each request, i.e. the number of distinct objects accessed in the same set
+/// since the last access to the same object. `None` means the request is the first access to the object (cold miss).
+///
+/// Objects are mapped to `k` sets in the same way as [`crate::cache::MultiCache`], so with per-set capacity `c`, a request
+/// hits in a `k`-way LRU cache (under the zero-latency miss model) if and only if its stack distance is less than `c`.
+/// Runs in O(n log n) time.
+pub fn lru_stack_distances(events: &[RequestEvent], k: usize) -> Vec<Option<usize>> {
+    let mut distances = vec![None; events.len()];
+    // event indices of each set
+    let mut sets = vec![Vec::new(); k];
+    for (idx, event) in events.iter().enumerate() {
+        sets[get_cache_idx(k, &event.key)].push(idx);
+    }
+
+    for set in sets {
+        let mut tree = FenwickTree::new(set.len());
+        // position (in this set) of the last access of each object
+        let mut last_position = AHashMap::new();
+        for (position, &idx) in set.iter().enumerate() {
+            let key = events[idx].key;
+            if let Some(last) = last_position.insert(key, position) {
+                let distance = tree.prefix_sum(position) - tree.prefix_sum(last + 1);
+                distances[idx] = Some(distance as usize);
+                tree.add(last, -1);
+            }
+            tree.add(position, 1);
+        }
+    }
+    distances
+}
+
+/// Get the miss ratio of a `k`-way LRU cache for each per-set capacity in `capacities`, in a single pass over the
+/// workload, using the stack distances. Misses are counted under the traditional zero-latency miss model, and the
+/// first `warmup` requests are not counted, so there should be requests after the warmup.
+pub fn lru_miss_ratio_curve(
+    events: &[RequestEvent],
+    k: usize,
+    capacities: &[usize],
+    warmup: usize,
+) -> Vec<f64> {
+    assert!(
+        warmup < events.len(),
+        "the warmup ({} requests) should be shorter than the workload ({} requests)",
+        warmup,
+        events.len()
+    );
+    let distances = lru_stack_distances(events, k);
+    let counted = &distances[warmup..];
+    capacities
+        .iter()
+        .map(|&capacity| {
+            let misses = counted
+                .iter()
+                .filter(|d| !matches!(d, Some(d) if *d < capacity))
+                .count();
+            misses as f64 / counted.len() as f64
+        })
+        .collect()
+}
+
+/// A histogram with power-of-two buckets.
+#[derive(Debug, Clone, Default)]
+pub struct Log2Histogram {
+    /// `buckets[0]`: value = 0, `buckets[i]`: 2^(i-1) <= value < 2^i
+    pub buckets: Vec<u64>,
+    /// Number of infinite values (e.g. the distance of first accesses).
+    pub infinite: u64,
+    pub count: u64,
+}
+
+impl Log2Histogram {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a value, where `None` is infinity.
+    pub fn add(&mut self, value: Option<usize>) {
+        self.count += 1;
+        let Some(value) = value else {
+            self.infinite += 1;
+            return;
+        };
+        let bucket = (usize::BITS - value.leading_zeros()) as usize;
+        if self.buckets.len() <= bucket {
+            self.buckets.resize(bucket + 1, 0);
+        }
+        self.buckets[bucket] += 1;
+    }
+
+    /// Lower (inclusive) and upper (exclusive) bound of the bucket.
+    pub fn bucket_range(bucket: usize) -> (usize, usize) {
+        if bucket == 0 {
+            (0, 1)
+        } else {
+            (1 << (bucket - 1), 1 << bucket)
+        }
+    }
+}
+
+/// Get the histogram of LRU stack distances (reuse distances) of a `k`-way cache. See [`lru_stack_distances`].
+pub fn lru_stack_distance_histogram(events: &[RequestEvent], k: usize) -> Log2Histogram {
+    let mut histogram = Log2Histogram::new();
+    for distance in lru_stack_distances(events, k) {
+        histogram.add(distance);
+    }
+    histogram
+}
+
+/// Get the histogram of reuse times, i.e. the number of requests between two consecutive accesses to the same object.
+/// Reuse time is an upper bound of the stack distance.
+pub fn reuse_time_histogram(events: &[RequestEvent]) -> Log2Histogram {
+    let mut histogram = Log2Histogram::new();
+    let mut last_access = AHashMap::new();
+    for (idx, event) in events.iter().enumerate() {
+        histogram.add(
+            last_access
+                .insert(event.key, idx)
+                .map(|last| idx - last - 1),
+        );
+    }
+    histogram
+}
+
+/// An estimate of how much a workload could benefit from delayed-hit-aware caching.
+#[derive(Debug, Clone)]
+pub struct DelayedHitPotential {
+    pub miss_latency: TimeUnit,
+    pub num_requests: usize,
+    /// Number of miss windows, i.e. requests that start a fetch.
+    pub num_windows: usize,
+    /// Number of requests that fall into the miss window of an earlier request to the same object.
+    pub num_delayed_hits: usize,
+    /// Number of delayed hits in each miss window.
+    pub burst_sizes: Log2Histogram,
+    /// Total latency of requests that start a fetch, i.e. `num_windows * miss_latency`.
+    pub miss_latency_total: u128,
+    /// Total latency of delayed hits.
+    pub delayed_hit_latency_total: u128,
+}
+
+impl DelayedHitPotential {
+    /// The fraction of the total latency that comes from delayed hits. This is an upper bound of the latency that is
+    /// invisible to the traditional hit-ratio view, where delayed hits are counted as hits.
+    pub fn delayed_hit_latency_fraction(&self) -> f64 {
+        self.delayed_hit_latency_total as f64
+            / (self.miss_latency_total + self.delayed_hit_latency_total) as f64
+    }
+
+    pub fn mean_delayed_hit_latency(&self) -> f64 {
+        self.delayed_hit_latency_total as f64 / self.num_delayed_hits as f64
+    }
+}
+
+/// Estimate the delayed-hit potential of the workload, assuming nothing is cached: the first request to an object
+/// misses and opens a miss window of `miss_latency`, every request to the same object inside the window is a delayed
+/// hit, and the first request after the window misses again.
+pub fn delayed_hit_potential(
+    events: &[RequestEvent],
+    miss_latency: TimeUnit,
+) -> DelayedHitPotential {
+    // (window start timestamp, number of delayed hits in the window)
+    let mut windows: AHashMap<u64, (TimeUnit, usize)> = AHashMap::new();
+    let mut burst_sizes = Log2Histogram::new();
+    let mut num_windows = 0;
+    let mut num_delayed_hits = 0;
+    let mut delayed_hit_latency_total: u128 = 0;
+    let mut last_event_timestamp = 0;
+    for event in events {
+        if event.timestamp < last_event_timestamp {
+            panic!(
+                "events are not in order: the event of key {:?} at timestamp {} is earlier than the last event at timestamp {}",
+                event.key, event.timestamp, last_event_timestamp
+            );
+        }
+        last_event_timestamp = event.timestamp;
+
+        match windows.get_mut(&event.key) {
+            Some((start, burst)) if event.timestamp < *start + miss_latency => {
+                *burst += 1;
+                num_delayed_hits += 1;
+                delayed_hit_latency_total += (*start + miss_latency - event.timestamp) as u128;
+            }
+            window => {
+                if let Some((_, burst)) = window {
+                    burst_sizes.add(Some(*burst));
+                }
+                windows.insert(event.key, (event.timestamp, 0));
+                num_windows += 1;
+            }
+        }
+    }
+    for (_, burst) in windows.into_values() {
+        burst_sizes.add(Some(burst));
+    }
+
+    DelayedHitPotential {
+        miss_latency,
+        num_requests: events.len(),
+        num_windows,
+        num_delayed_hits,
+        burst_sizes,
+        miss_latency_total: num_windows as u128 * miss_latency as u128,
+        delayed_hit_latency_total,
+    }
+}
+
+/// Key-frequency statistics of the workload, characterizing its popularity skew.
+#[derive(Debug, Clone)]
+pub struct PopularityStatistics {
+    pub num_requests: usize,
+    pub num_unique_keys: usize,
+    /// Number of keys that are accessed exactly once.
+    pub num_one_hit_wonders: usize,
+    /// `(n, share)`: the fraction of requests that go to the `n` most popular keys.
+    pub top_shares: Vec<(usize, f64)>,
+    /// Maximum-likelihood estimate of the Zipf exponent, fitted on the rank-frequency distribution.
+    pub zipf_alpha: f64,
+}
+
+impl PopularityStatistics {
+    pub fn one_hit_wonder_ratio(&self) -> f64 {
+        self.num_one_hit_wonders as f64 / self.num_unique_keys as f64
+    }
+}
+
+/// Maximum-likelihood estimate of the exponent `alpha` of a Zipf distribution `p(r) ~ r^-alpha` over ranks
+/// `1..=frequencies.len()`, where `frequencies` are sorted in descending order.
+///
+/// The likelihood is maximized when the expected `ln r` under the fitted distribution equals the empirical mean of
+/// `ln r`. The expected `ln r` is decreasing in `alpha`, so we solve it with bisection.
+pub fn fit_zipf_alpha(frequencies: &[u64]) -> f64 {
+    let total = frequencies.iter().sum::<u64>() as f64;
+    let ln_ranks = (1..=frequencies.len())
+        .map(|r| (r as f64).ln())
+        .collect::<Vec<_>>();
+    let empirical_mean = frequencies
+        .iter()
+        .zip(&ln_ranks)
+        .map(|(&f, ln_r)| f as f64 * ln_r)
+        .sum::<f64>()
+        / total;
+    let expected_mean = |alpha: f64| {
+        let (weighted, normalizer) = ln_ranks
+            .iter()
+            .map(|ln_r| {
+                let p = (-alpha * ln_r).exp();
+                (p * ln_r, p)
+            })
+            .fold((0., 0.), |(a, b), (c, d)| (a + c, b + d));
+        weighted / normalizer
+    };
+
+    let (mut low, mut high) = (0., 10.);
+    for _ in 0..64 {
+        let mid = (low + high) / 2.;
+        if expected_mean(mid) > empirical_mean {
+            low = mid;
+        } else {
+            high = mid;
+        }
+    }
+    (low + high) / 2.
+}
+
+/// Get the popularity statistics of the workload. `top_ns` are the numbers of most popular keys to compute the
+/// request share of.
+pub fn popularity_statistics(events: &[RequestEvent], top_ns: &[usize]) -> PopularityStatistics {
+    let mut counts: AHashMap<u64, u64> = AHashMap::new();
+    for event in events {
+        *counts.entry(event.key).or_default() += 1;
+    }
+    let mut frequencies = counts.into_values().collect::<Vec<_>>();
+    frequencies.sort_unstable_by(|a, b| b.cmp(a));
+
+    let top_shares = top_ns
+        .iter()
+        .map(|&n| {
+            let top = frequencies.iter().take(n).sum::<u64>();
+            (n, top as f64 / events.len() as f64)
+        })
+        .collect();
+
+    PopularityStatistics {
+        num_requests: events.len(),
+        num_unique_keys: frequencies.len(),
+        num_one_hit_wonders: frequencies.iter().filter(|&&f| f == 1).count(),
+        top_shares,
+        zipf_alpha: fit_zipf_alpha(&frequencies),
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -160,4 +491,133 @@ mod tests {
 
         assert_eq!(mean_rearrive_interval(&events), (3 + 3 + 1) as f64 / 3.0);
     }
+
+    #[test]
+    fn test_lru_stack_distances() {
+        let events = [(1, 1), (2, 2), (3, 3), (1, 4), (2, 5), (2, 6), (3, 7)]
+            .iter()
+            .map(|(key, timestamp)| RequestEvent {
+                key: *key,
+                timestamp: *timestamp,
+            })
+            .collect::<Vec<_>>();
+
+        assert_eq!(
+            lru_stack_distances(&events, 1),
+            [None, None, None, Some(2), Some(2), Some(0), Some(2)]
+        );
+        assert_eq!(
+            lru_miss_ratio_curve(&events, 1, &[1, 2, 3], 0),
+            [6. / 7., 6. / 7., 3. / 7.]
+        );
+    }
+
+    #[test]
+    fn test_distance_histograms() {
+        let events = [
+            (1, 1),
+            (2, 2),
+            (3, 3),
+            (1, 4),
+            (2, 5),
+            (2, 6),
+            (3, 7),
+            (3, 8),
+        ]
+        .iter()
+        .map(|(key, timestamp)| RequestEvent {
+            key: *key,
+            timestamp: *timestamp,
+        })
+        .collect::<Vec<_>>();
+
+        // stack distances: -, -, -, 2, 2, 0, 2, 0
+        let histogram = lru_stack_distance_histogram(&events, 1);
+        assert_eq!(histogram.buckets, [2, 0, 3]);
+        assert_eq!(histogram.infinite, 3);
+        assert_eq!(histogram.count, 8);
+
+        // reuse times: -, -, -, 2, 2, 0, 3, 0
+        let histogram = reuse_time_histogram(&events);
+        assert_eq!(histogram.buckets, [2, 0, 3]);
+        assert_eq!(histogram.infinite, 3);
+    }
+
+    #[test]
+    fn test_delayed_hit_potential() {
+        let events = [(1, 0), (1, 2), (2, 3), (1, 4), (1, 5), (2, 9), (1, 10)]
+            .iter()
+            .map(|(key, timestamp)| RequestEvent {
+                key: *key,
+                timestamp: *timestamp,
+            })
+            .collect::<Vec<_>>();
+
+        // windows: key 1 at [0, 5) with 2 delayed hits, [5, 10) with 0, [10, 15) with 0;
+        //          key 2 at [3, 8) with 0, [9, 14) with 0
+        let potential = delayed_hit_potential(&events, 5);
+        assert_eq!(potential.num_windows, 5);
+        assert_eq!(potential.num_delayed_hits, 2);
+        assert_eq!(potential.burst_sizes.buckets, [4, 0, 1]);
+        assert_eq!(potential.miss_latency_total, 25);
+        assert_eq!(potential.delayed_hit_latency_total, 3 + 1);
+        assert_eq!(potential.mean_delayed_hit_latency(), 2.);
+    }
+
+    #[test]
+    fn test_fit_zipf_alpha() {
+        for alpha in [0.6, 0.9, 1.2] {
+            let frequencies = (1..=10000)
+                .map(|r| (1e6 * (r as f64).powf(-alpha)).round() as u64)
+                .collect::<Vec<_>>();
+            let estimated = fit_zipf_alpha(&frequencies);
+            assert!(
+                (estimated - alpha).abs() < 0.01,
+                "alpha: {alpha}, estimated: {estimated}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_popularity_statistics() {
+        let events = [1, 1, 1, 2, 2, 3, 4, 1]
+            .iter()
+            .enumerate()
+            .map(|(timestamp, key)| RequestEvent {
+                key: *key,
+                timestamp: timestamp as u64,
+            })
+            .collect::<Vec<_>>();
+
+        let stats = popularity_statistics(&events, &[1, 2]);
+        assert_eq!(stats.num_unique_keys, 4);
+        assert_eq!(stats.num_one_hit_wonders, 2);
+        assert_eq!(stats.one_hit_wonder_ratio(), 0.5);
+        assert_eq!(stats.top_shares, [(1, 0.5), (2, 0.75)]);
+        assert!(stats.zipf_alpha > 0.);
+    }
+
+    #[test]
+    fn test_lru_miss_ratio_curve_matches_simulation() {
+        use crate::{
+            cache::{construct_k_way_cache, lru::LRU},
+            simulator::run_simulation,
+        };
+
+        // with a zero miss latency, every miss is written to the cache before the next request.
+        let events = (0..2000u64)
+            .map(|i| RequestEvent {
+                key: (i * i + 7 * i) % 37,
+                timestamp: i,
+            })
+            .collect::<Vec<_>>();
+        let k = 4;
+        let capacities = [1, 2, 4, 8];
+        let curve = lru_miss_ratio_curve(&events, k, &capacities, 0);
+        for (capacity, miss_ratio) in capacities.into_iter().zip(curve) {
+            let mut cache = construct_k_way_cache(k, |_| LRU::new(capacity));
+            let result = run_simulation(&mut cache, events.clone(), 0);
+            assert_eq!(result.num_of_loads as f64 / events.len() as f64, miss_ratio);
+        }
+    }
 }
@@ -1,7 +1,19 @@
-use proj_models::{codec::Codec, RequestEvent};
+use proj_models::{codec::Codec, OpRequestEvent, RequestEvent};
 
 pub fn load_events_file(path: &str) -> impl Iterator<Item = RequestEvent> {
     let reader = std::fs::File::open(path).unwrap();
     let reader = std::io::BufReader::new(reader);
     RequestEvent::repeat_read_till_end(reader).map(|r| r.unwrap())
 }
+
+/// Load the requests with their operations from a `.opevents` file. Other files are loaded as `.events` files, where
+/// every request is a read.
+pub fn load_op_events_file(path: &str) -> Box<dyn Iterator<Item = OpRequestEvent>> {
+    if path.ends_with(".opevents") {
+        let reader = std::fs::File::open(path).unwrap();
+        let reader = std::io::BufReader::new(reader);
+        Box::new(OpRequestEvent::repeat_read_till_end(reader).map(|r| r.unwrap()))
+    } else {
+        Box::new(load_events_file(path).map(OpRequestEvent::from))
+    }
+}
@@ -1,9 +1,19 @@
+pub mod arrival;
 pub mod cache;
+pub mod checkpoint;
+pub mod cluster;
+pub mod cost;
 pub mod heuristics;
 pub mod io;
 pub mod macros;
+pub mod origin;
+pub mod parallel;
+pub mod prefetch;
+pub mod sampling;
 pub mod simulator;
+pub mod tenant;
 pub mod types;
+pub mod warmup;
 
 pub fn parse_time_unit(s: &str) -> Result<u64, std::num::ParseIntError> {
     match s {
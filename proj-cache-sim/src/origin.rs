@@ -0,0 +1,205 @@
+//! A model of fetches from the origin (backing store) that may fail.
+//!
+//! A fetch attempt fails with a fixed probability, or always during an outage. A failed attempt is detected after
+//! the miss latency, and is retried after a backoff that doubles with every retry. A fetch fails when all its
+//! attempts fail.
+
+use proj_models::TimeUnit;
+use rand::{Rng, SeedableRng};
+use rand_xorshift::XorShiftRng;
+
+/// A time window `[start, end)` where the origin is down.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Outage {
+    pub start: TimeUnit,
+    pub end: TimeUnit,
+}
+
+impl Outage {
+    pub fn contains(&self, timestamp: TimeUnit) -> bool {
+        self.start <= timestamp && timestamp < self.end
+    }
+}
+
+impl std::fmt::Display for Outage {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}-{}", self.start, self.end)
+    }
+}
+
+impl std::str::FromStr for Outage {
+    type Err = String;
+
+    /// Parse `<start>-<end>` with time units, e.g. `10s-12s`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (start, end) = s
+            .split_once('-')
+            .ok_or_else(|| format!("Invalid outage {:?}: expected <start>-<end>", s))?;
+        let parse = |t: &str| {
+            crate::parse_time_unit(t).map_err(|e| format!("Invalid outage {:?}: {}", s, e))
+        };
+        let (start, end) = (parse(start)?, parse(end)?);
+        if start >= end {
+            return Err(format!(
+                "Invalid outage {:?}: start should be before end",
+                s
+            ));
+        }
+        Ok(Self { start, end })
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct FailureModel {
+    /// Probability that a fetch attempt fails outside outages.
+    pub error_probability: f64,
+    pub outages: Vec<Outage>,
+    /// Number of retries after the first failed attempt.
+    pub max_retries: u32,
+    /// Delay before the first retry. The delay doubles with every retry.
+    pub initial_backoff: TimeUnit,
+    /// How long a failed fetch is cached: requests in this period fail immediately without a fetch.
+    pub negative_ttl: Option<TimeUnit>,
+    /// Seed of the random failures.
+    pub seed: u64,
+}
+
+impl std::fmt::Display for FailureModel {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "error probability: {}", self.error_probability)?;
+        if !self.outages.is_empty() {
+            let outages = self
+                .outages
+                .iter()
+                .map(Outage::to_string)
+                .collect::<Vec<_>>();
+            write!(f, ", outages: {}", outages.join(","))?;
+        }
+        write!(
+            f,
+            ", retries: {}, backoff: {}",
+            self.max_retries, self.initial_backoff
+        )?;
+        if let Some(ttl) = self.negative_ttl {
+            write!(f, ", negative ttl: {}", ttl)?;
+        }
+        Ok(())
+    }
+}
+
+/// The result of a fetch, including all its attempts.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FetchOutcome {
+    /// When the object arrives, or when the last attempt fails.
+    pub completion: TimeUnit,
+    pub failed: bool,
+    pub num_retries: u32,
+}
+
+pub(crate) struct Origin {
+    model: FailureModel,
+    rng: XorShiftRng,
+}
+
+impl Origin {
+    pub(crate) fn new(model: &FailureModel) -> Self {
+        assert!(
+            (0.0..=1.0).contains(&model.error_probability),
+            "error probability should be in [0, 1]"
+        );
+        Self {
+            model: model.clone(),
+            rng: XorShiftRng::seed_from_u64(model.seed),
+        }
+    }
+
+    pub(crate) fn negative_ttl(&self) -> Option<TimeUnit> {
+        self.model.negative_ttl
+    }
+
+    fn attempt_fails(&mut self, timestamp: TimeUnit) -> bool {
+        self.model.outages.iter().any(|o| o.contains(timestamp))
+            || (self.model.error_probability > 0.0
+                && self.rng.gen::<f64>() < self.model.error_probability)
+    }
+
+    /// Fetch an object at `timestamp`, where each attempt takes `miss_latency`.
+    pub(crate) fn fetch(&mut self, timestamp: TimeUnit, miss_latency: TimeUnit) -> FetchOutcome {
+        let mut start = timestamp;
+        let mut backoff = self.model.initial_backoff;
+        for num_retries in 0..=self.model.max_retries {
+            if !self.attempt_fails(start) {
+                return FetchOutcome {
+                    completion: start + miss_latency,
+                    failed: false,
+                    num_retries,
+                };
+            }
+            if num_retries < self.model.max_retries {
+                start += miss_latency + backoff;
+                backoff *= 2;
+            }
+        }
+        FetchOutcome {
+            completion: start + miss_latency,
+            failed: true,
+            num_retries: self.model.max_retries,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fetch_with_outage_and_retries() {
+        let model = FailureModel {
+            outages: vec!["10-40".parse().unwrap()],
+            max_retries: 2,
+            initial_backoff: 4,
+            ..Default::default()
+        };
+        let mut origin = Origin::new(&model);
+        // attempts at 8
+        assert_eq!(
+            origin.fetch(8, 5),
+            FetchOutcome {
+                completion: 13,
+                failed: false,
+                num_retries: 0
+            }
+        );
+        // attempts at 20, 29 (20 + 5 + 4) and 42 (29 + 5 + 8)
+        assert_eq!(
+            origin.fetch(20, 5),
+            FetchOutcome {
+                completion: 47,
+                failed: false,
+                num_retries: 2
+            }
+        );
+        // attempts at 12, 21 and 34 all fail
+        let outcome = origin.fetch(12, 5);
+        assert!(outcome.failed);
+        assert_eq!(outcome.completion, 39);
+        assert!("30-10".parse::<Outage>().is_err());
+    }
+
+    #[test]
+    fn test_random_failures_are_seeded() {
+        let model = FailureModel {
+            error_probability: 0.5,
+            seed: 7,
+            ..Default::default()
+        };
+        let outcomes = |model: &FailureModel| {
+            let mut origin = Origin::new(model);
+            (0..100).map(|t| origin.fetch(t, 1)).collect::<Vec<_>>()
+        };
+        let first = outcomes(&model);
+        assert_eq!(first, outcomes(&model));
+        let num_failed = first.iter().filter(|o| o.failed).count();
+        assert!(num_failed > 20 && num_failed < 80);
+    }
+}
@@ -0,0 +1,279 @@
+//! Parallel simulation of the sets of a [`MultiCache`].
+//!
+//! When each key maps to one set, the sets do not interact under the simulator: a request only accesses the set of
+//! its key, and its fetch takes the miss latency whatever the other sets do. The trace is partitioned by set, the
+//! sets are simulated concurrently, and the results are merged in the order of [`run_simulation`].
+//!
+//! [`run_simulation`]: crate::simulator::run_simulation
+
+use proj_models::{OpRequestEvent, TimeUnit};
+use rayon::prelude::*;
+
+use crate::{
+    cache::{Cache, MultiCache},
+    prefetch::PrefetchConfig,
+    simulator::{
+        run_simulation_with_options, FailureStatistics, RequestResult, SimulationOptions,
+        SimulationResult, WriteStatistics,
+    },
+};
+
+/// A set of a [`MultiCache`], counting the keys inserted into it as the [`MultiCache`] does.
+struct CountingSet<'a, C> {
+    cache: &'a mut C,
+    num_insertions: &'a mut usize,
+}
+
+impl<C> CountingSet<'_, C> {
+    fn count_insertion(&mut self, key: &u64)
+    where
+        C: Cache<u64, ()>,
+    {
+        if !self.cache.contains(key) {
+            *self.num_insertions += 1;
+        }
+    }
+}
+
+impl<C: Cache<u64, ()>> Cache<u64, ()> for CountingSet<'_, C> {
+    const NAME: &'static str = C::NAME;
+
+    fn write(&mut self, key: u64, value: (), timestamp: TimeUnit) {
+        self.count_insertion(&key);
+        self.cache.write(key, value, timestamp)
+    }
+
+    fn write_with_ttl(&mut self, key: u64, value: (), timestamp: TimeUnit, ttl: TimeUnit) {
+        self.count_insertion(&key);
+        self.cache.write_with_ttl(key, value, timestamp, ttl)
+    }
+
+    fn get(&mut self, key: &u64, timestamp: TimeUnit) -> Option<&()> {
+        self.cache.get(key, timestamp)
+    }
+
+    fn get_stale(&mut self, key: &u64, timestamp: TimeUnit) -> Option<&()> {
+        self.cache.get_stale(key, timestamp)
+    }
+
+    fn is_expired(&self, key: &u64, timestamp: TimeUnit) -> bool {
+        self.cache.is_expired(key, timestamp)
+    }
+
+    fn contains(&self, key: &u64) -> bool {
+        self.cache.contains(key)
+    }
+
+    fn invalidate(&mut self, key: &u64) -> bool {
+        self.cache.invalidate(key)
+    }
+
+    fn set_fetch_cost(&mut self, key: &u64, cost: f64) {
+        self.cache.set_fetch_cost(key, cost)
+    }
+}
+
+/// Whether the sets of `cache` can be simulated independently under `options`: each key maps to one set, and the
+/// sets are not coupled by a prefetcher (which fetches keys of other sets) or by random fetch failures (drawn in the
+/// order of all fetches).
+pub fn can_simulate_in_parallel<C: Cache<u64, ()>>(
+    cache: &MultiCache<u64, (), C>,
+    options: &SimulationOptions,
+) -> bool {
+    cache.has_fixed_sets() && options.prefetch == PrefetchConfig::None && options.failures.is_none()
+}
+
+/// Same as [`run_simulation_with_options`], with the sets of `cache` simulated in parallel. The results are identical,
+/// except that the total fetch cost may differ by rounding, as it is summed in another order.
+///
+/// Falls back to [`run_simulation_with_options`] if the sets cannot be simulated independently (see
+/// [`can_simulate_in_parallel`]).
+///
+/// [`run_simulation`]: crate::simulator::run_simulation
+pub fn run_parallel_simulation<C, I, E>(
+    cache: &mut MultiCache<u64, (), C>,
+    requests: I,
+    miss_latency: TimeUnit,
+    options: &SimulationOptions,
+) -> SimulationResult
+where
+    C: Cache<u64, ()> + Send,
+    I: IntoIterator<Item = E>,
+    E: Into<OpRequestEvent>,
+{
+    if !can_simulate_in_parallel(cache, options) {
+        return run_simulation_with_options(cache, requests, miss_latency, options);
+    }
+    let mut partitions = vec![Vec::new(); cache.num_sets()];
+    let mut last_request_timestamp = 0;
+    for request in requests.into_iter().map(Into::into) {
+        // the simulator ignores requests earlier than the last request of any set
+        if request.timestamp < last_request_timestamp {
+            continue;
+        }
+        last_request_timestamp = request.timestamp;
+        partitions[cache.set_of(&request.key)].push(request);
+    }
+
+    let set_results = cache
+        .sets_with_insertions_mut()
+        .zip(partitions)
+        .collect::<Vec<_>>()
+        .into_par_iter()
+        .map(|((cache, num_insertions), requests)| {
+            let mut set = CountingSet {
+                cache,
+                num_insertions,
+            };
+            run_simulation_with_options(&mut set, requests, miss_latency, options)
+        })
+        .collect::<Vec<_>>();
+    merge_results(set_results)
+}
+
+/// Merge the results of the sets.
+fn merge_results(set_results: Vec<SimulationResult>) -> SimulationResult {
+    let mut merged = SimulationResult {
+        results: Vec::new(),
+        failed: Vec::new(),
+        last_event_timestamp: 0,
+        num_of_loads: 0,
+        writes: WriteStatistics::default(),
+        num_stale_hits: 0,
+        num_prefetches: 0,
+        num_useful_prefetches: 0,
+        failures: FailureStatistics::default(),
+        total_fetch_cost: 0.0,
+        counted_events: Vec::new(),
+    };
+    for result in set_results {
+        merged.results.extend(result.results);
+        merged.failed.extend(result.failed);
+        merged.last_event_timestamp = merged.last_event_timestamp.max(result.last_event_timestamp);
+        merged.num_of_loads += result.num_of_loads;
+        merged.writes.num_writes += result.writes.num_writes;
+        merged.writes.num_deletes += result.writes.num_deletes;
+        merged.writes.total_write_latency += result.writes.total_write_latency;
+        merged.writes.num_backing_store_writes += result.writes.num_backing_store_writes;
+        merged.writes.num_writebacks += result.writes.num_writebacks;
+        merged.writes.num_invalidated_fetches += result.writes.num_invalidated_fetches;
+        merged.num_stale_hits += result.num_stale_hits;
+        merged.num_prefetches += result.num_prefetches;
+        merged.num_useful_prefetches += result.num_useful_prefetches;
+        merged.failures.num_failed_fetches += result.failures.num_failed_fetches;
+        merged.failures.num_retries += result.failures.num_retries;
+        merged.failures.num_negative_hits += result.failures.num_negative_hits;
+        merged.total_fetch_cost += result.total_fetch_cost;
+        merged.counted_events.extend(result.counted_events);
+    }
+    // results with the same order are equal, so an unstable sort gives the same order as the simulator
+    merged
+        .results
+        .par_sort_unstable_by_key(RequestResult::order);
+    merged.failed.par_sort_unstable_by_key(RequestResult::order);
+    merged
+}
+
+#[cfg(test)]
+mod tests {
+    use proj_models::{Operation, RequestEvent};
+
+    use super::*;
+    use crate::{
+        cache::{
+            construct_k_way_cache_with_mapping, lru::LRU, lru_mad::LRUMinAD, mapping::SetMapping,
+        },
+        simulator::MissHandling,
+    };
+
+    fn events(n: u64) -> Vec<OpRequestEvent> {
+        (0..n)
+            .map(|i| {
+                let request = RequestEvent {
+                    key: (i * 7919) % 101,
+                    // bursts of requests at the same timestamp, and a few out of order
+                    timestamp: (i / 3) * 4 - if i % 50 == 49 { 5 } else { 0 },
+                };
+                let mut request = OpRequestEvent::from(request);
+                if i % 17 == 0 {
+                    request.op = Operation::Write;
+                }
+                request
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_parallel_simulation_matches_sequential() {
+        for miss_handling in [
+            MissHandling::Coalesce,
+            MissHandling::NoCoalescing,
+            MissHandling::ZeroLatency,
+        ] {
+            let options = SimulationOptions {
+                miss_handling,
+                default_ttl: Some(200),
+                stale_while_revalidate: true,
+                ..Default::default()
+            };
+            for mapping in [
+                SetMapping::Hash,
+                SetMapping::Modulo,
+                SetMapping::Skewed { ways: 2 },
+            ] {
+                let construct =
+                    || construct_k_way_cache_with_mapping(8, mapping, |_| LRUMinAD::new(4, 30));
+                let mut sequential = construct();
+                let expected =
+                    run_simulation_with_options(&mut sequential, events(5000), 30, &options);
+                let mut parallel = construct();
+                let actual = run_parallel_simulation(&mut parallel, events(5000), 30, &options);
+                assert_eq!(
+                    actual.results, expected.results,
+                    "{:?} {}",
+                    miss_handling, mapping
+                );
+                assert_eq!(actual.last_event_timestamp, expected.last_event_timestamp);
+                assert_eq!(actual.num_of_loads, expected.num_of_loads);
+                assert_eq!(actual.writes, expected.writes);
+                assert_eq!(actual.num_stale_hits, expected.num_stale_hits);
+
+                // the caches are left in the same state
+                let later = || {
+                    events(100).into_iter().map(|mut r| {
+                        r.timestamp += 10_000;
+                        r
+                    })
+                };
+                let next = run_simulation_with_options(&mut sequential, later(), 30, &options);
+                let next_parallel =
+                    run_simulation_with_options(&mut parallel, later(), 30, &options);
+                assert_eq!(next_parallel.results, next.results);
+            }
+        }
+    }
+
+    #[test]
+    fn test_parallel_simulation_keeps_insertion_counts() {
+        let requests = (0..200).map(|i| RequestEvent {
+            key: i % 23,
+            timestamp: i * 10,
+        });
+        let mut sequential =
+            construct_k_way_cache_with_mapping(4, SetMapping::Hash, |_| LRU::new(2));
+        let mut parallel = construct_k_way_cache_with_mapping(4, SetMapping::Hash, |_| LRU::new(2));
+        run_simulation_with_options(
+            &mut sequential,
+            requests.clone(),
+            5,
+            &SimulationOptions::default(),
+        );
+        run_parallel_simulation(&mut parallel, requests, 5, &SimulationOptions::default());
+        let save = |cache: &MultiCache<u64, (), LRU<u64, ()>>| {
+            let mut state = Vec::new();
+            cache.save_state(&mut state).unwrap();
+            state
+        };
+        assert_eq!(save(&parallel), save(&sequential));
+    }
+}
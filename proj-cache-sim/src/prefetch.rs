@@ -0,0 +1,186 @@
+//! Prefetchers, which issue fetches of keys that are likely to be requested soon.
+//!
+//! A prefetched key is fetched from the backing store like a miss, but without a waiting request. Requests arriving
+//! while the prefetch is in flight wait for it (delayed hits), and later requests hit.
+
+use ahash::AHashMap;
+use proj_models::{RequestId, TimeUnit};
+
+/// A hook of the simulator, called on every read request.
+pub trait Prefetcher {
+    /// Observe a read of `key`, where `hit` is whether it is served immediately, and return the keys to prefetch.
+    /// Keys already cached or in flight are skipped by the simulator.
+    fn on_request(&mut self, key: RequestId, timestamp: TimeUnit, hit: bool) -> Vec<RequestId>;
+}
+
+/// Never prefetch.
+pub struct NoPrefetcher;
+
+impl Prefetcher for NoPrefetcher {
+    fn on_request(&mut self, _key: RequestId, _timestamp: TimeUnit, _hit: bool) -> Vec<RequestId> {
+        Vec::new()
+    }
+}
+
+/// Sequential prefetch: on a miss to `key`, prefetch `key + 1, ..., key + n`. Keys of preprocessed traces are
+/// numbered by their first appearance, so this suits traces that scan blocks in order.
+pub struct NextNPrefetcher {
+    n: u64,
+}
+
+impl NextNPrefetcher {
+    pub fn new(n: u64) -> Self {
+        Self { n }
+    }
+}
+
+impl Prefetcher for NextNPrefetcher {
+    fn on_request(&mut self, key: RequestId, _timestamp: TimeUnit, hit: bool) -> Vec<RequestId> {
+        if hit {
+            return Vec::new();
+        }
+        (1..=self.n).filter_map(|i| key.checked_add(i)).collect()
+    }
+}
+
+/// A first-order Markov prefetcher: it counts how often each key follows another key in the request stream, and on
+/// every request prefetches the `degree` most frequent successors of the requested key.
+pub struct MarkovPrefetcher {
+    degree: usize,
+    /// Maximum number of successors remembered per key. The least frequent successor is replaced when it is full.
+    max_successors: usize,
+    successors: AHashMap<RequestId, Vec<(RequestId, u32)>>,
+    last_key: Option<RequestId>,
+}
+
+impl MarkovPrefetcher {
+    pub fn new(degree: usize) -> Self {
+        assert!(degree > 0, "degree should be greater than 0");
+        Self {
+            degree,
+            max_successors: 4 * degree,
+            successors: AHashMap::new(),
+            last_key: None,
+        }
+    }
+
+    fn record_transition(&mut self, from: RequestId, to: RequestId) {
+        let successors = self.successors.entry(from).or_default();
+        if let Some((_, count)) = successors.iter_mut().find(|(k, _)| *k == to) {
+            *count += 1;
+        } else if successors.len() < self.max_successors {
+            successors.push((to, 1));
+        } else {
+            let least = successors
+                .iter_mut()
+                .min_by_key(|(_, count)| *count)
+                .expect("successors should not be empty");
+            *least = (to, 1);
+        }
+    }
+}
+
+impl Prefetcher for MarkovPrefetcher {
+    fn on_request(&mut self, key: RequestId, _timestamp: TimeUnit, _hit: bool) -> Vec<RequestId> {
+        if let Some(last_key) = self.last_key.replace(key) {
+            if last_key != key {
+                self.record_transition(last_key, key);
+            }
+        }
+        let Some(successors) = self.successors.get(&key) else {
+            return Vec::new();
+        };
+        let mut successors = successors.clone();
+        // most frequent first, ties broken by key for determinism
+        successors.sort_by_key(|&(k, count)| (std::cmp::Reverse(count), k));
+        successors
+            .into_iter()
+            .take(self.degree)
+            .map(|(k, _)| k)
+            .collect()
+    }
+}
+
+/// A prefetcher choice, e.g. for the command line: `none`, `next[:<n>]` (1 by default) or `markov[:<degree>]` (1
+/// by default).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum PrefetchConfig {
+    #[default]
+    None,
+    NextN(u64),
+    Markov(usize),
+}
+
+impl PrefetchConfig {
+    /// Construct a new prefetcher with no history.
+    pub fn build(&self) -> Box<dyn Prefetcher> {
+        match *self {
+            Self::None => Box::new(NoPrefetcher),
+            Self::NextN(n) => Box::new(NextNPrefetcher::new(n)),
+            Self::Markov(degree) => Box::new(MarkovPrefetcher::new(degree)),
+        }
+    }
+}
+
+impl std::fmt::Display for PrefetchConfig {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::None => write!(f, "none"),
+            Self::NextN(n) => write!(f, "next:{}", n),
+            Self::Markov(degree) => write!(f, "markov:{}", degree),
+        }
+    }
+}
+
+impl std::str::FromStr for PrefetchConfig {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (name, arg) = match s.split_once(':') {
+            Some((name, arg)) => (name, Some(arg)),
+            None => (s, None),
+        };
+        let parse_arg = |default: u64| -> Result<u64, String> {
+            match arg {
+                Some(arg) => match arg.parse::<u64>() {
+                    Ok(n) if n > 0 => Ok(n),
+                    _ => Err(format!("Invalid prefetch degree: {}", arg)),
+                },
+                None => Ok(default),
+            }
+        };
+        match name {
+            "none" if arg.is_none() => Ok(Self::None),
+            "next" => Ok(Self::NextN(parse_arg(1)?)),
+            "markov" => Ok(Self::Markov(parse_arg(1)? as usize)),
+            _ => Err(format!("Unknown prefetcher: {}", s)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_prefetch_config() {
+        for s in ["none", "next:4", "markov:2"] {
+            assert_eq!(s.parse::<PrefetchConfig>().unwrap().to_string(), s);
+        }
+        assert_eq!("next".parse(), Ok(PrefetchConfig::NextN(1)));
+        assert!("next:0".parse::<PrefetchConfig>().is_err());
+        assert!("unknown".parse::<PrefetchConfig>().is_err());
+    }
+
+    #[test]
+    fn test_markov_prefetcher() {
+        let mut prefetcher = MarkovPrefetcher::new(1);
+        for key in [1, 2, 1, 3, 1, 2] {
+            prefetcher.on_request(key, 0, false);
+        }
+        // 1 is followed by 2 twice and by 3 once
+        assert_eq!(prefetcher.on_request(1, 0, false), [2]);
+        assert_eq!(prefetcher.on_request(3, 0, false), [1]);
+        assert!(prefetcher.on_request(4, 0, false).is_empty());
+    }
+}
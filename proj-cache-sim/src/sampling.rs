@@ -0,0 +1,223 @@
+//! Hash-based spatial sampling of keys (SHARDS), for fast approximate simulation.
+//!
+//! A request is sampled if and only if the hash of its key falls below a threshold, so either all or none of the
+//! requests to a key are kept. The per-key timing (and thus the miss windows of delayed hits) is preserved, and a
+//! cache simulated on the sampled trace approximates the full cache when its capacity is scaled by the sampling rate.
+//!
+//! Reference: Waldspurger et al., "Efficient MRC Construction with SHARDS", FAST '15.
+
+use std::collections::BinaryHeap;
+
+use ahash::AHashSet;
+use proj_models::{RequestEvent, RequestId};
+
+/// The modulus `P` of the sampling hash. A key is sampled if `hash(key) mod P < T`.
+const MODULUS: u64 = 1 << 24;
+
+/// The hash used for sampling. This is intentionally different from [`crate::cache::ObjectId::get_hash`], so that
+/// the sampled keys are not correlated with the set they are mapped to in [`crate::cache::MultiCache`].
+fn sampling_hash(key: RequestId) -> u64 {
+    // splitmix64 finalizer
+    let mut z = key.wrapping_add(0x9e3779b97f4a7c15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+    (z ^ (z >> 31)) % MODULUS
+}
+
+pub struct SpatialSampler {
+    threshold: u64,
+    /// The maximum number of sampled keys for the fixed-size variant.
+    max_keys: Option<usize>,
+    /// Sampled keys with their hashes, where the key with the largest hash is on top.
+    sampled_keys: BinaryHeap<(u64, RequestId)>,
+    sampled_key_set: AHashSet<RequestId>,
+}
+
+impl SpatialSampler {
+    /// Sample keys with a fixed rate in `(0, 1]`.
+    pub fn fixed_rate(rate: f64) -> Self {
+        assert!(rate > 0. && rate <= 1., "sampling rate should be in (0, 1]");
+        Self {
+            threshold: ((rate * MODULUS as f64).round() as u64).max(1),
+            max_keys: None,
+            sampled_keys: BinaryHeap::new(),
+            sampled_key_set: AHashSet::new(),
+        }
+    }
+
+    /// Sample at most `max_keys` distinct keys. The sampling rate starts at 1 and is lowered every time the number
+    /// of sampled keys exceeds `max_keys`, by evicting the keys with the largest hash.
+    pub fn fixed_size(max_keys: usize) -> Self {
+        assert!(max_keys > 0, "max_keys should be greater than 0");
+        Self {
+            threshold: MODULUS,
+            max_keys: Some(max_keys),
+            sampled_keys: BinaryHeap::new(),
+            sampled_key_set: AHashSet::new(),
+        }
+    }
+
+    /// The current sampling rate. For the fixed-size variant, the rate is non-increasing over time.
+    pub fn rate(&self) -> f64 {
+        self.threshold as f64 / MODULUS as f64
+    }
+
+    /// Decide online whether a request should be kept in the sampled trace. With the fixed-size variant, a key that
+    /// is kept may be evicted from the sample later, so filter a whole trace with [`Self::observe`] and
+    /// [`Self::is_sampled`] instead.
+    pub fn sample(&mut self, event: &RequestEvent) -> bool {
+        let hash = sampling_hash(event.key);
+        if hash >= self.threshold {
+            return false;
+        }
+        let Some(max_keys) = self.max_keys else {
+            return true;
+        };
+
+        if self.sampled_key_set.insert(event.key) {
+            self.sampled_keys.push((hash, event.key));
+            while self.sampled_key_set.len() > max_keys {
+                // lower the threshold to the largest hash, and evict all keys with that hash
+                let (largest, _) = *self.sampled_keys.peek().unwrap();
+                self.threshold = largest;
+                while let Some(&(h, key)) = self.sampled_keys.peek() {
+                    if h != largest {
+                        break;
+                    }
+                    self.sampled_keys.pop();
+                    self.sampled_key_set.remove(&key);
+                }
+            }
+        }
+        hash < self.threshold
+    }
+
+    /// Observe all the requests of a trace, so that the threshold (and thus the rate) is final.
+    pub fn observe<'a>(&mut self, events: impl IntoIterator<Item = &'a RequestEvent>) {
+        for event in events {
+            self.sample(event);
+        }
+    }
+
+    /// Whether a request is in the sample at the current threshold.
+    pub fn is_sampled(&self, event: &RequestEvent) -> bool {
+        sampling_hash(event.key) < self.threshold
+    }
+}
+
+/// Scale a cache capacity by the sampling rate. The scaled capacity is at least 1.
+pub fn scale_capacity(capacity: usize, rate: f64) -> usize {
+    ((capacity as f64 * rate).round() as usize).max(1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        cache::{construct_k_way_cache, lru::LRU},
+        simulator::{compute_statistics, run_simulation},
+    };
+
+    /// A Zipf-like (alpha = 0.6) synthetic trace generated by a linear congruential generator.
+    fn synthetic_trace(len: u64, num_keys: u64) -> Vec<RequestEvent> {
+        let mut state: u64 = 42;
+        (0..len)
+            .map(|i| {
+                state = state
+                    .wrapping_mul(6364136223846793005)
+                    .wrapping_add(1442695040888963407);
+                let u = (state >> 11) as f64 / (1u64 << 53) as f64;
+                RequestEvent {
+                    key: (num_keys as f64 * u.powf(2.5)) as u64,
+                    timestamp: i * 1000,
+                }
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_fixed_rate_sampling_is_per_key() {
+        let events = synthetic_trace(100_000, 10_000);
+        let mut sampler = SpatialSampler::fixed_rate(0.1);
+        let mut sampled = AHashSet::new();
+        let mut unsampled = AHashSet::new();
+        for event in &events {
+            if sampler.sample(event) {
+                sampled.insert(event.key);
+            } else {
+                unsampled.insert(event.key);
+            }
+        }
+        assert!(sampled.is_disjoint(&unsampled));
+        let ratio = sampled.len() as f64 / (sampled.len() + unsampled.len()) as f64;
+        assert!((ratio - 0.1).abs() < 0.02, "ratio of sampled keys: {ratio}");
+    }
+
+    #[test]
+    fn test_fixed_size_sampling() {
+        let events = synthetic_trace(100_000, 10_000);
+        let mut sampler = SpatialSampler::fixed_size(500);
+        let mut rate = sampler.rate();
+        for event in &events {
+            sampler.sample(event);
+            assert!(sampler.rate() <= rate);
+            rate = sampler.rate();
+        }
+        assert!(sampler.sampled_key_set.len() <= 500);
+        assert!(sampler.rate() < 1.);
+    }
+
+    #[test]
+    fn test_fixed_size_sampling_uses_final_threshold() {
+        let events = synthetic_trace(100_000, 10_000);
+        let mut sampler = SpatialSampler::fixed_size(500);
+        sampler.observe(&events);
+        let sampled = events
+            .iter()
+            .filter(|e| sampler.is_sampled(e))
+            .collect::<Vec<_>>();
+        assert!(sampled
+            .iter()
+            .all(|e| sampling_hash(e.key) < sampler.threshold));
+        let keys = sampled.iter().map(|e| e.key).collect::<AHashSet<_>>();
+        assert!(keys.len() <= 500);
+        assert_eq!(keys, sampler.sampled_key_set);
+    }
+
+    #[test]
+    fn test_sampling_estimation_error() {
+        let events = synthetic_trace(300_000, 30_000);
+        let (k, capacity, miss_latency) = (4, 600, 50_000);
+
+        let mut cache = construct_k_way_cache(k, |_| LRU::new(capacity));
+        let full =
+            compute_statistics(&run_simulation(&mut cache, events.clone(), miss_latency).results);
+
+        for mut sampler in [
+            SpatialSampler::fixed_rate(0.1),
+            SpatialSampler::fixed_size(3_000),
+        ] {
+            sampler.observe(&events);
+            let sampled = events
+                .iter()
+                .filter(|e| sampler.is_sampled(e))
+                .cloned()
+                .collect::<Vec<_>>();
+            let scaled_capacity = scale_capacity(capacity, sampler.rate());
+            let mut cache = construct_k_way_cache(k, |_| LRU::new(scaled_capacity));
+            let estimated =
+                compute_statistics(&run_simulation(&mut cache, sampled, miss_latency).results);
+
+            let error =
+                (estimated.average_latency - full.average_latency).abs() / full.average_latency;
+            assert!(
+                error < 0.05,
+                "rate: {}, full: {}, estimated: {}, error: {}",
+                sampler.rate(),
+                full.average_latency,
+                estimated.average_latency,
+                error
+            );
+        }
+    }
+}
@@ -1,9 +1,15 @@
-use std::{collections::VecDeque, iter::Peekable};
+use std::{cmp::Reverse, collections::BinaryHeap, iter::Peekable};
 
-use ahash::AHashMap;
-use proj_models::{RequestEvent, RequestId, TimeUnit};
+use ahash::{AHashMap, AHashSet};
+use proj_models::{OpRequestEvent, Operation, RequestId, TimeUnit};
 
-use crate::{cache::Cache, verbose};
+use crate::{
+    cache::Cache,
+    cost::CostModel,
+    origin::{FailureModel, FetchOutcome, Origin},
+    prefetch::{PrefetchConfig, Prefetcher},
+    verbose,
+};
 
 #[derive(Debug, Eq, PartialEq, Clone)]
 pub struct RequestResult {
@@ -12,20 +18,137 @@ pub struct RequestResult {
     pub completion_timestamp: TimeUnit,
 }
 
+impl RequestResult {
+    /// The order of the results of a simulation: by request timestamp, then by completion timestamp and key.
+    pub(crate) fn order(&self) -> (TimeUnit, TimeUnit, RequestId) {
+        (self.request_timestamp, self.completion_timestamp, self.key)
+    }
+}
+
+/// A fetch in flight. Fetches complete in the order of their completion timestamps, and then in the order they are
+/// issued.
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord)]
+struct Fetch {
+    completion: TimeUnit,
+    seq: usize,
+    key: RequestId,
+    failed: bool,
+}
+
+/// The state of a fetch in flight, by [`Fetch::seq`].
+struct PendingFetch {
+    /// The timestamps of the requests waiting for the fetch. Without coalescing, a fetch has at most one.
+    requests: Vec<TimeUnit>,
+    /// The TTL given by the request that issued the fetch.
+    ttl: Option<TimeUnit>,
+    /// Whether the object was written or deleted since the fetch was issued, so that the fetched object is outdated.
+    invalidated: bool,
+}
+
+/// The fetches in flight, and the origin they are fetched from.
+struct Fetches<'a> {
+    in_flight: BinaryHeap<Reverse<Fetch>>,
+    num_issued: usize,
+    miss_latency: TimeUnit,
+    /// `None` if fetches never fail.
+    origin: Option<Origin>,
+    failures: FailureStatistics,
+    cost_model: &'a CostModel,
+    total_cost: f64,
+    /// The fetches from the origin, at the timestamps they are issued.
+    loads: Vec<(TimeUnit, CountedEvent)>,
+}
+
+impl<'a> Fetches<'a> {
+    fn new(
+        miss_latency: TimeUnit,
+        failures: Option<&FailureModel>,
+        cost_model: &'a CostModel,
+    ) -> Self {
+        Self {
+            in_flight: BinaryHeap::new(),
+            num_issued: 0,
+            miss_latency,
+            origin: failures.map(Origin::new),
+            failures: FailureStatistics::default(),
+            cost_model,
+            total_cost: 0.0,
+            loads: Vec::new(),
+        }
+    }
+
+    /// Fetch `key` from the origin at `timestamp`, and return when it arrives or fails. The cost of the fetch is
+    /// charged unless it fails.
+    fn fetch(&mut self, key: RequestId, timestamp: TimeUnit) -> FetchOutcome {
+        let outcome = match &mut self.origin {
+            Some(origin) => origin.fetch(timestamp, self.miss_latency),
+            None => FetchOutcome {
+                completion: timestamp + self.miss_latency,
+                failed: false,
+                num_retries: 0,
+            },
+        };
+        let cost = if outcome.failed {
+            self.failures.num_failed_fetches += 1;
+            0.0
+        } else {
+            self.cost_model.cost(key)
+        };
+        self.failures.num_retries += outcome.num_retries as usize;
+        self.total_cost += cost;
+        self.loads.push((
+            timestamp,
+            CountedEvent::Load {
+                failed: outcome.failed,
+                num_retries: outcome.num_retries as usize,
+                cost,
+            },
+        ));
+        outcome
+    }
+
+    /// Issue a fetch of `key` at `timestamp`, which completes as a [`Event::Completion`], and return its
+    /// [`Fetch::seq`].
+    fn issue(&mut self, key: RequestId, timestamp: TimeUnit) -> usize {
+        let outcome = self.fetch(key, timestamp);
+        let seq = self.num_issued;
+        self.in_flight.push(Reverse(Fetch {
+            completion: outcome.completion,
+            seq,
+            key,
+            failed: outcome.failed,
+        }));
+        self.num_issued += 1;
+        seq
+    }
+
+    /// Cache the failure of a fetch of `key` that fails at `timestamp`, if negative caching is enabled.
+    fn cache_failure(
+        &self,
+        negative_cache: &mut AHashMap<RequestId, TimeUnit>,
+        key: RequestId,
+        timestamp: TimeUnit,
+    ) {
+        if let Some(ttl) = self.origin.as_ref().and_then(Origin::negative_ttl) {
+            negative_cache.insert(key, timestamp + ttl);
+        }
+    }
+}
+
 #[derive(Debug)]
 enum Event {
-    Request(RequestId, TimeUnit),
-    Completion(RequestId, TimeUnit),
+    Request(OpRequestEvent),
+    Completion(Fetch),
     End,
 }
 
 fn next_event<I>(
     requests: &mut Peekable<I>,
-    future_completion: &mut VecDeque<(RequestId, TimeUnit)>,
+    fetches: &mut Fetches<'_>,
     last_request_timestamp: &mut TimeUnit,
 ) -> Event
 where
-    I: Iterator<Item = RequestEvent>,
+    I: Iterator<Item = OpRequestEvent>,
 {
     // get the earlist among the next request and the next completion
     // if the timestamp of the next request is the same as the next completion, we should process the request first.
@@ -36,7 +159,7 @@ where
     let next_request;
     loop {
         match requests.peek() {
-            Some(RequestEvent { key, timestamp }) => {
+            Some(OpRequestEvent { key, timestamp, .. }) => {
                 if timestamp < last_request_timestamp {
                     verbose!("Warning: event not in order is ignored: the event of key {:?} at timestamp {} is earlier than the last request at timestamp {}", key, timestamp, last_request_timestamp);
                     requests.next();
@@ -52,122 +175,686 @@ where
         }
     }
 
-    let next_completion = future_completion.front();
+    let next_completion = fetches
+        .in_flight
+        .peek()
+        .map(|Reverse(fetch)| fetch.completion);
 
     let choose_request = match (next_request, next_completion) {
-        (Some((_, req_timestamp)), Some((_, com_timestamp))) if req_timestamp <= *com_timestamp => {
-            true
-        }
+        (Some((_, req_timestamp)), Some(com_timestamp)) if req_timestamp <= com_timestamp => true,
         (Some(_), None) => true,
         _ => false,
     };
 
     if choose_request {
-        let RequestEvent { key, timestamp } = requests.next().unwrap();
-        *last_request_timestamp = timestamp;
-        Event::Request(key, timestamp)
+        let request = requests.next().unwrap();
+        *last_request_timestamp = request.timestamp;
+        Event::Request(request)
     } else {
-        future_completion
-            .pop_front()
-            .map(|(key, timestamp)| Event::Completion(key, timestamp))
+        fetches
+            .in_flight
+            .pop()
+            .map(|Reverse(fetch)| Event::Completion(fetch))
             .unwrap_or(Event::End)
     }
 }
 
+/// How misses to an object with a fetch already in flight are handled.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum MissHandling {
+    /// Coalesce the miss with the fetch in flight (a delayed hit).
+    #[default]
+    Coalesce,
+    /// Every miss issues its own fetch, and completes after the full miss latency.
+    NoCoalescing,
+    /// The classic zero-latency miss model: a missed object is written to the cache immediately, so there is no
+    /// in-flight state. Misses still count the miss latency, but later requests hit right away.
+    ZeroLatency,
+}
+
+impl std::fmt::Display for MissHandling {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Coalesce => write!(f, "coalesce"),
+            Self::NoCoalescing => write!(f, "no-coalescing"),
+            Self::ZeroLatency => write!(f, "zero-latency"),
+        }
+    }
+}
+
+impl std::str::FromStr for MissHandling {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "coalesce" => Ok(Self::Coalesce),
+            "no-coalescing" => Ok(Self::NoCoalescing),
+            "zero-latency" => Ok(Self::ZeroLatency),
+            _ => Err(format!("Unknown miss handling: {}", s)),
+        }
+    }
+}
+
+/// When a write updates the backing store.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum WritePolicy {
+    /// Every write updates the backing store, and completes after the miss latency.
+    #[default]
+    WriteThrough,
+    /// A write to a cached object completes immediately and marks the object dirty. Dirty objects are written back
+    /// when they are evicted.
+    WriteBack,
+}
+
+impl std::fmt::Display for WritePolicy {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::WriteThrough => write!(f, "write-through"),
+            Self::WriteBack => write!(f, "write-back"),
+        }
+    }
+}
+
+impl std::str::FromStr for WritePolicy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "write-through" => Ok(Self::WriteThrough),
+            "write-back" => Ok(Self::WriteBack),
+            _ => Err(format!("Unknown write policy: {}", s)),
+        }
+    }
+}
+
+/// Options of [`run_simulation_with_options`]. The default is the delayed-hits model of [`run_simulation`].
+#[derive(Debug, Clone, Default)]
+pub struct SimulationOptions {
+    pub miss_handling: MissHandling,
+    pub write_policy: WritePolicy,
+    /// Whether a write to an object that is not cached inserts it into the cache. Otherwise, the write goes to the
+    /// backing store only.
+    pub write_allocate: bool,
+    /// Time to live of the objects written to the cache, unless the request gives its own TTL. Objects never expire
+    /// if both are not given.
+    pub default_ttl: Option<TimeUnit>,
+    /// Serve an expired object immediately, and revalidate it with a fetch in the background. Otherwise, a request
+    /// to an expired object is a miss.
+    pub stale_while_revalidate: bool,
+    /// The prefetcher called on every read request.
+    pub prefetch: PrefetchConfig,
+    /// Failures of fetches from the backing store. Fetches never fail if not given.
+    pub failures: Option<FailureModel>,
+    /// The cost of fetches from the backing store, also given to cost-aware policies.
+    pub fetch_cost: CostModel,
+}
+
+/// Counters of the write and delete requests. Only reads have a [`RequestResult`].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct WriteStatistics {
+    pub num_writes: usize,
+    pub num_deletes: usize,
+    /// Total latency of the writes.
+    pub total_write_latency: u128,
+    /// Number of writes that update the backing store directly.
+    pub num_backing_store_writes: usize,
+    /// Number of dirty objects written back, when evicted or at the end of the simulation.
+    pub num_writebacks: usize,
+    /// Number of fetches whose objects are not cached on completion, because the object is written or deleted while
+    /// the fetch is in flight.
+    pub num_invalidated_fetches: usize,
+}
+
+/// Counters of the failed fetches. See [`FailureModel`].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct FailureStatistics {
+    /// Number of fetches whose attempts all fail. They are included in `num_of_loads`.
+    pub num_failed_fetches: usize,
+    /// Number of retried attempts of all fetches.
+    pub num_retries: usize,
+    /// Number of requests that fail immediately because of a negatively cached failure.
+    pub num_negative_hits: usize,
+}
+
+/// An event counted by the simulation, at the timestamp of the request that causes it. See
+/// [`SimulationResult::counters_since`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CountedEvent {
+    /// A fetch from the backing store, with its cost (zero if it fails) and its number of retried attempts.
+    Load {
+        failed: bool,
+        num_retries: usize,
+        cost: f64,
+    },
+    Prefetch,
+    UsefulPrefetch,
+    StaleHit,
+    Write,
+    Delete,
+    Writeback,
+}
+
+/// The counters of a [`SimulationResult`] over the requests from a timestamp, e.g. after the warmup.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Counters {
+    pub num_of_loads: usize,
+    pub num_writes: usize,
+    pub num_deletes: usize,
+    pub num_writebacks: usize,
+    pub num_stale_hits: usize,
+    pub num_prefetches: usize,
+    pub num_useful_prefetches: usize,
+    pub num_failed_requests: usize,
+    pub num_failed_fetches: usize,
+    pub num_retries: usize,
+    pub total_fetch_cost: f64,
+}
+
 pub struct SimulationResult {
     pub results: Vec<RequestResult>,
+    /// Read requests that fail, completed when their fetch fails. They are not in `results`.
+    pub failed: Vec<RequestResult>,
     pub last_event_timestamp: TimeUnit,
     pub num_of_loads: usize,
+    pub writes: WriteStatistics,
+    /// Number of requests served with an expired object under stale-while-revalidate. They are hits in `results`.
+    pub num_stale_hits: usize,
+    /// Number of prefetches issued. They are included in `num_of_loads`.
+    pub num_prefetches: usize,
+    /// Number of prefetched objects that are requested (as hits or delayed hits) before they are evicted. Note that
+    /// [`compute_statistics`] counts the first request waiting for a prefetch as a miss.
+    pub num_useful_prefetches: usize,
+    pub failures: FailureStatistics,
+    /// Total cost of the fetches under [`SimulationOptions::fetch_cost`], including prefetches and revalidations.
+    pub total_fetch_cost: f64,
+    /// The events behind the counters above, with their timestamps. The writebacks at the end of the simulation are
+    /// at `last_event_timestamp`.
+    pub counted_events: Vec<(TimeUnit, CountedEvent)>,
+}
+
+impl SimulationResult {
+    /// The counters of the requests at or after `start`, e.g. the timestamp of the first request after the warmup.
+    /// A fetch is counted at the timestamp it is issued, and a writeback at the timestamp of the request that finds
+    /// the object evicted.
+    pub fn counters_since(&self, start: TimeUnit) -> Counters {
+        let mut counters = Counters {
+            num_failed_requests: self
+                .failed
+                .iter()
+                .filter(|r| r.request_timestamp >= start)
+                .count(),
+            ..Default::default()
+        };
+        for &(_, event) in self.counted_events.iter().filter(|&&(t, _)| t >= start) {
+            match event {
+                CountedEvent::Load {
+                    failed,
+                    num_retries,
+                    cost,
+                } => {
+                    counters.num_of_loads += 1;
+                    counters.num_failed_fetches += failed as usize;
+                    counters.num_retries += num_retries;
+                    counters.total_fetch_cost += cost;
+                }
+                CountedEvent::Prefetch => counters.num_prefetches += 1,
+                CountedEvent::UsefulPrefetch => counters.num_useful_prefetches += 1,
+                CountedEvent::StaleHit => counters.num_stale_hits += 1,
+                CountedEvent::Write => counters.num_writes += 1,
+                CountedEvent::Delete => counters.num_deletes += 1,
+                CountedEvent::Writeback => counters.num_writebacks += 1,
+            }
+        }
+        counters
+    }
+}
+
+/// Write `key` to the cache, expiring after `ttl` if given.
+fn write_object<C: Cache<u64, ()>>(
+    cache: &mut C,
+    cost_model: &CostModel,
+    key: u64,
+    timestamp: TimeUnit,
+    ttl: Option<TimeUnit>,
+) {
+    cache.set_fetch_cost(&key, cost_model.cost(key));
+    match ttl {
+        Some(ttl) => cache.write_with_ttl(key, (), timestamp, ttl),
+        None => cache.write(key, (), timestamp),
+    }
+}
+
+/// Remove `key` from `dirty` if the cache has evicted it, and return whether it was written back.
+fn write_back_if_evicted<C: Cache<u64, ()>>(
+    cache: &C,
+    dirty: &mut AHashSet<u64>,
+    key: &u64,
+) -> bool {
+    if dirty.contains(key) && !cache.contains(key) {
+        dirty.remove(key);
+        true
+    } else {
+        false
+    }
 }
 
 /// Run a delay-aware cache simulation, given a `caches.len()`-Way set associative cache and a sequence of requests. Return a vector of `RequestResult`.
 /// - `miss_penalty` is the time in nanoseconds it takes to fetch a missed request from the backing store.
 ///
+/// Requests can be [`proj_models::RequestEvent`]s (reads) or [`OpRequestEvent`]s with writes and deletes.
+///
 /// Return the request results, and the timestamp of the last event.
-pub fn run_simulation<C, I>(cache: &mut C, requests: I, miss_latency: TimeUnit) -> SimulationResult
+pub fn run_simulation<C, I, E>(
+    cache: &mut C,
+    requests: I,
+    miss_latency: TimeUnit,
+) -> SimulationResult
 where
     C: Cache<u64, ()>,
-    I: IntoIterator<Item = RequestEvent>,
+    I: IntoIterator<Item = E>,
+    E: Into<OpRequestEvent>,
 {
-    // Requests that are currently in fetching state.
-    let mut requests_in_progress: AHashMap<u64, Vec<TimeUnit>> = AHashMap::new();
-    // A monotonic queue of completion timestamps of requests.
-    let mut future_completions: VecDeque<(u64, TimeUnit)> = VecDeque::new();
+    run_simulation_with_options(cache, requests, miss_latency, &SimulationOptions::default())
+}
+
+/// Same as [`run_simulation`], with the behavior configured by `options`. See
+/// [`run_simulation_with_prefetcher`] for the handling of writes, expiry and prefetching.
+pub fn run_simulation_with_options<C, I, E>(
+    cache: &mut C,
+    requests: I,
+    miss_latency: TimeUnit,
+    options: &SimulationOptions,
+) -> SimulationResult
+where
+    C: Cache<u64, ()>,
+    I: IntoIterator<Item = E>,
+    E: Into<OpRequestEvent>,
+{
+    let mut prefetcher = options.prefetch.build();
+    run_simulation_with_prefetcher(cache, requests, miss_latency, options, &mut *prefetcher)
+}
+
+/// Same as [`run_simulation_with_options`], with a custom `prefetcher` instead of `options.prefetch`.
+///
+/// A write is an access of the object, and updates it in the cache if it is cached (or write-allocate is enabled).
+/// A delete invalidates the cached object. In both cases, fetches of the object in flight bring an outdated object,
+/// so their pending requests are completed but the object is not cached, and later reads of the object do not wait
+/// for them but issue a new fetch.
+///
+/// Objects written with a TTL expire. Under stale-while-revalidate, a request to an expired object is a hit and
+/// issues a fetch without waiting requests, unless the object is already being fetched.
+///
+/// After each read request, the keys returned by the prefetcher that are neither cached nor in flight are fetched
+/// without waiting requests.
+///
+/// A fetch may fail under `options.failures`, after its retries. The requests waiting for it fail, and the object is
+/// not cached. With negative caching, the failure of a fetch with waiting requests is cached, and reads of the object
+/// fail immediately until it expires or the object is written.
+pub fn run_simulation_with_prefetcher<C, I, E, P>(
+    cache: &mut C,
+    requests: I,
+    miss_latency: TimeUnit,
+    options: &SimulationOptions,
+    prefetcher: &mut P,
+) -> SimulationResult
+where
+    C: Cache<u64, ()>,
+    I: IntoIterator<Item = E>,
+    E: Into<OpRequestEvent>,
+    P: Prefetcher + ?Sized,
+{
+    // The fetches in flight, by `Fetch::seq`.
+    let mut pending_fetches: AHashMap<usize, PendingFetch> = AHashMap::new();
+    // The fetches in flight of each key that are not invalidated, which later reads wait for. With coalescing, a key
+    // has at most one.
+    let mut requests_in_progress: AHashMap<u64, Vec<usize>> = AHashMap::new();
+    let mut fetches = Fetches::new(miss_latency, options.failures.as_ref(), &options.fetch_cost);
     // A vector of request results.
     let mut results = Vec::new();
+    let mut failed = Vec::new();
+    // The keys with a cached failure, and when the failure expires.
+    let mut negative_cache: AHashMap<u64, TimeUnit> = AHashMap::new();
 
     // In case the request are occasionally out of order, we use timestamp = max(last_request_timestamp, request_timestamp) as the timestamp of the request.
     let mut last_request_timestamp = 0;
     let mut last_event_timestamp = 0;
-    let mut requests = requests.into_iter().peekable();
+    let mut requests = requests.into_iter().map(Into::into).peekable();
     let mut num_of_loads = 0;
+    // Dirty objects under write-back.
+    let mut dirty: AHashSet<u64> = AHashSet::new();
+    let mut writes = WriteStatistics::default();
+    let mut num_stale_hits = 0;
+    // Keys cached or in flight because of a prefetch, and not requested since.
+    let mut prefetched: AHashSet<u64> = AHashSet::new();
+    let mut num_prefetches = 0;
+    let mut num_useful_prefetches = 0;
+    // The events behind the counters other than the loads, which are counted by `fetches`.
+    let mut counted_events = Vec::new();
 
     loop {
-        let event = next_event(
-            &mut requests,
-            &mut future_completions,
-            &mut last_request_timestamp,
-        );
+        let event = next_event(&mut requests, &mut fetches, &mut last_request_timestamp);
         verbose!("{:?}", event);
         match event {
             Event::End => {
                 break;
             }
-            Event::Request(key, timestamp) => {
-                if cache.get(&key, timestamp).is_some() {
+            Event::Request(request) if request.op != Operation::Read => {
+                let OpRequestEvent { key, timestamp, .. } = request;
+                if write_back_if_evicted(cache, &mut dirty, &key) {
+                    writes.num_writebacks += 1;
+                    counted_events.push((timestamp, CountedEvent::Writeback));
+                }
+                // all fetches in flight are outdated, and later reads do not wait for them.
+                for seq in requests_in_progress.remove(&key).unwrap_or_default() {
+                    pending_fetches.get_mut(&seq).unwrap().invalidated = true;
+                }
+                prefetched.remove(&key);
+                negative_cache.remove(&key);
+
+                if request.op == Operation::Delete {
+                    writes.num_deletes += 1;
+                    counted_events.push((timestamp, CountedEvent::Delete));
+                    cache.invalidate(&key);
+                    dirty.remove(&key);
+                } else {
+                    writes.num_writes += 1;
+                    counted_events.push((timestamp, CountedEvent::Write));
+                    let cached = cache.get(&key, timestamp).is_some();
+                    let write_to_cache = cached || options.write_allocate;
+                    if write_to_cache {
+                        write_object(
+                            cache,
+                            &options.fetch_cost,
+                            key,
+                            timestamp,
+                            request.ttl.or(options.default_ttl),
+                        );
+                    }
+                    if write_to_cache && options.write_policy == WritePolicy::WriteBack {
+                        dirty.insert(key);
+                    } else {
+                        writes.num_backing_store_writes += 1;
+                        writes.total_write_latency += miss_latency as u128;
+                    }
+                }
+                last_event_timestamp = timestamp;
+            }
+            Event::Request(request) => {
+                let OpRequestEvent { key, timestamp, .. } = request;
+                if write_back_if_evicted(cache, &mut dirty, &key) {
+                    writes.num_writebacks += 1;
+                    counted_events.push((timestamp, CountedEvent::Writeback));
+                }
+                match negative_cache.get(&key) {
+                    Some(&until) if timestamp < until => {
+                        fetches.failures.num_negative_hits += 1;
+                        failed.push(RequestResult {
+                            key,
+                            request_timestamp: timestamp,
+                            completion_timestamp: timestamp,
+                        });
+                        last_event_timestamp = timestamp;
+                        continue;
+                    }
+                    Some(_) => {
+                        negative_cache.remove(&key);
+                    }
+                    None => {}
+                }
+                let stale = options.stale_while_revalidate && cache.is_expired(&key, timestamp);
+                let hit = if stale {
+                    cache.get_stale(&key, timestamp).is_some()
+                } else {
+                    cache.get(&key, timestamp).is_some()
+                };
+                if prefetched.remove(&key) && (hit || requests_in_progress.contains_key(&key)) {
+                    num_useful_prefetches += 1;
+                    counted_events.push((timestamp, CountedEvent::UsefulPrefetch));
+                }
+                if hit {
                     // the request is immediately fulfilled.
                     results.push(RequestResult {
                         key,
                         request_timestamp: timestamp,
                         completion_timestamp: timestamp,
                     });
+                    if stale {
+                        num_stale_hits += 1;
+                        counted_events.push((timestamp, CountedEvent::StaleHit));
+                        if options.miss_handling == MissHandling::ZeroLatency {
+                            if !fetches.fetch(key, timestamp).failed {
+                                write_object(
+                                    cache,
+                                    &options.fetch_cost,
+                                    key,
+                                    timestamp,
+                                    request.ttl.or(options.default_ttl),
+                                );
+                            }
+                            num_of_loads += 1;
+                        } else if !requests_in_progress.contains_key(&key) {
+                            // revalidate in the background.
+                            issue_fetch(
+                                &mut fetches,
+                                &mut pending_fetches,
+                                &mut requests_in_progress,
+                                key,
+                                timestamp,
+                                request.ttl,
+                                Vec::new(),
+                            );
+                        }
+                    }
                 } else {
-                    // check if the request is already in progress.
-                    if !requests_in_progress.contains_key(&key) {
-                        requests_in_progress.insert(key, Vec::new());
-                        future_completions.push_back((key, timestamp + miss_latency as TimeUnit));
+                    match options.miss_handling {
+                        MissHandling::Coalesce => {
+                            // check if the request is already in progress.
+                            match requests_in_progress.get(&key) {
+                                Some(seqs) => pending_fetches
+                                    .get_mut(&seqs[0])
+                                    .unwrap()
+                                    .requests
+                                    .push(timestamp),
+                                None => issue_fetch(
+                                    &mut fetches,
+                                    &mut pending_fetches,
+                                    &mut requests_in_progress,
+                                    key,
+                                    timestamp,
+                                    request.ttl,
+                                    vec![timestamp],
+                                ),
+                            }
+                        }
+                        MissHandling::NoCoalescing => issue_fetch(
+                            &mut fetches,
+                            &mut pending_fetches,
+                            &mut requests_in_progress,
+                            key,
+                            timestamp,
+                            request.ttl,
+                            vec![timestamp],
+                        ),
+                        MissHandling::ZeroLatency => {
+                            let outcome = fetches.fetch(key, timestamp);
+                            let result = RequestResult {
+                                key,
+                                request_timestamp: timestamp,
+                                completion_timestamp: outcome.completion,
+                            };
+                            if outcome.failed {
+                                fetches.cache_failure(&mut negative_cache, key, outcome.completion);
+                                failed.push(result);
+                            } else {
+                                write_object(
+                                    cache,
+                                    &options.fetch_cost,
+                                    key,
+                                    timestamp,
+                                    request.ttl.or(options.default_ttl),
+                                );
+                                results.push(result);
+                            }
+                            num_of_loads += 1;
+                        }
+                    }
+                }
+
+                for prefetch_key in prefetcher.on_request(key, timestamp, hit) {
+                    if cache.contains(&prefetch_key)
+                        || requests_in_progress.contains_key(&prefetch_key)
+                    {
+                        continue;
+                    }
+                    num_prefetches += 1;
+                    counted_events.push((timestamp, CountedEvent::Prefetch));
+                    prefetched.insert(prefetch_key);
+                    if options.miss_handling == MissHandling::ZeroLatency {
+                        if !fetches.fetch(prefetch_key, timestamp).failed {
+                            write_object(
+                                cache,
+                                &options.fetch_cost,
+                                prefetch_key,
+                                timestamp,
+                                options.default_ttl,
+                            );
+                        }
+                        num_of_loads += 1;
+                    } else {
+                        issue_fetch(
+                            &mut fetches,
+                            &mut pending_fetches,
+                            &mut requests_in_progress,
+                            prefetch_key,
+                            timestamp,
+                            None,
+                            Vec::new(),
+                        );
                     }
-                    requests_in_progress.get_mut(&key).unwrap().push(timestamp);
                 }
                 last_event_timestamp = timestamp;
             }
-            Event::Completion(key, timestamp) => {
-                debug_assert!(!cache.contains(&key), "{key:?} should not in the cache until the completion of the request, but it is.");
-                let pending_requests = requests_in_progress
-                    .remove(&key)
-                    .expect("pending requests for {key:?} should exist.");
-                debug_assert!(
-                    !pending_requests.is_empty(),
-                    "pending requests for {key:?} should not be empty."
-                );
+            Event::Completion(fetch) => {
+                let Fetch {
+                    key,
+                    completion: timestamp,
+                    seq,
+                    failed: fetch_failed,
+                } = fetch;
+                let PendingFetch {
+                    requests: waiting_requests,
+                    ttl,
+                    invalidated,
+                } = pending_fetches
+                    .remove(&seq)
+                    .expect("the fetch should be pending.");
+                if !invalidated {
+                    let seqs = requests_in_progress.get_mut(&key).unwrap();
+                    seqs.retain(|&s| s != seq);
+                    if seqs.is_empty() {
+                        requests_in_progress.remove(&key);
+                    }
+                }
+                // an expired object may be in the cache while it is revalidated. Without coalescing, the object may
+                // be in the cache already, written by an earlier fetch.
+                debug_assert!(invalidated || options.miss_handling == MissHandling::NoCoalescing || !cache.contains(&key) || cache.is_expired(&key, timestamp), "{key:?} should not in the cache until the completion of the request, but it is.");
 
-                cache.write(key, (), timestamp);
-                pending_requests.into_iter().for_each(|req_timestamp| {
-                    results.push(RequestResult {
+                if fetch_failed {
+                    if !waiting_requests.is_empty() {
+                        fetches.cache_failure(&mut negative_cache, key, timestamp);
+                    }
+                } else if invalidated {
+                    writes.num_invalidated_fetches += 1;
+                } else {
+                    write_object(
+                        cache,
+                        &options.fetch_cost,
                         key,
-                        request_timestamp: req_timestamp,
-                        completion_timestamp: timestamp,
-                    });
-                });
+                        timestamp,
+                        ttl.or(options.default_ttl),
+                    );
+                }
+                let completed = if fetch_failed {
+                    &mut failed
+                } else {
+                    &mut results
+                };
+                completed.extend(
+                    waiting_requests
+                        .into_iter()
+                        .map(|req_timestamp| RequestResult {
+                            key,
+                            request_timestamp: req_timestamp,
+                            completion_timestamp: timestamp,
+                        }),
+                );
                 last_event_timestamp = timestamp;
                 num_of_loads += 1;
             }
         }
     }
 
-    results.sort_by_key(|r| r.request_timestamp);
+    // ties are ordered by completion timestamp and key, so that the order does not depend on the order of the events
+    // at the same timestamp, e.g. across the sets simulated by `crate::parallel`.
+    results.sort_by_key(RequestResult::order);
+    failed.sort_by_key(RequestResult::order);
+    // the remaining dirty objects are evicted already or flushed at the end.
+    writes.num_writebacks += dirty.len();
+    counted_events.extend(
+        dirty
+            .iter()
+            .map(|_| (last_event_timestamp, CountedEvent::Writeback)),
+    );
+    counted_events.append(&mut fetches.loads);
 
     SimulationResult {
         results,
+        failed,
         last_event_timestamp,
         num_of_loads,
+        writes,
+        num_stale_hits,
+        num_prefetches,
+        num_useful_prefetches,
+        failures: fetches.failures,
+        total_fetch_cost: fetches.total_cost,
+        counted_events,
     }
 }
 
+/// Issue a fetch of `key` at `timestamp` for the waiting `requests`, with the TTL given by the request that issues
+/// it, if any.
+fn issue_fetch(
+    fetches: &mut Fetches<'_>,
+    pending_fetches: &mut AHashMap<usize, PendingFetch>,
+    requests_in_progress: &mut AHashMap<u64, Vec<usize>>,
+    key: u64,
+    timestamp: TimeUnit,
+    ttl: Option<TimeUnit>,
+    requests: Vec<TimeUnit>,
+) {
+    let seq = fetches.issue(key, timestamp);
+    pending_fetches.insert(
+        seq,
+        PendingFetch {
+            requests,
+            ttl,
+            invalidated: false,
+        },
+    );
+    requests_in_progress.entry(key).or_default().push(seq);
+}
+
 #[derive(Debug, Clone)]
 pub struct Statistics {
     pub total_latency: u128,
     pub average_latency: f64,
+    /// Number of requests that are served immediately.
+    pub num_hits: usize,
+    /// Number of requests that wait for a fetch issued by an earlier request.
+    pub num_delayed_hits: usize,
+    /// Number of requests that issue a fetch.
+    pub num_misses: usize,
     // pub latencies_by_timestamp_sorted: Vec<(TimeUnit, TimeUnit)>,
 }
 
@@ -195,9 +882,485 @@ pub fn compute_statistics(result: &[RequestResult]) -> Statistics {
 
     let average_latency = total_latency as f64 / result.len() as f64;
 
+    // requests waiting for the same fetch complete at the same time, so each fetch is identified by the key and the
+    // completion timestamp.
+    let mut fetches = AHashSet::new();
+    let mut num_hits = 0;
+    for r in result {
+        if r.completion_timestamp <= r.request_timestamp {
+            num_hits += 1;
+        } else {
+            fetches.insert((r.key, r.completion_timestamp));
+        }
+    }
+    let num_misses = fetches.len();
+
     Statistics {
         total_latency,
         average_latency,
+        num_hits,
+        num_delayed_hits: result.len() - num_hits - num_misses,
+        num_misses,
         // latencies_by_timestamp_sorted,
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use proj_models::RequestEvent;
+
+    use super::*;
+    use crate::cache::{lru::LRU, lru_mad::LRUMinAD};
+
+    const A: u64 = 0;
+    const B: u64 = 1;
+    const C: u64 = 2;
+    const D: u64 = 3;
+    const MISS_LATENCY: TimeUnit = 5;
+
+    /// A small trace with bursts (delayed hits), two requests at the same timestamp, and a key that LRU-MAD keeps
+    /// longer than LRU.
+    fn trace() -> Vec<RequestEvent> {
+        [
+            (A, 0),
+            (A, 2),
+            (A, 4),
+            (B, 10),
+            (C, 11),
+            (B, 12),
+            (D, 20),
+            (A, 21),
+            (B, 22),
+            (C, 23),
+            (A, 30),
+            (A, 30),
+            (B, 31),
+            (C, 40),
+            (D, 41),
+            (A, 42),
+            (B, 43),
+        ]
+        .iter()
+        .map(|&(key, timestamp)| RequestEvent { key, timestamp })
+        .collect()
+    }
+
+    fn simulate<C: Cache<u64, ()>>(mut cache: C, miss_handling: MissHandling) -> SimulationResult {
+        let options = SimulationOptions {
+            miss_handling,
+            ..Default::default()
+        };
+        run_simulation_with_options(&mut cache, trace(), MISS_LATENCY, &options)
+    }
+
+    /// Check the results, in the order they are returned, as `(key, request, completion)`.
+    fn assert_golden(
+        result: &SimulationResult,
+        expected: &[(u64, TimeUnit, TimeUnit)],
+        num_of_loads: usize,
+        last_event_timestamp: TimeUnit,
+    ) {
+        let actual = result
+            .results
+            .iter()
+            .map(|r| (r.key, r.request_timestamp, r.completion_timestamp))
+            .collect::<Vec<_>>();
+        assert_eq!(actual, expected);
+        assert_eq!(result.num_of_loads, num_of_loads);
+        assert_eq!(result.last_event_timestamp, last_event_timestamp);
+        assert!(result.failed.is_empty());
+    }
+
+    /// Simulate `requests` on an LRU cache of `capacity` objects, and return the result with the latency of every
+    /// request, in the order of the results.
+    fn simulate_lru<E: Into<OpRequestEvent>>(
+        capacity: usize,
+        requests: impl IntoIterator<Item = E>,
+        options: &SimulationOptions,
+    ) -> (SimulationResult, Vec<TimeUnit>) {
+        let result =
+            run_simulation_with_options(&mut LRU::new(capacity), requests, MISS_LATENCY, options);
+        let latencies = result
+            .results
+            .iter()
+            .map(|r| r.completion_timestamp - r.request_timestamp)
+            .collect();
+        (result, latencies)
+    }
+
+    #[test]
+    fn test_golden_coalesce() {
+        // objects are written on completion, so B still hits at 22 before D evicts it at 25
+        let expected = [
+            (A, 0, 5),
+            (A, 2, 5),
+            (A, 4, 5),
+            (B, 10, 15),
+            (C, 11, 16),
+            (B, 12, 15),
+            (D, 20, 25),
+            (A, 21, 26),
+            (B, 22, 22),
+            (C, 23, 23),
+            (A, 30, 30),
+            (A, 30, 30),
+            (B, 31, 36),
+            (C, 40, 45),
+            (D, 41, 46),
+            (A, 42, 42),
+            (B, 43, 43),
+        ];
+        let lru = simulate(LRU::new(2), MissHandling::Coalesce);
+        assert_golden(&lru, &expected, 8, 46);
+        let stats = compute_statistics(&lru.results);
+        assert_eq!(
+            (stats.num_hits, stats.num_delayed_hits, stats.num_misses),
+            (6, 3, 8)
+        );
+        assert_eq!(stats.total_latency, 8 * 5 + 3 + 1 + 3);
+        // LRU-MAD makes the same choices on this trace
+        assert_golden(
+            &simulate(LRUMinAD::new(2, MISS_LATENCY), MissHandling::Coalesce),
+            &expected,
+            8,
+            46,
+        );
+    }
+
+    #[test]
+    fn test_golden_no_coalescing() {
+        // every request of a burst waits for the full miss latency
+        let expected = [
+            (A, 0, 5),
+            (A, 2, 7),
+            (A, 4, 9),
+            (B, 10, 15),
+            (C, 11, 16),
+            (B, 12, 17),
+            (D, 20, 25),
+            (A, 21, 26),
+            (B, 22, 22),
+            (C, 23, 23),
+            (A, 30, 30),
+            (A, 30, 30),
+            (B, 31, 36),
+            (C, 40, 45),
+            (D, 41, 46),
+            (A, 42, 42),
+            (B, 43, 43),
+        ];
+        assert_golden(
+            &simulate(LRU::new(2), MissHandling::NoCoalescing),
+            &expected,
+            11,
+            46,
+        );
+        assert_golden(
+            &simulate(LRUMinAD::new(2, MISS_LATENCY), MissHandling::NoCoalescing),
+            &expected,
+            11,
+            46,
+        );
+    }
+
+    #[test]
+    fn test_golden_zero_latency() {
+        // objects are written immediately, so the second request of a burst hits, and the last event is the last
+        // request
+        let lru = [
+            (A, 0, 5),
+            (A, 2, 2),
+            (A, 4, 4),
+            (B, 10, 15),
+            (C, 11, 16),
+            (B, 12, 12),
+            (D, 20, 25),
+            (A, 21, 26),
+            (B, 22, 27),
+            (C, 23, 28),
+            // the hit is ordered before the miss at the same timestamp
+            (A, 30, 30),
+            (A, 30, 35),
+            (B, 31, 36),
+            (C, 40, 45),
+            (D, 41, 46),
+            (A, 42, 47),
+            (B, 43, 48),
+        ];
+        assert_golden(
+            &simulate(LRU::new(2), MissHandling::ZeroLatency),
+            &lru,
+            13,
+            43,
+        );
+
+        // B has a higher aggregate delay than C, so LRU-MAD evicts C instead of B at 30, and B hits at 31
+        let mut lru_mad = lru;
+        lru_mad[12] = (B, 31, 31);
+        assert_golden(
+            &simulate(LRUMinAD::new(2, MISS_LATENCY), MissHandling::ZeroLatency),
+            &lru_mad,
+            12,
+            43,
+        );
+    }
+
+    #[test]
+    fn test_miss_handling() {
+        let requests = [
+            (B, 0),
+            (A, 1),
+            (A, 4),
+            (A, 5),
+            (B, 7),
+            (C, 8),
+            (A, 9),
+            (B, 14),
+            (C, 15),
+            (A, 19),
+            (C, 20),
+        ]
+        .iter()
+        .map(|&(key, timestamp)| RequestEvent { key, timestamp })
+        .collect::<Vec<_>>();
+        let simulate = |miss_handling| {
+            let options = SimulationOptions {
+                miss_handling,
+                ..Default::default()
+            };
+            simulate_lru(2, requests.clone(), &options)
+        };
+
+        // the requests to A at 4 and 5 issue their own fetches instead of waiting for the fetch at 1
+        let (result, latencies) = simulate(MissHandling::NoCoalescing);
+        assert_eq!(latencies, [5, 5, 5, 5, 0, 5, 0, 5, 0, 0, 5]);
+        assert_eq!(result.num_of_loads, 7);
+        let stats = compute_statistics(&result.results);
+        assert_eq!(stats.num_delayed_hits, 0);
+        assert_eq!(stats.num_misses, 7);
+
+        // misses are written immediately, so A at 4 and 5 hit, but the cache churns more
+        let (result, latencies) = simulate(MissHandling::ZeroLatency);
+        assert_eq!(latencies, [5, 5, 0, 0, 0, 5, 5, 5, 5, 5, 0]);
+        assert_eq!(result.num_of_loads, 7);
+        let stats = compute_statistics(&result.results);
+        assert_eq!(stats.num_hits, 4);
+        assert_eq!(stats.num_delayed_hits, 0);
+    }
+
+    #[test]
+    fn test_ttl() {
+        let requests = [(A, 0), (A, 7), (A, 16), (A, 18), (A, 22)]
+            .iter()
+            .map(|&(key, timestamp)| RequestEvent { key, timestamp })
+            .collect::<Vec<_>>();
+        let simulate = |stale_while_revalidate| {
+            let options = SimulationOptions {
+                default_ttl: Some(10),
+                stale_while_revalidate,
+                ..Default::default()
+            };
+            simulate_lru(2, requests.clone(), &options)
+        };
+
+        // A expires at 15, so A at 16 misses and A at 18 waits for its fetch
+        let (result, latencies) = simulate(false);
+        assert_eq!(latencies, [5, 0, 5, 3, 0]);
+        assert_eq!(result.num_of_loads, 2);
+        assert_eq!(result.num_stale_hits, 0);
+
+        // A at 16 and 18 are served stale while A is revalidated
+        let (result, latencies) = simulate(true);
+        assert_eq!(latencies, [5, 0, 0, 0, 0]);
+        assert_eq!(result.num_of_loads, 2);
+        assert_eq!(result.num_stale_hits, 2);
+
+        // the TTL given by the request overrides the default
+        let requests = [(A, 0, Some(3)), (A, 7, None), (A, 9, None)]
+            .iter()
+            .map(|&(key, timestamp, ttl)| OpRequestEvent {
+                key,
+                timestamp,
+                op: Operation::Read,
+                ttl,
+            })
+            .collect::<Vec<_>>();
+        let options = SimulationOptions {
+            default_ttl: Some(10),
+            ..Default::default()
+        };
+        let (_, latencies) = simulate_lru(2, requests, &options);
+        assert_eq!(latencies, [5, 0, 5]);
+    }
+
+    #[test]
+    fn test_prefetch() {
+        let requests = [(0, 0), (1, 2), (2, 10), (3, 20)]
+            .iter()
+            .map(|&(key, timestamp)| RequestEvent { key, timestamp })
+            .collect::<Vec<_>>();
+        let simulate = |prefetch| {
+            let options = SimulationOptions {
+                prefetch,
+                ..Default::default()
+            };
+            simulate_lru(4, requests.clone(), &options)
+        };
+
+        let (result, latencies) = simulate(PrefetchConfig::None);
+        assert_eq!(latencies, [5, 5, 5, 5]);
+        assert_eq!(result.num_prefetches, 0);
+
+        // 1 is prefetched with 0 and is a delayed hit, 2 is prefetched on the miss of 1 and is a hit, and 4 is
+        // prefetched but never requested
+        let (result, latencies) = simulate(PrefetchConfig::NextN(1));
+        assert_eq!(latencies, [5, 3, 0, 5]);
+        assert_eq!(result.num_prefetches, 3);
+        assert_eq!(result.num_useful_prefetches, 2);
+        assert_eq!(result.num_of_loads, 5);
+        // the first request waiting for a prefetch looks like a miss in the results
+        let stats = compute_statistics(&result.results);
+        assert_eq!(stats.num_misses, 3);
+    }
+
+    #[test]
+    fn test_failures() {
+        let requests = [(A, 0), (B, 11), (B, 13), (C, 15), (B, 25), (B, 40)]
+            .iter()
+            .map(|&(key, timestamp)| RequestEvent { key, timestamp })
+            .collect::<Vec<_>>();
+        let simulate = |negative_ttl| {
+            let options = SimulationOptions {
+                failures: Some(FailureModel {
+                    outages: vec!["10-20".parse().unwrap()],
+                    max_retries: 1,
+                    initial_backoff: 2,
+                    negative_ttl,
+                    ..Default::default()
+                }),
+                ..Default::default()
+            };
+            simulate_lru(4, requests.clone(), &options)
+        };
+
+        // B at 11 is retried at 18, and both attempts fail in the outage. C at 15 succeeds on its retry at 22.
+        let (result, latencies) = simulate(Some(10));
+        assert_eq!(latencies, [5, 12, 5]);
+        assert_eq!(
+            result.failed,
+            [
+                RequestResult {
+                    key: B,
+                    request_timestamp: 11,
+                    completion_timestamp: 23
+                },
+                RequestResult {
+                    key: B,
+                    request_timestamp: 13,
+                    completion_timestamp: 23
+                },
+                RequestResult {
+                    key: B,
+                    request_timestamp: 25,
+                    completion_timestamp: 25
+                },
+            ]
+        );
+        assert_eq!(result.failures.num_failed_fetches, 1);
+        assert_eq!(result.failures.num_retries, 2);
+        assert_eq!(result.failures.num_negative_hits, 1);
+        assert_eq!(result.num_of_loads, 4);
+        // the fetches of A and B before 15 are not counted from 15
+        let counters = result.counters_since(0);
+        assert_eq!(counters.num_of_loads, 4);
+        assert_eq!(counters.num_failed_requests, 3);
+        assert_eq!(counters.num_failed_fetches, 1);
+        assert_eq!(counters.num_retries, 2);
+        assert_eq!(counters.total_fetch_cost, result.total_fetch_cost);
+        let counters = result.counters_since(15);
+        assert_eq!(counters.num_of_loads, 2);
+        assert_eq!(counters.num_failed_requests, 1);
+        assert_eq!(counters.num_failed_fetches, 0);
+        assert_eq!(counters.num_retries, 1);
+        assert_eq!(counters.total_fetch_cost, 2.0);
+
+        // without negative caching, B at 25 is fetched again
+        let (result, latencies) = simulate(None);
+        assert_eq!(latencies, [5, 12, 5, 0]);
+        assert_eq!(result.failed.len(), 2);
+        assert_eq!(result.failures.num_negative_hits, 0);
+    }
+
+    #[test]
+    fn test_writes() {
+        use Operation::*;
+        let requests = [
+            (A, 0, Write),
+            (A, 1, Read),
+            (B, 2, Write),
+            (C, 3, Read),
+            (A, 10, Read),
+        ]
+        .iter()
+        .map(|&(key, timestamp, op)| OpRequestEvent {
+            key,
+            timestamp,
+            op,
+            ttl: None,
+        })
+        .collect::<Vec<_>>();
+        let simulate = |write_policy, write_allocate| {
+            let options = SimulationOptions {
+                write_policy,
+                write_allocate,
+                ..Default::default()
+            };
+            simulate_lru(2, requests.clone(), &options)
+        };
+
+        // writes go around the cache, so A misses at 1 and hits at 10
+        let (result, latencies) = simulate(WritePolicy::WriteThrough, false);
+        assert_eq!(latencies, [5, 5, 0]);
+        assert_eq!(result.writes.num_writes, 2);
+        assert_eq!(result.writes.num_backing_store_writes, 2);
+        assert_eq!(result.writes.total_write_latency, 10);
+        assert_eq!(result.writes.num_writebacks, 0);
+
+        // A and B are dirty in the cache. C evicts A at 8, which is written back, and A evicts B at 15, which is
+        // written back at the end.
+        let (result, latencies) = simulate(WritePolicy::WriteBack, true);
+        assert_eq!(latencies, [0, 5, 5]);
+        assert_eq!(result.writes.num_backing_store_writes, 0);
+        assert_eq!(result.writes.total_write_latency, 0);
+        assert_eq!(result.writes.num_writebacks, 2);
+    }
+
+    #[test]
+    fn test_invalidation() {
+        use Operation::*;
+        let requests = [
+            (A, 0, Read),
+            (A, 2, Delete),
+            (A, 3, Read),
+            (A, 6, Read),
+            (A, 12, Delete),
+            (A, 13, Read),
+        ]
+        .iter()
+        .map(|&(key, timestamp, op)| OpRequestEvent {
+            key,
+            timestamp,
+            op,
+            ttl: None,
+        })
+        .collect::<Vec<_>>();
+        let (result, latencies) = simulate_lru(2, requests, &SimulationOptions::default());
+
+        // the fetch at 0 brings the deleted object, so A at 3 does not wait for it but issues a new fetch, which A at
+        // 6 waits for
+        assert_eq!(latencies, [5, 5, 2, 5]);
+        assert_eq!(result.num_of_loads, 3);
+        assert_eq!(result.writes.num_deletes, 2);
+        assert_eq!(result.writes.num_invalidated_fetches, 1);
+    }
+}
@@ -0,0 +1,328 @@
+//! Multi-tenant traces: the traces of several tenants merged into one stream, with the tenant of each request tagged
+//! in the high bits of its key.
+//!
+//! The keys of preprocessed traces are numbered from 0 in every trace, so the tag keeps the key spaces of the tenants
+//! apart. The tenants can share one cache, or each tenant can have its own partition (see [`PartitionedCache`]).
+
+use std::{
+    cmp::Reverse,
+    collections::BinaryHeap,
+    io::{Read, Write},
+};
+
+use proj_models::{codec::Codec, RequestEvent, RequestId, TimeUnit};
+
+use crate::{
+    cache::Cache,
+    checkpoint::{expect_param, expect_tag, write_tag},
+    simulator::{compute_statistics, RequestResult, Statistics},
+};
+
+/// Number of low bits of a tagged key holding the key in the trace of its tenant.
+pub const KEY_BITS: u32 = 48;
+
+/// Tag `key` from the trace of `tenant`.
+pub fn tag_key(tenant: usize, key: RequestId) -> RequestId {
+    assert!(
+        key < 1 << KEY_BITS,
+        "key {} of tenant {} is too large to be tagged",
+        key,
+        tenant
+    );
+    assert!(tenant < 1 << (64 - KEY_BITS), "too many tenants");
+    ((tenant as RequestId) << KEY_BITS) | key
+}
+
+/// The tenant of a tagged key.
+pub fn tenant_of(key: RequestId) -> usize {
+    (key >> KEY_BITS) as usize
+}
+
+/// The key of a tagged key in the trace of its tenant.
+pub fn untag_key(key: RequestId) -> RequestId {
+    key & ((1 << KEY_BITS) - 1)
+}
+
+/// How the timestamps of the trace of a tenant are moved into the merged stream.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TenantTransform {
+    /// Timestamp of the first request of the trace in the merged stream.
+    pub time_shift: TimeUnit,
+    /// Speed-up of the trace: 2 replays it at twice its request rate.
+    pub rate_scale: f64,
+}
+
+impl Default for TenantTransform {
+    fn default() -> Self {
+        Self {
+            time_shift: 0,
+            rate_scale: 1.0,
+        }
+    }
+}
+
+struct TenantTrace<I> {
+    requests: I,
+    transform: TenantTransform,
+    first_timestamp: Option<TimeUnit>,
+}
+
+impl<I: Iterator<Item = RequestEvent>> TenantTrace<I> {
+    /// The next request of the trace, with its timestamp in the merged stream and its untagged key.
+    fn next_request(&mut self) -> Option<RequestEvent> {
+        let request = self.requests.next()?;
+        let first = *self.first_timestamp.get_or_insert(request.timestamp);
+        let elapsed = request.timestamp.saturating_sub(first) as f64 / self.transform.rate_scale;
+        Some(RequestEvent {
+            key: request.key,
+            timestamp: self.transform.time_shift + elapsed as TimeUnit,
+        })
+    }
+}
+
+/// A time-ordered merge of the traces of several tenants, with tagged keys. Requests at the same timestamp are
+/// ordered by tenant. Each trace should be sorted by timestamp.
+pub struct MergedTenants<I> {
+    traces: Vec<TenantTrace<I>>,
+    /// The next request of each tenant, as `(timestamp, tenant, key)`.
+    heads: BinaryHeap<Reverse<(TimeUnit, usize, RequestId)>>,
+}
+
+impl<I: Iterator<Item = RequestEvent>> MergedTenants<I> {
+    /// Merge the traces, tenant `i` being the `i`-th trace.
+    pub fn new(traces: impl IntoIterator<Item = (I, TenantTransform)>) -> Self {
+        let mut traces = traces
+            .into_iter()
+            .map(|(requests, transform)| {
+                assert!(
+                    transform.rate_scale > 0.0,
+                    "the rate scale should be positive"
+                );
+                TenantTrace {
+                    requests,
+                    transform,
+                    first_timestamp: None,
+                }
+            })
+            .collect::<Vec<_>>();
+        let heads = traces
+            .iter_mut()
+            .enumerate()
+            .filter_map(|(tenant, trace)| {
+                let request = trace.next_request()?;
+                Some(Reverse((request.timestamp, tenant, request.key)))
+            })
+            .collect();
+        Self { traces, heads }
+    }
+}
+
+impl<I: Iterator<Item = RequestEvent>> Iterator for MergedTenants<I> {
+    type Item = RequestEvent;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let Reverse((timestamp, tenant, key)) = self.heads.pop()?;
+        if let Some(request) = self.traces[tenant].next_request() {
+            self.heads
+                .push(Reverse((request.timestamp, tenant, request.key)));
+        }
+        Some(RequestEvent {
+            key: tag_key(tenant, key),
+            timestamp,
+        })
+    }
+}
+
+/// Statistics of the requests of one tenant.
+#[derive(Debug, Clone)]
+pub struct TenantStatistics {
+    pub tenant: usize,
+    pub num_requests: usize,
+    pub stats: Statistics,
+}
+
+/// Split simulation results of tagged keys by tenant, for each of `num_tenants` tenants.
+pub fn tenant_statistics(results: &[RequestResult], num_tenants: usize) -> Vec<TenantStatistics> {
+    let mut by_tenant = vec![Vec::new(); num_tenants];
+    for r in results {
+        by_tenant[tenant_of(r.key)].push(r.clone());
+    }
+    by_tenant
+        .into_iter()
+        .enumerate()
+        .map(|(tenant, results)| TenantStatistics {
+            tenant,
+            num_requests: results.len(),
+            stats: compute_statistics(&results),
+        })
+        .collect()
+}
+
+/// A cache partitioned by tenant: the requests of each tenant are served by its own partition only, so tenants do not
+/// evict each other's objects.
+pub struct PartitionedCache<C> {
+    partitions: Vec<C>,
+}
+
+impl<C> PartitionedCache<C> {
+    /// A cache with one partition per tenant, tenant `i` being served by the `i`-th partition.
+    pub fn new(partitions: Vec<C>) -> Self {
+        assert!(!partitions.is_empty(), "no partitions");
+        Self { partitions }
+    }
+
+    pub fn partitions(&self) -> &[C] {
+        &self.partitions
+    }
+
+    fn partition(&self, key: &RequestId) -> &C {
+        let tenant = tenant_of(*key);
+        self.partitions
+            .get(tenant)
+            .unwrap_or_else(|| panic!("no partition for tenant {}", tenant))
+    }
+
+    fn partition_mut(&mut self, key: &RequestId) -> &mut C {
+        let tenant = tenant_of(*key);
+        self.partitions
+            .get_mut(tenant)
+            .unwrap_or_else(|| panic!("no partition for tenant {}", tenant))
+    }
+}
+
+impl<V, C: Cache<RequestId, V>> Cache<RequestId, V> for PartitionedCache<C> {
+    const NAME: &'static str = C::NAME;
+
+    fn write(&mut self, key: RequestId, value: V, timestamp: TimeUnit) {
+        self.partition_mut(&key).write(key, value, timestamp)
+    }
+
+    fn write_with_ttl(&mut self, key: RequestId, value: V, timestamp: TimeUnit, ttl: TimeUnit) {
+        self.partition_mut(&key)
+            .write_with_ttl(key, value, timestamp, ttl)
+    }
+
+    fn get(&mut self, key: &RequestId, timestamp: TimeUnit) -> Option<&V> {
+        self.partition_mut(key).get(key, timestamp)
+    }
+
+    fn get_stale(&mut self, key: &RequestId, timestamp: TimeUnit) -> Option<&V> {
+        self.partition_mut(key).get_stale(key, timestamp)
+    }
+
+    fn is_expired(&self, key: &RequestId, timestamp: TimeUnit) -> bool {
+        self.partition(key).is_expired(key, timestamp)
+    }
+
+    fn contains(&self, key: &RequestId) -> bool {
+        self.partition(key).contains(key)
+    }
+
+    fn invalidate(&mut self, key: &RequestId) -> bool {
+        self.partition_mut(key).invalidate(key)
+    }
+
+    fn set_fetch_cost(&mut self, key: &RequestId, cost: f64) {
+        self.partition_mut(key).set_fetch_cost(key, cost)
+    }
+
+    fn save_state(&self, writer: &mut dyn Write) -> std::io::Result<()>
+    where
+        V: Codec,
+    {
+        write_tag(writer, "partitioned")?;
+        (self.partitions.len() as u64).to_bytes(&mut *writer)?;
+        for partition in &self.partitions {
+            partition.save_state(writer)?;
+        }
+        Ok(())
+    }
+
+    fn load_state(&mut self, reader: &mut dyn Read) -> std::io::Result<()>
+    where
+        V: Codec<Deserialized = V>,
+    {
+        expect_tag(reader, "partitioned")?;
+        expect_param(reader, "number of partitions", self.partitions.len() as u64)?;
+        for partition in &mut self.partitions {
+            partition.load_state(reader)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{cache::lru::LRU, simulator::run_simulation};
+
+    fn trace(keys: &[RequestId], irt: TimeUnit, start: TimeUnit) -> Vec<RequestEvent> {
+        keys.iter()
+            .enumerate()
+            .map(|(i, &key)| RequestEvent {
+                key,
+                timestamp: start + i as TimeUnit * irt,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_merge_tenant_traces() {
+        let merged = MergedTenants::new([
+            (
+                trace(&[0, 1, 2], 10, 100).into_iter(),
+                TenantTransform::default(),
+            ),
+            (
+                trace(&[0, 1, 2], 10, 5000).into_iter(),
+                TenantTransform {
+                    time_shift: 5,
+                    rate_scale: 2.0,
+                },
+            ),
+        ])
+        .map(|r| (tenant_of(r.key), untag_key(r.key), r.timestamp))
+        .collect::<Vec<_>>();
+        assert_eq!(
+            merged,
+            vec![
+                (0, 0, 0),
+                (1, 0, 5),
+                (0, 1, 10),
+                (1, 1, 10),
+                (1, 2, 15),
+                (0, 2, 20)
+            ]
+        );
+    }
+
+    #[test]
+    fn test_shared_and_partitioned_caches() {
+        // tenant 0 reuses three keys, and tenant 1 scans over new keys
+        let merged = MergedTenants::new([
+            (
+                trace(&[0, 1, 2].repeat(14), 10, 0).into_iter(),
+                TenantTransform::default(),
+            ),
+            (
+                trace(&(0..40).collect::<Vec<_>>(), 10, 0).into_iter(),
+                TenantTransform::default(),
+            ),
+        ])
+        .collect::<Vec<_>>();
+
+        let mut shared = LRU::new(4);
+        let result = run_simulation(&mut shared, merged.clone(), 5);
+        let shared_stats = tenant_statistics(&result.results, 2);
+        assert_eq!(shared_stats[0].num_requests, 42);
+        assert_eq!(shared_stats[0].stats.num_misses, 42);
+        assert_eq!(shared_stats[1].stats.num_misses, 40);
+
+        let mut partitioned = PartitionedCache::new(vec![LRU::new(3), LRU::new(1)]);
+        let result = run_simulation(&mut partitioned, merged, 5);
+        let partitioned_stats = tenant_statistics(&result.results, 2);
+        assert_eq!(partitioned_stats[0].stats.num_misses, 3);
+        assert_eq!(partitioned_stats[0].stats.num_hits, 39);
+        assert_eq!(partitioned_stats[1].stats.num_misses, 40);
+    }
+}
@@ -0,0 +1,281 @@
+//! Warmup periods, excluded from the statistics: a number of requests, a duration of trace time, or until the hit
+//! ratio reaches a steady state.
+
+use std::iter::Peekable;
+
+use proj_models::{RequestEvent, TimeUnit};
+
+use crate::{
+    cache::Cache,
+    simulator::{run_simulation, RequestResult},
+};
+
+/// How the warmup is defined, e.g. for the command line: `<n>` requests, a duration with a unit (e.g. `30s`), or
+/// `auto[:<window>[:<tolerance>]]` (by default, windows of 10000 requests and a tolerance of 0.01).
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum WarmupConfig {
+    Requests(usize),
+    /// The requests within this duration from the first request.
+    Duration(TimeUnit),
+    /// Until the hit ratio of a window of `window` requests differs from the previous window by at most `tolerance`.
+    /// The stable window is the last warmup window.
+    SteadyState {
+        window: usize,
+        tolerance: f64,
+    },
+}
+
+impl Default for WarmupConfig {
+    fn default() -> Self {
+        Self::Requests(0)
+    }
+}
+
+/// The end of the warmup.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct WarmupBoundary {
+    /// Number of warmup requests.
+    pub num_requests: usize,
+    /// Timestamp of the first request after the warmup, or `None` if all requests are warmup requests.
+    pub end_timestamp: Option<TimeUnit>,
+}
+
+/// Compares the hit ratios of consecutive windows.
+struct SteadyStateDetector {
+    tolerance: f64,
+    last_hit_ratio: Option<f64>,
+}
+
+impl SteadyStateDetector {
+    fn new(tolerance: f64) -> Self {
+        Self {
+            tolerance,
+            last_hit_ratio: None,
+        }
+    }
+
+    /// Observe the next window, and return whether its hit ratio is stable.
+    fn is_stable(&mut self, window: &[RequestResult]) -> bool {
+        let num_hits = window
+            .iter()
+            .filter(|r| r.completion_timestamp <= r.request_timestamp)
+            .count();
+        let hit_ratio = num_hits as f64 / window.len() as f64;
+        let stable = self
+            .last_hit_ratio
+            .is_some_and(|last| (hit_ratio - last).abs() <= self.tolerance);
+        self.last_hit_ratio = Some(hit_ratio);
+        stable
+    }
+}
+
+impl WarmupConfig {
+    /// The number of warmup requests, if the warmup is given as a number of requests.
+    pub fn num_requests(&self) -> Option<usize> {
+        match *self {
+            Self::Requests(n) => Some(n),
+            _ => None,
+        }
+    }
+
+    /// The end of the warmup in simulation results sorted by request timestamps.
+    pub fn boundary(&self, results: &[RequestResult]) -> WarmupBoundary {
+        let num_requests = match *self {
+            Self::Requests(n) => n.min(results.len()),
+            Self::Duration(duration) => match results.first() {
+                Some(first) => {
+                    let end = first.request_timestamp + duration;
+                    results.partition_point(|r| r.request_timestamp < end)
+                }
+                None => 0,
+            },
+            Self::SteadyState { window, tolerance } => {
+                let mut detector = SteadyStateDetector::new(tolerance);
+                results
+                    .chunks(window)
+                    .position(|w| detector.is_stable(w))
+                    .map_or(results.len(), |i| ((i + 1) * window).min(results.len()))
+            }
+        };
+        WarmupBoundary {
+            num_requests,
+            end_timestamp: results.get(num_requests).map(|r| r.request_timestamp),
+        }
+    }
+
+    /// Simulate the warmup requests from `requests` on `cache`, leaving the requests after the warmup in `requests`.
+    /// The fetches in flight complete at the end of the warmup (window by window with
+    /// [`WarmupConfig::SteadyState`]).
+    ///
+    /// Return the end of the warmup, and the timestamp of the last simulated event.
+    pub fn simulate<C, I>(
+        &self,
+        cache: &mut C,
+        requests: &mut Peekable<I>,
+        miss_latency: TimeUnit,
+    ) -> (WarmupBoundary, TimeUnit)
+    where
+        C: Cache<u64, ()>,
+        I: Iterator<Item = RequestEvent>,
+    {
+        let mut num_requests = 0;
+        let mut last_event_timestamp = 0;
+        let mut simulate = |cache: &mut C, warmup_requests: Vec<RequestEvent>| {
+            num_requests += warmup_requests.len();
+            let result = run_simulation(cache, warmup_requests, miss_latency);
+            last_event_timestamp = last_event_timestamp.max(result.last_event_timestamp);
+            result.results
+        };
+        match *self {
+            Self::Requests(n) => {
+                simulate(cache, requests.by_ref().take(n).collect());
+            }
+            Self::Duration(duration) => {
+                if let Some(first) = requests.peek() {
+                    let end = first.timestamp + duration;
+                    let warmup_requests =
+                        std::iter::from_fn(|| requests.next_if(|r| r.timestamp < end));
+                    simulate(cache, warmup_requests.collect());
+                }
+            }
+            Self::SteadyState { window, tolerance } => {
+                let mut detector = SteadyStateDetector::new(tolerance);
+                loop {
+                    let warmup_requests = requests.by_ref().take(window).collect::<Vec<_>>();
+                    if warmup_requests.is_empty() {
+                        break;
+                    }
+                    let results = simulate(cache, warmup_requests);
+                    if detector.is_stable(&results) {
+                        break;
+                    }
+                }
+            }
+        }
+        let boundary = WarmupBoundary {
+            num_requests,
+            end_timestamp: requests.peek().map(|r| r.timestamp),
+        };
+        (boundary, last_event_timestamp)
+    }
+}
+
+impl std::fmt::Display for WarmupConfig {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Requests(n) => write!(f, "{}", n),
+            Self::Duration(duration) => write!(f, "{}ns", duration),
+            Self::SteadyState { window, tolerance } => write!(f, "auto:{}:{}", window, tolerance),
+        }
+    }
+}
+
+impl std::str::FromStr for WarmupConfig {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if let Ok(n) = s.parse() {
+            return Ok(Self::Requests(n));
+        }
+        let mut parts = s.split(':');
+        if parts.next() == Some("auto") {
+            let window = match parts.next() {
+                Some(window) => match window.parse::<usize>() {
+                    Ok(window) if window > 0 => window,
+                    _ => return Err(format!("Invalid warmup window: {}", window)),
+                },
+                None => 10000,
+            };
+            let tolerance = match parts.next() {
+                Some(tolerance) => match tolerance.parse::<f64>() {
+                    Ok(tolerance) if tolerance >= 0.0 => tolerance,
+                    _ => return Err(format!("Invalid warmup tolerance: {}", tolerance)),
+                },
+                None => 0.01,
+            };
+            if parts.next().is_some() {
+                return Err(format!("Invalid warmup: {}", s));
+            }
+            return Ok(Self::SteadyState { window, tolerance });
+        }
+        crate::parse_time_unit(s).map(Self::Duration).map_err(|_| {
+            format!(
+                "Invalid warmup: {} (expected <n>, <duration> or auto[:<window>[:<tolerance>]])",
+                s
+            )
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_warmup_config() {
+        for s in ["100", "30000000000ns", "auto:100:0.05"] {
+            assert_eq!(s.parse::<WarmupConfig>().unwrap().to_string(), s);
+        }
+        assert_eq!("30s".parse(), Ok(WarmupConfig::Duration(30_000_000_000)));
+        assert_eq!(
+            "auto".parse(),
+            Ok(WarmupConfig::SteadyState {
+                window: 10000,
+                tolerance: 0.01
+            })
+        );
+        assert!("auto:0".parse::<WarmupConfig>().is_err());
+        assert!("soon".parse::<WarmupConfig>().is_err());
+    }
+
+    #[test]
+    fn test_warmup_boundary() {
+        // the first request of each key misses
+        let keys = [0, 1, 0, 2, 0, 1, 2, 0, 1, 2, 0, 1];
+        let results = keys
+            .iter()
+            .enumerate()
+            .map(|(i, &key)| {
+                let timestamp = i as TimeUnit * 10;
+                let miss = !keys[..i].contains(&key);
+                RequestResult {
+                    key,
+                    request_timestamp: timestamp,
+                    completion_timestamp: if miss { timestamp + 5 } else { timestamp },
+                }
+            })
+            .collect::<Vec<_>>();
+        let boundary = |config: &str| config.parse::<WarmupConfig>().unwrap().boundary(&results);
+        assert_eq!(
+            boundary("3"),
+            WarmupBoundary {
+                num_requests: 3,
+                end_timestamp: Some(30)
+            }
+        );
+        assert_eq!(boundary("45ns").num_requests, 5);
+        // hit ratios 0, 0.5, 1, 1
+        assert_eq!(boundary("auto:2:0.1").num_requests, 8);
+        assert_eq!(
+            boundary("100"),
+            WarmupBoundary {
+                num_requests: 12,
+                end_timestamp: None
+            }
+        );
+
+        // simulating the warmup ends at the same boundary
+        let mut requests = results
+            .iter()
+            .map(|r| RequestEvent {
+                key: r.key,
+                timestamp: r.request_timestamp,
+            })
+            .peekable();
+        let mut cache = crate::cache::lru::LRU::new(4);
+        let config = "auto:2:0.1".parse::<WarmupConfig>().unwrap();
+        let (boundary, _) = config.simulate(&mut cache, &mut requests, 5);
+        assert_eq!(boundary.num_requests, 8);
+        assert_eq!(requests.next().map(|r| r.timestamp), Some(80));
+    }
+}
@@ -0,0 +1,15 @@
+//! Embed the git revision in the binary, so that experiment reports can be traced back to the code.
+
+fn main() {
+    let revision = std::process::Command::new("git")
+        .args(["describe", "--always", "--dirty", "--abbrev=12"])
+        .output()
+        .ok()
+        .filter(|output| output.status.success())
+        .map(|output| String::from_utf8_lossy(&output.stdout).trim().to_string());
+    if let Some(revision) = revision {
+        println!("cargo:rustc-env=GIT_REVISION={}", revision);
+    }
+    println!("cargo:rerun-if-changed=../.git/HEAD");
+    println!("cargo:rerun-if-changed=../.git/refs/heads");
+}
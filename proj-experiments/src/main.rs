@@ -1,11 +1,39 @@
-use std::fmt::Display;
+mod report;
+mod sweep;
+
+use std::{io::Write, time::Instant};
 
 use clap::{Parser, Subcommand};
 use proj_cache_sim::{
-    cache::{construct_k_way_cache, lru::LRU, lru_mad::LRUMinAD},
+    arrival::ArrivalTransform,
+    cache::{
+        construct_k_way_cache_with_mapping,
+        mapping::SetMapping,
+        registry::{
+            construct_policy, construct_single_policy, parse_policy_name, PolicyParams,
+            DEFAULT_COST_COEFFICIENT,
+        },
+    },
+    checkpoint::{load_checkpoint, save_checkpoint, CheckpointHeader},
+    cluster::{check_schedule, churn_impact, CacheCluster, ClusterEvent},
+    cost::CostModel,
     get_time_string, heuristics,
-    io::load_events_file,
-    simulator::{compute_statistics, run_simulation},
+    io::{load_events_file, load_op_events_file},
+    origin::{FailureModel, Outage},
+    parallel::run_parallel_simulation,
+    prefetch::PrefetchConfig,
+    sampling::{scale_capacity, SpatialSampler},
+    simulator::{
+        run_simulation, run_simulation_with_options, MissHandling, SimulationOptions,
+        SimulationResult, WritePolicy,
+    },
+    tenant::{tenant_statistics, MergedTenants, PartitionedCache, TenantTransform},
+    warmup::{WarmupBoundary, WarmupConfig},
+};
+use proj_models::{OpRequestEvent, Operation, RequestEvent};
+use rayon::prelude::*;
+use report::{
+    CacheConfig, ExperimentReport, OutputFormat, PolicyStats, TraceMetadata, VersionInfo,
 };
 
 fn print_irt_stats(irt_stat: &heuristics::TimingStatistics) {
@@ -30,7 +58,84 @@ fn print_irt_stats(irt_stat: &heuristics::TimingStatistics) {
     }
 }
 
-fn analyze_event(event_path: &str) {
+/// Print the histogram, with the cumulative percentage of values below the upper bound of each bucket.
+fn print_log2_histogram(name: &str, value_name: &str, histogram: &heuristics::Log2Histogram) {
+    println!("{} distribution:", name);
+    let mut cumulative = 0;
+    for (bucket, &count) in histogram.buckets.iter().enumerate() {
+        cumulative += count;
+        if count == 0 {
+            continue;
+        }
+        let (lower, upper) = heuristics::Log2Histogram::bucket_range(bucket);
+        println!(
+            "  {} <= {} < {}: {} ({:.2}%, cumulative {:.2}%)",
+            lower,
+            value_name,
+            upper,
+            count,
+            count as f64 / histogram.count as f64 * 100.0,
+            cumulative as f64 / histogram.count as f64 * 100.0
+        );
+    }
+    if histogram.infinite > 0 {
+        println!(
+            "  first access: {} ({:.2}%)",
+            histogram.infinite,
+            histogram.infinite as f64 / histogram.count as f64 * 100.0
+        );
+    }
+}
+
+fn print_delayed_hit_potential(potential: &heuristics::DelayedHitPotential) {
+    println!(
+        "Delayed-hit potential (miss latency: {}, nothing cached):",
+        get_time_string(potential.miss_latency as u128)
+    );
+    println!(
+        "  misses: {} ({:.2}%)",
+        potential.num_windows,
+        potential.num_windows as f64 / potential.num_requests as f64 * 100.0
+    );
+    println!(
+        "  delayed hits: {} ({:.2}%)",
+        potential.num_delayed_hits,
+        potential.num_delayed_hits as f64 / potential.num_requests as f64 * 100.0
+    );
+    println!(
+        "  mean delayed-hit latency: {}",
+        get_time_string(potential.mean_delayed_hit_latency() as u128)
+    );
+    println!(
+        "  latency from delayed hits (upper bound): {} ({:.2}% of total latency)",
+        get_time_string(potential.delayed_hit_latency_total),
+        potential.delayed_hit_latency_fraction() * 100.0
+    );
+    print_log2_histogram(
+        "Delayed hits per miss window",
+        "size",
+        &potential.burst_sizes,
+    );
+}
+
+fn print_popularity_stats(stats: &heuristics::PopularityStatistics) {
+    println!("Number of unique keys: {}", stats.num_unique_keys);
+    println!(
+        "One-hit wonders: {} ({:.2}% of keys)",
+        stats.num_one_hit_wonders,
+        stats.one_hit_wonder_ratio() * 100.0
+    );
+    for (n, share) in stats
+        .top_shares
+        .iter()
+        .filter(|(n, _)| *n < stats.num_unique_keys)
+    {
+        println!("Top {} keys: {:.2}% of requests", n, share * 100.0);
+    }
+    println!("Zipf alpha (MLE): {:.4}", stats.zipf_alpha);
+}
+
+fn analyze_event(event_path: &str, cache_counts: usize, miss_latency: Option<u64>) {
     let requests = load_events_file(event_path).collect::<Vec<_>>();
     let maximum_active_objects = heuristics::maximum_active_objects(&requests);
     let irt_stat = heuristics::get_irt(&requests);
@@ -38,6 +143,21 @@ fn analyze_event(event_path: &str) {
     println!("Number of requests: {}", requests.len());
     println!("Maximum active objects: {}", maximum_active_objects);
     print_irt_stats(&irt_stat);
+    let top_ns = [10, 100, 1000, 10000];
+    print_popularity_stats(&heuristics::popularity_statistics(&requests, &top_ns));
+    print_log2_histogram(
+        &format!("LRU stack distance (per set, k = {})", cache_counts),
+        "d",
+        &heuristics::lru_stack_distance_histogram(&requests, cache_counts),
+    );
+    print_log2_histogram(
+        "Reuse time (in requests)",
+        "d",
+        &heuristics::reuse_time_histogram(&requests),
+    );
+    if let Some(miss_latency) = miss_latency {
+        print_delayed_hit_potential(&heuristics::delayed_hit_potential(&requests, miss_latency));
+    }
 
     let suggested_total_cache_size = (maximum_active_objects as f64 * 0.05).ceil() as usize;
     println!(
@@ -46,136 +166,555 @@ fn analyze_event(event_path: &str) {
     );
 }
 
-#[derive(Debug, Clone)]
-struct ExperimentResult {
-    total_latency_lru: u128,
-    average_latency_lru: f64,
-    total_latency_lru_mad: u128,
-    average_latency_lru_mad: f64,
-    num_loads_lru: usize,
-    num_loads_lru_mad: usize,
-    improvement: f64,
+/// Take the requests of `requests` up to the `num_reads`-th read, since a warmup given as a number of requests counts
+/// the results of the reads only.
+fn warmup_requests<I: Iterator<Item = OpRequestEvent>>(
+    requests: &mut I,
+    mut num_reads: usize,
+) -> impl Iterator<Item = OpRequestEvent> + '_ {
+    std::iter::from_fn(move || {
+        if num_reads == 0 {
+            return None;
+        }
+        let request = requests.next()?;
+        if request.op == Operation::Read {
+            num_reads -= 1;
+        }
+        Some(request)
+    })
+}
+
+/// Run an experiment with the given parameters.
+/// - `requests_path`: the path to the file containing the requests, a `.opevents` file for traces with writes
+/// - `arrival`: how the arrival times of the requests are transformed, before the warmup
+/// - `policies`: the names of the policies to compare. The first one is the baseline.
+/// - `cache_counts`: the number of caches in the cache hierarchy
+/// - `cache_capacity`: the capacity of each cache
+/// - `set_mapping`: how keys are mapped to the caches
+/// - `miss_latency`: the latency of a cache miss
+/// - `cost_coefficient`: the weight of fetch costs in cost-aware policies
+/// - `miss_handlings`: the miss models to simulate each policy with, e.g. to compare the delayed-hits view with the
+///   classic zero-latency view
+/// - `options`: the other simulation options, e.g. how writes are handled
+/// - `warmup`: the warmup period, resolved on the results of the baseline. The warmup requests are not included in
+///   the statistics.
+/// - `warm_state`: where to save the warmed caches, or to restore them from instead of simulating the warmup
+/// - `parallel`: simulate the sets in parallel (see [`run_parallel_simulation`]), without a warm state
+#[allow(clippy::too_many_arguments)]
+fn run_experiment(
+    requests_path: &str,
+    arrival: ArrivalTransform,
+    policies: &[&str],
+    miss_handlings: &[MissHandling],
+    options: &SimulationOptions,
     cache_counts: usize,
     cache_capacity: usize,
+    set_mapping: SetMapping,
+    miss_latency: u64,
+    cost_coefficient: f64,
+    warmup: WarmupConfig,
+    max_requests: Option<usize>,
+    warm_state: Option<&WarmState>,
+    parallel: bool,
+) -> ExperimentReport {
+    let load_requests = || {
+        arrival.apply(load_op_events_file(requests_path).take(max_requests.unwrap_or(usize::MAX)))
+    };
+
+    // // to simulate the toy cdn deployment (after dummy warmup, the CDN nodes waits for all requests to be fulfilled
+    // // before playing the trace), replace `load_requests()` below with:
+    // let mut requests = load_requests();
+    // let requests_a = requests
+    //     .by_ref()
+    //     .take(warmup)
+    //     .collect::<Vec<_>>()
+    //     .into_iter();
+    // let requests_b = requests.map(|mut req| {
+    //     req.timestamp += miss_latency;
+    //     req
+    // });
+    // requests_a.chain(requests_b)
+
+    let params = PolicyParams {
+        cache_counts,
+        cache_capacity,
+        estimated_miss_latency: miss_latency,
+        set_mapping,
+        cost_coefficient,
+    };
+    let mut trace = None;
+    let mut warmup_boundary = None;
+    let policies = policies
+        .iter()
+        .flat_map(|policy| miss_handlings.iter().map(move |&m| (policy, m)))
+        .map(|(policy, miss_handling)| {
+            // the sets are boxed individually so that the parallel simulation can split the cache
+            let mut cache = construct_k_way_cache_with_mapping(cache_counts, set_mapping, |_| {
+                construct_single_policy(policy, &params).unwrap()
+            });
+            let options = SimulationOptions {
+                miss_handling,
+                ..options.clone()
+            };
+            let mut requests = load_requests();
+            let (result, warmup_in_results) = match warm_state {
+                None => {
+                    let result = if parallel {
+                        run_parallel_simulation(&mut cache, requests, miss_latency, &options)
+                    } else {
+                        run_simulation_with_options(&mut cache, requests, miss_latency, &options)
+                    };
+                    // the boundary of the baseline is used for all policies
+                    let boundary =
+                        *warmup_boundary.get_or_insert_with(|| warmup.boundary(&result.results));
+                    (result, boundary.num_requests)
+                }
+                Some(warm_state) => {
+                    let num_warmup_requests = warmup
+                        .num_requests()
+                        .expect("a warm state needs a warmup given as a number of requests");
+                    let path = warm_state.path(policy, miss_handling);
+                    match warm_state {
+                        WarmState::Save(_) => {
+                            let warm = run_simulation_with_options(
+                                &mut cache,
+                                warmup_requests(&mut requests, num_warmup_requests),
+                                miss_latency,
+                                &options,
+                            );
+                            let header = CheckpointHeader {
+                                num_requests: num_warmup_requests as u64,
+                                last_event_timestamp: warm.last_event_timestamp,
+                            };
+                            save_checkpoint(&path, &cache, &header)
+                                .unwrap_or_else(|e| panic!("failed to save {}: {}", path, e));
+                        }
+                        WarmState::Load(_) => {
+                            let header = load_checkpoint(&path, &mut cache)
+                                .unwrap_or_else(|e| panic!("failed to load {}: {}", path, e));
+                            assert_eq!(
+                                header.num_requests, num_warmup_requests as u64,
+                                "{} is saved after {} warmup requests",
+                                path, header.num_requests
+                            );
+                            warmup_requests(&mut requests, num_warmup_requests).for_each(drop);
+                        }
+                    }
+                    // the warmup requests are not in the results
+                    let result =
+                        run_simulation_with_options(&mut cache, requests, miss_latency, &options);
+                    warmup_boundary.get_or_insert(WarmupBoundary {
+                        num_requests: num_warmup_requests,
+                        end_timestamp: result.results.first().map(|r| r.request_timestamp),
+                    });
+                    (result, 0)
+                }
+            };
+            trace.get_or_insert_with(|| TraceMetadata::from_simulation(requests_path, &result));
+            PolicyStats::from_simulation(cache.set(0).name(), &result, warmup_in_results)
+                .with_miss_handling(miss_handling)
+        })
+        .collect();
+    let warmup_boundary = warmup_boundary.expect("at least one policy should be given");
+
+    ExperimentReport {
+        version: VersionInfo::current(),
+        trace: trace.expect("at least one policy should be given"),
+        config: CacheConfig {
+            cache_counts,
+            cache_capacity,
+            set_mapping: set_mapping.to_string(),
+            miss_latency,
+            warmup: warmup_boundary.num_requests,
+            warmup_config: warmup.to_string(),
+            warmup_end_timestamp: warmup_boundary.end_timestamp,
+            max_requests,
+            cost_coefficient,
+            arrival: arrival.to_string(),
+            simulation: options.into(),
+        },
+        policies,
+    }
+}
+
+/// Warm cache states of [`run_experiment`], saved in a directory with one checkpoint per policy and miss handling.
+#[derive(Debug, Clone)]
+enum WarmState {
+    /// Simulate the warmup, save the warmed caches, and simulate the rest of the trace from them.
+    Save(String),
+    /// Restore the warmed caches and simulate the rest of the trace, skipping the warmup requests.
+    Load(String),
+}
+
+impl WarmState {
+    fn path(&self, policy: &str, miss_handling: MissHandling) -> String {
+        let (Self::Save(dir) | Self::Load(dir)) = self;
+        format!("{}/{}-{}.state", dir, policy, miss_handling)
+    }
+}
+
+#[derive(Debug, Clone)]
+struct CurvePoint {
+    cache_capacity: usize,
+    lru_miss_ratio: f64,
+    /// statistics of each policy, in the order given on the command line
+    policies: Vec<PolicyStats>,
+}
+
+/// Simulate the policy `policy` on `requests`, excluding the first `warmup` requests from the statistics.
+fn simulate_policy(
+    policy: &str,
+    params: &PolicyParams,
+    requests: &[RequestEvent],
+    miss_latency: u64,
     warmup: usize,
+) -> PolicyStats {
+    let mut cache = construct_policy(policy, params).unwrap();
+    let result = run_simulation(&mut cache, requests.iter().cloned(), miss_latency);
+    PolicyStats::from_simulation(cache.name(), &result, warmup)
+}
+
+/// Compute the miss-ratio curve and the average latency curve over the per-set capacities in `cache_capacities`.
+/// The LRU miss ratio (under the zero-latency miss model) is computed in one pass using stack distances, while
+/// latencies under delayed hits are computed by simulating each capacity and policy in parallel.
+#[allow(clippy::too_many_arguments)]
+fn run_curve(
+    requests_path: &str,
+    policies: &[&str],
+    cache_counts: usize,
+    cache_capacities: &[usize],
     miss_latency: u64,
+    warmup: usize,
+    max_requests: Option<usize>,
+) -> Vec<CurvePoint> {
+    let requests = load_events_file(requests_path)
+        .take(max_requests.unwrap_or(usize::MAX))
+        .collect::<Vec<_>>();
+    let lru_miss_ratios =
+        heuristics::lru_miss_ratio_curve(&requests, cache_counts, cache_capacities, warmup);
+
+    cache_capacities
+        .par_iter()
+        .zip(lru_miss_ratios)
+        .map(|(&cache_capacity, lru_miss_ratio)| {
+            let params = PolicyParams {
+                cache_counts,
+                cache_capacity,
+                estimated_miss_latency: miss_latency,
+                set_mapping: SetMapping::default(),
+                cost_coefficient: DEFAULT_COST_COEFFICIENT,
+            };
+            let policies = policies
+                .par_iter()
+                .map(|policy| simulate_policy(policy, &params, &requests, miss_latency, warmup))
+                .collect();
+            CurvePoint {
+                cache_capacity,
+                lru_miss_ratio,
+                policies,
+            }
+        })
+        .collect()
 }
 
-impl Display for ExperimentResult {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+/// Write the curve as a CSV file with a header, one row per capacity. Each policy gets a pair of
+/// `<policy>_delayed_miss_ratio` and `<policy>_average_latency` columns.
+fn write_curve<W: Write>(
+    mut writer: W,
+    points: &[CurvePoint],
+    policies: &[&str],
+    cache_counts: usize,
+    miss_latency: u64,
+    warmup: usize,
+) -> std::io::Result<()> {
+    write!(writer, "k,c,miss_latency,warmup,lru_miss_ratio")?;
+    for policy in policies {
+        let column = policy.replace('-', "_");
         write!(
-            f,
-            "k: {}, c: {}, miss_latency: {}\n\
-            total latency (lru): {}\n\
-            average latency (lru): {}\n\
-            num loads (lru): {}\n\
-            total latency (lru-mad): {}\n\
-            average latency (lru-mad): {}\n\
-            num loads (lru-mad): {}\n\
-            improvement (%): {}\n\
-            CSV: {}, {}, {}, {}, {}, {}",
-            self.cache_counts,
-            self.cache_capacity,
-            self.miss_latency,
-            self.total_latency_lru,
-            self.average_latency_lru,
-            self.num_loads_lru,
-            self.total_latency_lru_mad,
-            self.average_latency_lru_mad,
-            self.num_loads_lru_mad,
-            self.improvement * 100.0,
-            self.cache_counts,
-            self.cache_capacity,
-            self.miss_latency,
-            self.warmup,
-            self.average_latency_lru,
-            self.average_latency_lru_mad
-        )
+            writer,
+            ",{}_delayed_miss_ratio,{}_average_latency",
+            column, column
+        )?;
+    }
+    writeln!(writer)?;
+    for point in points {
+        write!(
+            writer,
+            "{},{},{},{},{}",
+            cache_counts, point.cache_capacity, miss_latency, warmup, point.lru_miss_ratio,
+        )?;
+        for stats in &point.policies {
+            write!(
+                writer,
+                ",{},{}",
+                stats.non_hit_ratio(),
+                stats.average_latency
+            )?;
+        }
+        writeln!(writer)?;
     }
+    Ok(())
 }
 
-/// Run an experiment with the given parameters.
-/// - `requests_path`: the path to the file containing the requests
-/// - `cache_counts`: the number of caches in the cache hierarchy
-/// - `cache_capacity`: the capacity of each cache
-/// - `miss_latency`: the latency of a cache miss
-/// - `warmup`: the number of requests to warm up the cache. The warmup requests are not included in the statistics.
-fn run_experiment(
+/// Estimate the average latency of each policy on a spatially sampled trace (SHARDS) with the cache capacity
+/// scaled by the sampling rate, and report the estimation error against the full simulation.
+#[allow(clippy::too_many_arguments)]
+fn run_sampling_experiment(
     requests_path: &str,
+    policies: &[&str],
     cache_counts: usize,
     cache_capacity: usize,
     miss_latency: u64,
     warmup: usize,
     max_requests: Option<usize>,
-) -> ExperimentResult {
-    let mut lru = construct_k_way_cache(cache_counts, |_| LRU::new(cache_capacity));
-    let request_results_lru = if let Some(max_requests) = max_requests {
-        // // uncomment this block to simulate the toy cdn deployment (after dummy warmup, the CDN nodes waits for all requests to be fulfilled before playing the trace)
-        // let mut requests = load_events_file(requests_path).take(max_requests);
-        // let requests_a = requests
-        //     .by_ref()
-        //     .take(warmup)
-        //     .collect::<Vec<_>>()
-        //     .into_iter();
-        // let requests_b = requests.map(|mut req| {
-        //     req.timestamp += miss_latency;
-        //     req
-        // });
-        // run_simulation(&mut lru, requests_a.chain(requests_b), miss_latency)
-        run_simulation(
-            &mut lru,
-            load_events_file(requests_path).take(max_requests),
-            miss_latency,
-        )
-    } else {
-        run_simulation(&mut lru, load_events_file(requests_path), miss_latency)
+    mut sampler: SpatialSampler,
+) {
+    let requests = load_events_file(requests_path)
+        .take(max_requests.unwrap_or(usize::MAX))
+        .collect::<Vec<_>>();
+    // the threshold of the fixed-size sampler is only final after the whole trace
+    sampler.observe(&requests);
+    let sampled_warmup = requests
+        .iter()
+        .take(warmup)
+        .filter(|r| sampler.is_sampled(r))
+        .count();
+    let sampled_requests = requests
+        .iter()
+        .filter(|r| sampler.is_sampled(r))
+        .cloned()
+        .collect::<Vec<_>>();
+    let rate = sampler.rate();
+    let scaled_capacity = scale_capacity(cache_capacity, rate);
+    println!(
+        "sampling rate: {}, sampled requests: {}/{}, scaled cache capacity: {}",
+        rate,
+        sampled_requests.len(),
+        requests.len(),
+        scaled_capacity
+    );
+
+    let full_params = PolicyParams {
+        cache_counts,
+        cache_capacity,
+        estimated_miss_latency: miss_latency,
+        set_mapping: SetMapping::default(),
+        cost_coefficient: DEFAULT_COST_COEFFICIENT,
     };
+    let start = Instant::now();
+    let full = policies
+        .iter()
+        .map(|policy| simulate_policy(policy, &full_params, &requests, miss_latency, warmup))
+        .collect::<Vec<_>>();
+    let full_elapsed = start.elapsed();
 
-    let stats = compute_statistics(&request_results_lru.results[warmup..]);
-    let lru_avg_latency = stats.average_latency;
-
-    let mut lru_mad = construct_k_way_cache(cache_counts, |_| {
-        LRUMinAD::new(cache_capacity, miss_latency)
-    });
-    let request_results_lru_mad = if let Some(max_requests) = max_requests {
-        // // uncomment this block to simulate the toy cdn deployment (after dummy warmup, the CDN nodes waits for all requests to be fulfilled before playing the trace)
-        // let mut requests = data::load_data(requests_path).take(max_requests);
-        // let requests_a = requests
-        //     .by_ref()
-        //     .take(warmup)
-        //     .collect::<Vec<_>>()
-        //     .into_iter();
-        // let requests_b = requests.map(|mut req| {
-        //     req.timestamp += miss_latency;
-        //     req
-        // });
-        // run_simulation(&mut lru_mad, requests_a.chain(requests_b), miss_latency)
-        run_simulation(
-            &mut lru_mad,
-            load_events_file(requests_path).take(max_requests),
-            miss_latency,
-        )
-    } else {
-        run_simulation(&mut lru_mad, load_events_file(requests_path), miss_latency)
+    let sampled_params = PolicyParams {
+        cache_capacity: scaled_capacity,
+        ..full_params
     };
+    let start = Instant::now();
+    let sampled = policies
+        .iter()
+        .map(|policy| {
+            simulate_policy(
+                policy,
+                &sampled_params,
+                &sampled_requests,
+                miss_latency,
+                sampled_warmup,
+            )
+        })
+        .collect::<Vec<_>>();
+    let sampled_elapsed = start.elapsed();
 
-    let stats = compute_statistics(&request_results_lru_mad.results[warmup..]);
-    let lru_mad_avg_latency = stats.average_latency;
+    for (full, sampled) in full.iter().zip(sampled.iter()) {
+        println!(
+            "average latency ({}): full: {}, sampled: {}, error (%): {}",
+            full.policy,
+            full.average_latency,
+            sampled.average_latency,
+            (sampled.average_latency - full.average_latency).abs() / full.average_latency * 100.0
+        );
+    }
+    println!(
+        "simulation time: full: {:?}, sampled: {:?}",
+        full_elapsed, sampled_elapsed
+    );
+}
 
-    let improvement = (lru_avg_latency - lru_mad_avg_latency) / lru_avg_latency;
+/// Simulate a cluster of `num_servers` servers under the membership changes in `churn`, and compare the statistics
+/// in the windows before and after each change.
+#[allow(clippy::too_many_arguments)]
+fn run_cluster_experiment(
+    requests_path: &str,
+    policies: &[&str],
+    num_servers: usize,
+    server_capacity: usize,
+    virtual_nodes: usize,
+    miss_latency: u64,
+    failure_detection_delay: u64,
+    churn: &[ClusterEvent],
+    window: u64,
+    max_requests: Option<usize>,
+) {
+    // fail early instead of after loading the trace
+    check_schedule(num_servers, failure_detection_delay, churn)
+        .unwrap_or_else(|e| panic!("Invalid churn schedule: {}", e));
+    let requests = load_events_file(requests_path)
+        .take(max_requests.unwrap_or(usize::MAX))
+        .collect::<Vec<_>>();
+    let params = PolicyParams {
+        cache_counts: 1,
+        cache_capacity: server_capacity,
+        estimated_miss_latency: miss_latency,
+        set_mapping: SetMapping::default(),
+        cost_coefficient: DEFAULT_COST_COEFFICIENT,
+    };
+    println!(
+        "servers: {}, c: {}, virtual nodes: {}, miss_latency: {}, failure detection delay: {}",
+        num_servers,
+        server_capacity,
+        virtual_nodes,
+        miss_latency,
+        get_time_string(failure_detection_delay as u128)
+    );
+    for policy in policies {
+        let mut cluster = CacheCluster::new(
+            num_servers,
+            virtual_nodes,
+            failure_detection_delay,
+            churn.to_vec(),
+            |_| construct_single_policy(policy, &params).unwrap(),
+        )
+        .unwrap();
+        let result = run_simulation(&mut cluster, requests.iter().cloned(), miss_latency);
+        let stats = PolicyStats::from_simulation(policy, &result, 0);
+        println!(
+            "{}: average latency: {}, hits / delayed hits / misses: {} / {} / {}, requests to failed servers: {}, dropped writes: {}",
+            policy,
+            stats.average_latency,
+            stats.num_hits,
+            stats.num_delayed_hits,
+            stats.num_misses,
+            cluster.num_requests_to_down_servers,
+            cluster.num_dropped_writes
+        );
+        for impact in churn_impact(&result.results, churn, window) {
+            let ratio = |n: usize, total: usize| n as f64 / total as f64 * 100.0;
+            println!(
+                "  {}: miss ratio (%): {:.2} -> {:.2}, delayed hit ratio (%): {:.2} -> {:.2}, average latency: {} -> {}",
+                impact.event,
+                ratio(impact.before.num_misses, impact.num_requests_before),
+                ratio(impact.after.num_misses, impact.num_requests_after),
+                ratio(impact.before.num_delayed_hits, impact.num_requests_before),
+                ratio(impact.after.num_delayed_hits, impact.num_requests_after),
+                impact.before.average_latency,
+                impact.after.average_latency
+            );
+        }
+    }
+}
 
-    ExperimentResult {
-        total_latency_lru: stats.total_latency,
-        average_latency_lru: lru_avg_latency,
-        num_loads_lru: request_results_lru.num_of_loads,
-        total_latency_lru_mad: stats.total_latency,
-        average_latency_lru_mad: lru_mad_avg_latency,
-        num_loads_lru_mad: request_results_lru_mad.num_of_loads,
-        improvement,
+/// Merge the traces of several tenants and simulate them on a shared cache, and on a cache partitioned by tenant if
+/// `partitioned` is set, reporting the statistics of each tenant.
+#[allow(clippy::too_many_arguments)]
+fn run_tenant_experiment(
+    requests_paths: &[String],
+    transforms: &[TenantTransform],
+    policies: &[&str],
+    cache_counts: usize,
+    cache_capacity: usize,
+    miss_latency: u64,
+    warmup: WarmupConfig,
+    partitioned: bool,
+    fetch_cost: CostModel,
+    max_requests: Option<usize>,
+) {
+    let num_tenants = requests_paths.len();
+    let requests = MergedTenants::new(
+        requests_paths
+            .iter()
+            .zip(transforms)
+            .map(|(path, &transform)| (load_events_file(path), transform)),
+    )
+    .take(max_requests.unwrap_or(usize::MAX))
+    .collect::<Vec<_>>();
+    let params = PolicyParams {
         cache_counts,
         cache_capacity,
-        warmup,
-        miss_latency,
+        estimated_miss_latency: miss_latency,
+        set_mapping: SetMapping::default(),
+        cost_coefficient: DEFAULT_COST_COEFFICIENT,
+    };
+    let partition_params = PolicyParams {
+        cache_capacity: (cache_capacity / num_tenants).max(1),
+        ..params
+    };
+    let options = SimulationOptions {
+        fetch_cost,
+        ..Default::default()
+    };
+    println!(
+        "tenants: {}, k: {}, c: {}, miss_latency: {}, fetch cost: {}",
+        num_tenants, cache_counts, cache_capacity, miss_latency, options.fetch_cost
+    );
+
+    let mut warmup_boundary: Option<WarmupBoundary> = None;
+    let mut report = |policy: &str, cache: &str, result: &SimulationResult| {
+        let boundary = *warmup_boundary.get_or_insert_with(|| warmup.boundary(&result.results));
+        let results = &result.results[boundary.num_requests..];
+        let stats = PolicyStats::from_simulation(policy, result, boundary.num_requests);
+        println!(
+            "{} ({}): average latency: {}, hits / delayed hits / misses: {} / {} / {}, fetch cost: {}",
+            policy,
+            cache,
+            stats.average_latency,
+            stats.num_hits,
+            stats.num_delayed_hits,
+            stats.num_misses,
+            stats.total_fetch_cost
+        );
+        for tenant in tenant_statistics(results, num_tenants) {
+            let ratio = |n: usize| n as f64 / tenant.num_requests as f64 * 100.0;
+            println!(
+                "  tenant {} ({}): requests: {}, average latency: {}, hit ratio (%): {:.2}, delayed hit ratio (%): {:.2}, miss ratio (%): {:.2}",
+                tenant.tenant,
+                requests_paths[tenant.tenant],
+                tenant.num_requests,
+                tenant.stats.average_latency,
+                ratio(tenant.stats.num_hits),
+                ratio(tenant.stats.num_delayed_hits),
+                ratio(tenant.stats.num_misses)
+            );
+        }
+    };
+    for policy in policies {
+        let mut shared = construct_policy(policy, &params).unwrap();
+        let result = run_simulation_with_options(
+            &mut shared,
+            requests.iter().cloned(),
+            miss_latency,
+            &options,
+        );
+        report(policy, "shared", &result);
+        if partitioned {
+            let mut cache = PartitionedCache::new(
+                (0..num_tenants)
+                    .map(|_| construct_policy(policy, &partition_params).unwrap())
+                    .collect(),
+            );
+            let result = run_simulation_with_options(
+                &mut cache,
+                requests.iter().cloned(),
+                miss_latency,
+                &options,
+            );
+            let name = format!("partitioned, c: {}", partition_params.cache_capacity);
+            report(policy, &name, &result);
+        }
+    }
+    if let Some(boundary) = warmup_boundary.filter(|b| b.num_requests > 0) {
+        println!("warmup: {} requests ({})", boundary.num_requests, warmup);
     }
 }
 
@@ -187,10 +726,177 @@ fn head(path: &str, n: usize) {
 }
 
 #[derive(Debug, Subcommand)]
+#[allow(clippy::large_enum_variant)]
 enum Experiment {
     Trace {
         #[clap(long, short = 'p')]
         event_path: String,
+        #[clap(
+            long,
+            help = "comma-separated list of policies to compare, the first one being the baseline",
+            value_delimiter = ',',
+            value_parser = parse_policy_name,
+            default_value = "lru,lru-mad"
+        )]
+        policy: Vec<&'static str>,
+        #[clap(
+            long,
+            help = "comma-separated list of miss models: coalesce (delayed hits), no-coalescing or zero-latency (classic hit-ratio view)",
+            value_delimiter = ',',
+            default_value = "coalesce"
+        )]
+        miss_handling: Vec<MissHandling>,
+        #[clap(
+            long,
+            help = "how writes in .opevents traces are handled: write-through or write-back",
+            default_value = "write-through"
+        )]
+        write_policy: WritePolicy,
+        #[clap(long, help = "insert objects that are written but not cached")]
+        write_allocate: bool,
+        #[clap(long, help = "time to live of the cached objects, unless given by the request (default: never expire)", value_parser = proj_cache_sim::parse_time_unit)]
+        ttl: Option<u64>,
+        #[clap(
+            long,
+            help = "serve expired objects while they are revalidated in the background"
+        )]
+        stale_while_revalidate: bool,
+        #[clap(
+            long,
+            help = "prefetcher: none, next[:<n>] (sequential) or markov[:<degree>]",
+            default_value = "none"
+        )]
+        prefetch: PrefetchConfig,
+        #[clap(
+            long,
+            help = "probability that a fetch attempt from the backing store fails",
+            default_value = "0"
+        )]
+        error_probability: f64,
+        #[clap(
+            long,
+            help = "comma-separated list of outages of the backing store, as <start>-<end> with units (e.g. 10s-12s)",
+            value_delimiter = ','
+        )]
+        outage: Vec<Outage>,
+        #[clap(long, help = "retries of a failed fetch attempt", default_value = "0")]
+        max_retries: u32,
+        #[clap(long, help = "delay before the first retry, doubled for every retry", default_value = "0", value_parser = proj_cache_sim::parse_time_unit)]
+        backoff: u64,
+        #[clap(long, help = "how long failed fetches are cached (default: no negative caching)", value_parser = proj_cache_sim::parse_time_unit)]
+        negative_ttl: Option<u64>,
+        #[clap(long, help = "seed of the random fetch failures", default_value = "0")]
+        failure_seed: u64,
+        #[clap(
+            long,
+            help = "cost of the fetches from the backing store: <cost> for every fetch, or keys:<path>[:<default>] for a file of <key>,<cost> lines",
+            default_value = "1"
+        )]
+        fetch_cost: CostModel,
+        #[clap(
+            long,
+            help = "weight of the fetch cost against the delay in cost-aware policies (lru-mad-cost), in miss latencies per unit of cost",
+            default_value_t = DEFAULT_COST_COEFFICIENT
+        )]
+        cost_coefficient: f64,
+        #[clap(
+            long,
+            help = "arrival times of the requests, keeping their order: original, irt:<irt> for a constant inter-request time as in the toy CDN, scale:<factor> to multiply the inter-request times, or poisson:<mean irt>[:<seed>] for Poisson arrivals",
+            default_value = "original"
+        )]
+        arrival: ArrivalTransform,
+        #[clap(long, short = 'k')]
+        cache_counts: usize,
+        #[clap(long, short = 'c')]
+        cache_capacity: usize,
+        #[clap(
+            long,
+            help = "set-index function: hash, modulo, multiplicative, xxhash, consistent[:<virtual_nodes>] or skewed[:<ways>]",
+            default_value = "hash"
+        )]
+        set_mapping: SetMapping,
+        #[clap(long, short = 'l', help = "miss latency with unit (e.g. 300ns, 2ms)", value_parser = proj_cache_sim::parse_time_unit)]
+        miss_latency: u64,
+        #[clap(
+            long,
+            short = 'w',
+            help = "warmup: a number of requests, a duration of trace time with unit (e.g. 30s), or auto[:<window>[:<tolerance>]] to end when the hit ratio of the baseline is stable",
+            default_value = "0"
+        )]
+        warmup: WarmupConfig,
+        #[clap(long, short = 'm', help = "maximum number of requests to process")]
+        max_requests: Option<usize>,
+        #[clap(
+            long,
+            help = "directory to save the caches to after the warmup, one file per policy and miss handling"
+        )]
+        save_warm_state: Option<String>,
+        #[clap(
+            long,
+            help = "directory to restore the caches from instead of simulating the warmup, as saved by --save-warm-state",
+            conflicts_with = "save_warm_state"
+        )]
+        load_warm_state: Option<String>,
+        #[clap(
+            long,
+            help = "simulate the k sets in parallel, with identical results. Falls back to a sequential simulation with skewed set mapping, prefetching or fetch failures",
+            conflicts_with_all = ["save_warm_state", "load_warm_state"]
+        )]
+        parallel: bool,
+        #[clap(long, short = 'f', value_enum, default_value = "text")]
+        format: OutputFormat,
+    },
+    Curve {
+        #[clap(long, short = 'p')]
+        event_path: String,
+        #[clap(
+            long,
+            help = "comma-separated list of policies to compare, the first one being the baseline",
+            value_delimiter = ',',
+            value_parser = parse_policy_name,
+            default_value = "lru,lru-mad"
+        )]
+        policy: Vec<&'static str>,
+        #[clap(long, short = 'k')]
+        cache_counts: usize,
+        #[clap(
+            long,
+            short = 'c',
+            help = "comma-separated list of cache capacities (e.g. 16,32,64)",
+            value_delimiter = ',',
+            required = true
+        )]
+        cache_capacities: Vec<usize>,
+        #[clap(long, short = 'l', help = "miss latency with unit (e.g. 300ns, 2ms)", value_parser = proj_cache_sim::parse_time_unit)]
+        miss_latency: u64,
+        #[clap(
+            long,
+            short = 'w',
+            help = "number of warmup requests",
+            default_value = "0"
+        )]
+        warmup: usize,
+        #[clap(long, short = 'm', help = "maximum number of requests to process")]
+        max_requests: Option<usize>,
+        #[clap(
+            long,
+            short = 'o',
+            help = "path to the output CSV file (default: stdout)"
+        )]
+        output: Option<String>,
+    },
+    #[command(group(clap::ArgGroup::new("sampler").required(true).args(["rate", "max_keys"])))]
+    Shards {
+        #[clap(long, short = 'p')]
+        event_path: String,
+        #[clap(
+            long,
+            help = "comma-separated list of policies to compare, the first one being the baseline",
+            value_delimiter = ',',
+            value_parser = parse_policy_name,
+            default_value = "lru,lru-mad"
+        )]
+        policy: Vec<&'static str>,
         #[clap(long, short = 'k')]
         cache_counts: usize,
         #[clap(long, short = 'c')]
@@ -206,10 +912,123 @@ enum Experiment {
         warmup: usize,
         #[clap(long, short = 'm', help = "maximum number of requests to process")]
         max_requests: Option<usize>,
+        #[clap(long, short = 'r', help = "fixed sampling rate in (0, 1]")]
+        rate: Option<f64>,
+        #[clap(long, help = "maximum number of sampled keys (fixed-size sampling)")]
+        max_keys: Option<usize>,
+    },
+    Cluster {
+        #[clap(long, short = 'p')]
+        event_path: String,
+        #[clap(
+            long,
+            help = "comma-separated list of policies to compare",
+            value_delimiter = ',',
+            value_parser = parse_policy_name,
+            default_value = "lru,lru-mad"
+        )]
+        policy: Vec<&'static str>,
+        #[clap(long, short = 'k', help = "initial number of servers (0..k)")]
+        num_servers: usize,
+        #[clap(long, short = 'c', help = "cache capacity of each server")]
+        server_capacity: usize,
+        #[clap(long, default_value = "100", help = "virtual nodes per server")]
+        virtual_nodes: usize,
+        #[clap(long, short = 'l', help = "miss latency with unit (e.g. 300ns, 2ms)", value_parser = proj_cache_sim::parse_time_unit)]
+        miss_latency: u64,
+        #[clap(long, help = "time from a failure until the server is removed from the ring", value_parser = proj_cache_sim::parse_time_unit, default_value = "0")]
+        detection_delay: u64,
+        #[clap(
+            long,
+            help = "comma-separated membership changes <join|leave|fail>:<server>@<timestamp> (e.g. fail:3@2s,join:3@5s)",
+            value_delimiter = ','
+        )]
+        churn: Vec<ClusterEvent>,
+        #[clap(long, help = "window before and after each change to compare", value_parser = proj_cache_sim::parse_time_unit, default_value = "1s")]
+        window: u64,
+        #[clap(long, short = 'm', help = "maximum number of requests to process")]
+        max_requests: Option<usize>,
+    },
+    Tenants {
+        #[clap(
+            long,
+            short = 'p',
+            help = "comma-separated list of traces, one per tenant",
+            value_delimiter = ',',
+            required = true
+        )]
+        event_path: Vec<String>,
+        #[clap(
+            long,
+            help = "comma-separated list of the timestamps of the first request of each tenant, with units (default: 0)",
+            value_delimiter = ',',
+            value_parser = proj_cache_sim::parse_time_unit
+        )]
+        time_shift: Vec<u64>,
+        #[clap(
+            long,
+            help = "comma-separated list of the speed-ups of the traces, e.g. 2 to replay a trace at twice its request rate (default: 1)",
+            value_delimiter = ','
+        )]
+        rate_scale: Vec<f64>,
+        #[clap(
+            long,
+            help = "comma-separated list of policies to compare",
+            value_delimiter = ',',
+            value_parser = parse_policy_name,
+            default_value = "lru,lru-mad"
+        )]
+        policy: Vec<&'static str>,
+        #[clap(long, short = 'k')]
+        cache_counts: usize,
+        #[clap(long, short = 'c', help = "cache capacity, shared by all tenants")]
+        cache_capacity: usize,
+        #[clap(long, short = 'l', help = "miss latency with unit (e.g. 300ns, 2ms)", value_parser = proj_cache_sim::parse_time_unit)]
+        miss_latency: u64,
+        #[clap(
+            long,
+            short = 'w',
+            help = "warmup: a number of requests, a duration of trace time with unit (e.g. 30s), or auto[:<window>[:<tolerance>]] to end when the hit ratio of the first shared cache is stable",
+            default_value = "0"
+        )]
+        warmup: WarmupConfig,
+        #[clap(
+            long,
+            help = "also simulate a cache partitioned by tenant, each tenant having an equal share of the capacity"
+        )]
+        partitioned: bool,
+        #[clap(
+            long,
+            help = "cost of the fetches from the backing store: <cost> for every fetch, or origins:<cost>,... for the origin of each tenant",
+            default_value = "1"
+        )]
+        fetch_cost: CostModel,
+        #[clap(long, short = 'm', help = "maximum number of requests to process")]
+        max_requests: Option<usize>,
+    },
+    Sweep {
+        #[clap(
+            required = true,
+            help = "Path to the toml file listing the configurations"
+        )]
+        config_path: String,
+        #[clap(long, short = 'o', help = "path to the output file (default: stdout)")]
+        output: Option<String>,
+        #[clap(long, short = 'f', value_enum, default_value = "csv")]
+        format: OutputFormat,
     },
     Analysis {
         #[clap(required = true)]
         event_path: String,
+        #[clap(
+            long,
+            short = 'k',
+            help = "number of caches, for per-set stack distances",
+            default_value = "1"
+        )]
+        cache_counts: usize,
+        #[clap(long, short = 'l', help = "miss latency with unit (e.g. 300ns, 2ms), to estimate the delayed-hit potential", value_parser = proj_cache_sim::parse_time_unit)]
+        miss_latency: Option<u64>,
     },
     Head {
         #[clap(required = true)]
@@ -231,24 +1050,233 @@ fn main() {
     match args.experiment {
         Experiment::Trace {
             event_path,
+            policy,
+            miss_handling,
+            write_policy,
+            write_allocate,
+            ttl,
+            stale_while_revalidate,
+            prefetch,
+            error_probability,
+            outage,
+            max_retries,
+            backoff,
+            negative_ttl,
+            failure_seed,
+            fetch_cost,
+            cost_coefficient,
+            arrival,
+            cache_counts,
+            cache_capacity,
+            set_mapping,
+            miss_latency,
+            warmup,
+            max_requests,
+            save_warm_state,
+            load_warm_state,
+            parallel,
+            format,
+        } => {
+            let warm_state = save_warm_state
+                .map(WarmState::Save)
+                .or(load_warm_state.map(WarmState::Load));
+            if let Some(WarmState::Save(dir)) = &warm_state {
+                std::fs::create_dir_all(dir).unwrap();
+            }
+            let failures =
+                (error_probability > 0.0 || !outage.is_empty()).then_some(FailureModel {
+                    error_probability,
+                    outages: outage,
+                    max_retries,
+                    initial_backoff: backoff,
+                    negative_ttl,
+                    seed: failure_seed,
+                });
+            let options = SimulationOptions {
+                write_policy,
+                write_allocate,
+                default_ttl: ttl,
+                stale_while_revalidate,
+                prefetch,
+                failures,
+                fetch_cost,
+                ..Default::default()
+            };
+            // the keys of a trace without tenants are all from origin 0
+            options
+                .fetch_cost
+                .check_origins(1)
+                .unwrap_or_else(|e| panic!("Invalid fetch cost: {}", e));
+            let report = run_experiment(
+                &event_path,
+                arrival,
+                &policy,
+                &miss_handling,
+                &options,
+                cache_counts,
+                cache_capacity,
+                set_mapping,
+                miss_latency,
+                cost_coefficient,
+                warmup,
+                max_requests,
+                warm_state.as_ref(),
+                parallel,
+            );
+            report.write(std::io::stdout(), format).unwrap();
+        }
+        Experiment::Curve {
+            event_path,
+            policy,
+            cache_counts,
+            cache_capacities,
+            miss_latency,
+            warmup,
+            max_requests,
+            output,
+        } => {
+            let points = run_curve(
+                &event_path,
+                &policy,
+                cache_counts,
+                &cache_capacities,
+                miss_latency,
+                warmup,
+                max_requests,
+            );
+            if let Some(output) = output {
+                let file = std::fs::File::create(output).unwrap();
+                write_curve(file, &points, &policy, cache_counts, miss_latency, warmup).unwrap();
+            } else {
+                write_curve(
+                    std::io::stdout(),
+                    &points,
+                    &policy,
+                    cache_counts,
+                    miss_latency,
+                    warmup,
+                )
+                .unwrap();
+            }
+        }
+        Experiment::Shards {
+            event_path,
+            policy,
             cache_counts,
             cache_capacity,
             miss_latency,
             warmup,
             max_requests,
+            rate,
+            max_keys,
         } => {
-            let result = run_experiment(
+            let sampler = match (rate, max_keys) {
+                (Some(rate), _) => SpatialSampler::fixed_rate(rate),
+                (None, Some(max_keys)) => SpatialSampler::fixed_size(max_keys),
+                (None, None) => unreachable!("clap requires one of rate and max_keys"),
+            };
+            run_sampling_experiment(
                 &event_path,
+                &policy,
                 cache_counts,
                 cache_capacity,
                 miss_latency,
                 warmup,
                 max_requests,
+                sampler,
+            );
+        }
+        Experiment::Cluster {
+            event_path,
+            policy,
+            num_servers,
+            server_capacity,
+            virtual_nodes,
+            miss_latency,
+            detection_delay,
+            churn,
+            window,
+            max_requests,
+        } => {
+            run_cluster_experiment(
+                &event_path,
+                &policy,
+                num_servers,
+                server_capacity,
+                virtual_nodes,
+                miss_latency,
+                detection_delay,
+                &churn,
+                window,
+                max_requests,
             );
-            println!("{}", result);
         }
-        Experiment::Analysis { event_path } => {
-            analyze_event(&event_path);
+        Experiment::Tenants {
+            event_path,
+            time_shift,
+            rate_scale,
+            policy,
+            cache_counts,
+            cache_capacity,
+            miss_latency,
+            warmup,
+            partitioned,
+            fetch_cost,
+            max_requests,
+        } => {
+            fetch_cost
+                .check_origins(event_path.len())
+                .unwrap_or_else(|e| panic!("Invalid fetch cost: {}", e));
+            for (name, len) in [
+                ("--time-shift", time_shift.len()),
+                ("--rate-scale", rate_scale.len()),
+            ] {
+                assert!(
+                    len == 0 || len == event_path.len(),
+                    "{} should give one value per trace",
+                    name
+                );
+            }
+            let transforms = (0..event_path.len())
+                .map(|i| TenantTransform {
+                    time_shift: time_shift.get(i).copied().unwrap_or(0),
+                    rate_scale: rate_scale.get(i).copied().unwrap_or(1.0),
+                })
+                .collect::<Vec<_>>();
+            run_tenant_experiment(
+                &event_path,
+                &transforms,
+                &policy,
+                cache_counts,
+                cache_capacity,
+                miss_latency,
+                warmup,
+                partitioned,
+                fetch_cost,
+                max_requests,
+            );
+        }
+        Experiment::Sweep {
+            config_path,
+            output,
+            format,
+        } => {
+            let toml_str = std::fs::read_to_string(&config_path).unwrap();
+            let config: sweep::SweepConfig = toml::from_str(&toml_str).unwrap();
+            let report = sweep::run_sweep(&config);
+            if let Some(output) = output {
+                let file = std::fs::File::create(output).unwrap();
+                report.write(std::io::BufWriter::new(file), format).unwrap();
+            } else {
+                report.write(std::io::stdout(), format).unwrap();
+            }
+        }
+        Experiment::Analysis {
+            event_path,
+            cache_counts,
+            miss_latency,
+        } => {
+            analyze_event(&event_path, cache_counts, miss_latency);
         }
         Experiment::Head { event_path, n } => {
             head(&event_path, n);
@@ -0,0 +1,464 @@
+//! Machine-readable experiment reports.
+
+use std::{fmt::Display, io::Write};
+
+use proj_cache_sim::{
+    arrival::ArrivalTransform,
+    cost::CostModel,
+    prefetch::PrefetchConfig,
+    simulator::{compute_statistics, Counters, MissHandling, SimulationOptions, SimulationResult},
+};
+use serde_derive::Serialize;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum OutputFormat {
+    /// human-readable text
+    Text,
+    /// CSV with a header, one row per policy
+    Csv,
+    /// a JSON object
+    Json,
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct VersionInfo {
+    pub version: &'static str,
+    /// output of `git describe` at build time
+    pub git_revision: Option<&'static str>,
+}
+
+impl VersionInfo {
+    pub fn current() -> Self {
+        Self {
+            version: env!("CARGO_PKG_VERSION"),
+            git_revision: option_env!("GIT_REVISION"),
+        }
+    }
+}
+
+impl Display for VersionInfo {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} ({})",
+            self.version,
+            self.git_revision.unwrap_or("unknown revision")
+        )
+    }
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct TraceMetadata {
+    pub path: String,
+    /// number of simulated requests, including warmup
+    pub num_requests: usize,
+    pub first_timestamp: u64,
+    pub last_timestamp: u64,
+}
+
+impl TraceMetadata {
+    pub fn from_simulation(path: &str, result: &SimulationResult) -> Self {
+        Self {
+            path: path.to_string(),
+            num_requests: result.results.len(),
+            first_timestamp: result.results.first().map_or(0, |r| r.request_timestamp),
+            last_timestamp: result.results.last().map_or(0, |r| r.request_timestamp),
+        }
+    }
+}
+
+/// Configuration of a `cache_counts`-way set associative cache experiment.
+#[derive(Serialize, Debug, Clone)]
+pub struct CacheConfig {
+    pub cache_counts: usize,
+    pub cache_capacity: usize,
+    pub set_mapping: String,
+    pub miss_latency: u64,
+    /// number of warmup requests
+    pub warmup: usize,
+    /// how the warmup is defined (see [`proj_cache_sim::warmup::WarmupConfig`])
+    pub warmup_config: String,
+    /// timestamp of the first request after the warmup, if known
+    pub warmup_end_timestamp: Option<u64>,
+    pub max_requests: Option<usize>,
+    /// the weight of fetch costs in cost-aware policies
+    pub cost_coefficient: f64,
+    /// how the arrival times are transformed (see [`proj_cache_sim::arrival::ArrivalTransform`])
+    pub arrival: String,
+    #[serde(flatten)]
+    pub simulation: SimulationConfig,
+}
+
+/// The [`SimulationOptions`] of an experiment, except the miss handling which is given per policy.
+#[derive(Serialize, Debug, Clone)]
+pub struct SimulationConfig {
+    pub write_policy: String,
+    pub write_allocate: bool,
+    pub default_ttl: Option<u64>,
+    pub stale_while_revalidate: bool,
+    pub prefetch: String,
+    /// the failure model of the origin, if fetches may fail
+    pub failures: Option<String>,
+    /// the cost model of the fetches (see [`proj_cache_sim::cost::CostModel`])
+    pub fetch_cost: String,
+}
+
+impl From<&SimulationOptions> for SimulationConfig {
+    fn from(options: &SimulationOptions) -> Self {
+        Self {
+            write_policy: options.write_policy.to_string(),
+            write_allocate: options.write_allocate,
+            default_ttl: options.default_ttl,
+            stale_while_revalidate: options.stale_while_revalidate,
+            prefetch: options.prefetch.to_string(),
+            failures: options.failures.as_ref().map(ToString::to_string),
+            fetch_cost: options.fetch_cost.to_string(),
+        }
+    }
+}
+
+/// Statistics of one policy, excluding the warmup requests. The counters of fetches, writes and other events count
+/// the events from the first request after the warmup (see [`SimulationResult::counters_since`]).
+#[derive(Serialize, Debug, Clone)]
+pub struct PolicyStats {
+    pub policy: String,
+    /// how misses to in-flight objects are handled (see [`MissHandling`])
+    pub miss_handling: String,
+    pub num_requests: usize,
+    pub total_latency: u128,
+    pub average_latency: f64,
+    pub num_hits: usize,
+    pub num_delayed_hits: usize,
+    pub num_misses: usize,
+    /// number of fetches from the backing store
+    pub num_loads: usize,
+    /// numbers of writes, deletes and writebacks of dirty objects
+    pub num_writes: usize,
+    pub num_deletes: usize,
+    pub num_writebacks: usize,
+    /// number of hits on expired objects under stale-while-revalidate
+    pub num_stale_hits: usize,
+    /// numbers of prefetches, and of prefetched objects requested before eviction
+    pub num_prefetches: usize,
+    pub num_useful_prefetches: usize,
+    /// numbers of failed read requests, failed fetches and retried fetch attempts
+    pub num_failed_requests: usize,
+    pub num_failed_fetches: usize,
+    pub num_retries: usize,
+    /// total cost of the fetches from the backing store
+    pub total_fetch_cost: f64,
+}
+
+impl PolicyStats {
+    pub fn from_simulation(policy: &str, result: &SimulationResult, warmup: usize) -> Self {
+        let results = &result.results[warmup.min(result.results.len())..];
+        let stats = compute_statistics(results);
+        let counters = match (warmup, results.first()) {
+            (0, _) => result.counters_since(0),
+            (_, Some(first)) => result.counters_since(first.request_timestamp),
+            (_, None) => Counters::default(),
+        };
+        Self {
+            policy: policy.to_string(),
+            miss_handling: MissHandling::default().to_string(),
+            num_requests: results.len(),
+            total_latency: stats.total_latency,
+            average_latency: stats.average_latency,
+            num_hits: stats.num_hits,
+            num_delayed_hits: stats.num_delayed_hits,
+            num_misses: stats.num_misses,
+            num_loads: counters.num_of_loads,
+            num_writes: counters.num_writes,
+            num_deletes: counters.num_deletes,
+            num_writebacks: counters.num_writebacks,
+            num_stale_hits: counters.num_stale_hits,
+            num_prefetches: counters.num_prefetches,
+            num_useful_prefetches: counters.num_useful_prefetches,
+            num_failed_requests: counters.num_failed_requests,
+            num_failed_fetches: counters.num_failed_fetches,
+            num_retries: counters.num_retries,
+            total_fetch_cost: counters.total_fetch_cost,
+        }
+    }
+
+    pub fn with_miss_handling(self, miss_handling: MissHandling) -> Self {
+        Self {
+            miss_handling: miss_handling.to_string(),
+            ..self
+        }
+    }
+
+    /// The policy, with the miss handling if it is not the default.
+    pub fn label(&self) -> String {
+        if self.miss_handling == MissHandling::default().to_string() {
+            self.policy.clone()
+        } else {
+            format!("{} ({})", self.policy, self.miss_handling)
+        }
+    }
+
+    /// Ratio of requests that are not served immediately (misses and delayed hits).
+    pub fn non_hit_ratio(&self) -> f64 {
+        (self.num_requests - self.num_hits) as f64 / self.num_requests as f64
+    }
+}
+
+pub const POLICY_STATS_CSV_HEADER: &str =
+    "policy,miss_handling,num_requests,total_latency,average_latency,num_hits,num_delayed_hits,num_misses,num_loads,num_writes,num_deletes,num_writebacks,num_stale_hits,num_prefetches,num_useful_prefetches,num_failed_requests,num_failed_fetches,num_retries,total_fetch_cost";
+
+impl PolicyStats {
+    pub fn to_csv(&self) -> String {
+        format!(
+            "{},{},{},{},{},{},{},{},{},{},{},{},{},{},{},{},{},{},{}",
+            self.policy,
+            self.miss_handling,
+            self.num_requests,
+            self.total_latency,
+            self.average_latency,
+            self.num_hits,
+            self.num_delayed_hits,
+            self.num_misses,
+            self.num_loads,
+            self.num_writes,
+            self.num_deletes,
+            self.num_writebacks,
+            self.num_stale_hits,
+            self.num_prefetches,
+            self.num_useful_prefetches,
+            self.num_failed_requests,
+            self.num_failed_fetches,
+            self.num_retries,
+            self.total_fetch_cost
+        )
+    }
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct ExperimentReport {
+    pub version: VersionInfo,
+    pub trace: TraceMetadata,
+    pub config: CacheConfig,
+    pub policies: Vec<PolicyStats>,
+}
+
+impl ExperimentReport {
+    /// Relative improvement in average latency of the second policy over the first one, for each miss handling in
+    /// the order of the report. Only the policies simulated under the same miss handling are compared.
+    pub fn improvements(&self) -> Vec<(&str, f64)> {
+        self.miss_handlings()
+            .into_iter()
+            .filter_map(|miss_handling| {
+                let mut policies = self
+                    .policies
+                    .iter()
+                    .filter(|stats| stats.miss_handling == miss_handling);
+                let baseline = policies.next()?;
+                let other = policies.next()?;
+                let improvement =
+                    (baseline.average_latency - other.average_latency) / baseline.average_latency;
+                Some((miss_handling, improvement))
+            })
+            .collect()
+    }
+
+    /// The miss handlings of the policies, in the order of the report.
+    fn miss_handlings(&self) -> Vec<&str> {
+        let mut miss_handlings = Vec::new();
+        for stats in &self.policies {
+            if !miss_handlings.contains(&stats.miss_handling.as_str()) {
+                miss_handlings.push(stats.miss_handling.as_str());
+            }
+        }
+        miss_handlings
+    }
+
+    pub fn write<W: Write>(&self, mut writer: W, format: OutputFormat) -> std::io::Result<()> {
+        match format {
+            OutputFormat::Text => writeln!(writer, "{}", self),
+            OutputFormat::Csv => {
+                writeln!(
+                    writer,
+                    "trace,version,k,c,set_mapping,miss_latency,warmup,{}",
+                    POLICY_STATS_CSV_HEADER
+                )?;
+                for stats in &self.policies {
+                    writeln!(
+                        writer,
+                        "{},{},{},{},{},{},{},{}",
+                        self.trace.path,
+                        self.version,
+                        self.config.cache_counts,
+                        self.config.cache_capacity,
+                        self.config.set_mapping,
+                        self.config.miss_latency,
+                        self.config.warmup,
+                        stats.to_csv()
+                    )?;
+                }
+                Ok(())
+            }
+            OutputFormat::Json => {
+                serde_json::to_writer_pretty(&mut writer, self)?;
+                writeln!(writer)
+            }
+        }
+    }
+}
+
+impl Display for ExperimentReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(
+            f,
+            "k: {}, c: {}, set mapping: {}, miss_latency: {}, write policy: {}{}",
+            self.config.cache_counts,
+            self.config.cache_capacity,
+            self.config.set_mapping,
+            self.config.miss_latency,
+            self.config.simulation.write_policy,
+            if self.config.simulation.write_allocate {
+                " (write-allocate)"
+            } else {
+                ""
+            }
+        )?;
+        if self.config.simulation.prefetch != PrefetchConfig::None.to_string() {
+            writeln!(f, "prefetch: {}", self.config.simulation.prefetch)?;
+        }
+        if self.config.warmup > 0 {
+            write!(
+                f,
+                "warmup: {} requests ({})",
+                self.config.warmup, self.config.warmup_config
+            )?;
+            match self.config.warmup_end_timestamp {
+                Some(timestamp) => writeln!(f, ", ends at {}", timestamp)?,
+                None => writeln!(f, ", all requests")?,
+            }
+        }
+        if self.config.arrival != ArrivalTransform::Original.to_string() {
+            writeln!(f, "arrival: {}", self.config.arrival)?;
+        }
+        if let Some(failures) = &self.config.simulation.failures {
+            writeln!(f, "failures: {}", failures)?;
+        }
+        if self.config.simulation.fetch_cost != CostModel::default().to_string() {
+            writeln!(
+                f,
+                "fetch cost: {}, cost coefficient: {}",
+                self.config.simulation.fetch_cost, self.config.cost_coefficient
+            )?;
+        }
+        if let Some(ttl) = self.config.simulation.default_ttl {
+            writeln!(
+                f,
+                "default ttl: {}{}",
+                ttl,
+                if self.config.simulation.stale_while_revalidate {
+                    " (stale-while-revalidate)"
+                } else {
+                    ""
+                }
+            )?;
+        }
+        for stats in &self.policies {
+            writeln!(
+                f,
+                "total latency ({}): {}",
+                stats.label(),
+                stats.total_latency
+            )?;
+            writeln!(
+                f,
+                "average latency ({}): {}",
+                stats.label(),
+                stats.average_latency
+            )?;
+            writeln!(
+                f,
+                "hits / delayed hits / misses ({}): {} / {} / {}",
+                stats.label(),
+                stats.num_hits,
+                stats.num_delayed_hits,
+                stats.num_misses
+            )?;
+            writeln!(f, "num loads ({}): {}", stats.label(), stats.num_loads)?;
+            writeln!(
+                f,
+                "total fetch cost ({}): {}",
+                stats.label(),
+                stats.total_fetch_cost
+            )?;
+            if stats.num_writes + stats.num_deletes > 0 {
+                writeln!(
+                    f,
+                    "writes / deletes / writebacks ({}): {} / {} / {}",
+                    stats.label(),
+                    stats.num_writes,
+                    stats.num_deletes,
+                    stats.num_writebacks
+                )?;
+            }
+            if stats.num_prefetches > 0 {
+                writeln!(
+                    f,
+                    "prefetches / useful prefetches ({}): {} / {}",
+                    stats.label(),
+                    stats.num_prefetches,
+                    stats.num_useful_prefetches
+                )?;
+            }
+            if stats.num_failed_fetches + stats.num_failed_requests > 0 {
+                writeln!(
+                    f,
+                    "failed requests / failed fetches / retries ({}): {} / {} / {}",
+                    stats.label(),
+                    stats.num_failed_requests,
+                    stats.num_failed_fetches,
+                    stats.num_retries
+                )?;
+            }
+            if stats.num_stale_hits > 0 {
+                writeln!(
+                    f,
+                    "stale hits ({}): {}",
+                    stats.label(),
+                    stats.num_stale_hits
+                )?;
+            }
+        }
+        let improvements = self.improvements();
+        match improvements.as_slice() {
+            [(_, improvement)] => writeln!(f, "improvement (%): {}", improvement * 100.0)?,
+            _ => {
+                for (miss_handling, improvement) in &improvements {
+                    writeln!(
+                        f,
+                        "improvement (%, {}): {}",
+                        miss_handling,
+                        improvement * 100.0
+                    )?;
+                }
+            }
+        }
+        // legacy single-line summary, as used by the CSV files in `results/`. The policies are grouped by miss
+        // handling, so that the baseline and the policy compared with it are next to each other.
+        write!(
+            f,
+            "CSV: {}, {}, {}, {}",
+            self.config.cache_counts,
+            self.config.cache_capacity,
+            self.config.miss_latency,
+            self.config.warmup
+        )?;
+        for miss_handling in self.miss_handlings() {
+            for stats in self
+                .policies
+                .iter()
+                .filter(|stats| stats.miss_handling == miss_handling)
+            {
+                write!(f, ", {}", stats.average_latency)?;
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,229 @@
+//! Parameter sweep over traces, policies, cache geometries and miss latencies, driven by a toml file.
+
+use std::io::Write;
+
+use proj_cache_sim::{
+    arrival::ArrivalTransform,
+    cache::{
+        mapping::SetMapping,
+        registry::{parse_policy_name, PolicyParams, DEFAULT_COST_COEFFICIENT},
+    },
+    io::load_events_file,
+    simulator::SimulationOptions,
+};
+use proj_models::RequestEvent;
+use rayon::prelude::*;
+use serde_derive::{Deserialize, Serialize};
+
+use crate::{
+    report::{CacheConfig, OutputFormat, PolicyStats, VersionInfo, POLICY_STATS_CSV_HEADER},
+    simulate_policy,
+};
+
+/// Example:
+/// ```toml
+/// traces = ["data/net-traces/chicago-lite/processed.events"]
+/// policies = ["lru", "lru-mad"]
+/// latencies = ["3us", "30us", "300us", "3ms", "30ms"]
+/// warmup = 5000000
+/// # optional, defaults to ["hash"]
+/// set_mappings = ["hash", "modulo", "consistent:100"]
+///
+/// [[geometries]]
+/// k = 64
+/// c = 104
+/// ```
+#[derive(Deserialize, Debug, Clone)]
+pub struct SweepConfig {
+    pub traces: Vec<String>,
+    pub policies: Vec<String>,
+    pub geometries: Vec<CacheGeometry>,
+    /// miss latencies with unit (e.g. 300ns, 2ms)
+    pub latencies: Vec<String>,
+    /// set-index functions (see [`SetMapping`])
+    #[serde(default = "default_set_mappings")]
+    pub set_mappings: Vec<String>,
+    #[serde(default)]
+    pub warmup: usize,
+    pub max_requests: Option<usize>,
+}
+
+fn default_set_mappings() -> Vec<String> {
+    vec![SetMapping::Hash.to_string()]
+}
+
+/// A `k`-way set associative cache with `c` lines in each set.
+#[derive(Deserialize, Debug, Clone, Copy)]
+pub struct CacheGeometry {
+    pub k: usize,
+    pub c: usize,
+}
+
+/// The result of one configuration in the sweep.
+#[derive(Serialize, Debug, Clone)]
+pub struct SweepRow {
+    pub trace: String,
+    pub config: CacheConfig,
+    pub stats: PolicyStats,
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct SweepReport {
+    pub version: VersionInfo,
+    pub rows: Vec<SweepRow>,
+}
+
+#[allow(clippy::too_many_arguments)]
+fn run_configuration(
+    trace: &str,
+    requests: &[RequestEvent],
+    policy: &str,
+    geometry: CacheGeometry,
+    set_mapping: SetMapping,
+    miss_latency: u64,
+    warmup: usize,
+    max_requests: Option<usize>,
+) -> SweepRow {
+    let CacheGeometry { k, c } = geometry;
+    let params = PolicyParams {
+        cache_counts: k,
+        cache_capacity: c,
+        estimated_miss_latency: miss_latency,
+        set_mapping,
+        cost_coefficient: DEFAULT_COST_COEFFICIENT,
+    };
+    let stats = simulate_policy(policy, &params, requests, miss_latency, warmup);
+    SweepRow {
+        trace: trace.to_string(),
+        config: CacheConfig {
+            cache_counts: k,
+            cache_capacity: c,
+            set_mapping: set_mapping.to_string(),
+            miss_latency,
+            warmup,
+            warmup_config: warmup.to_string(),
+            warmup_end_timestamp: None,
+            max_requests,
+            cost_coefficient: params.cost_coefficient,
+            arrival: ArrivalTransform::default().to_string(),
+            // `simulate_policy` runs the default delayed-hits model
+            simulation: (&SimulationOptions::default()).into(),
+        },
+        stats,
+    }
+}
+
+/// Run the cartesian product of the configurations in parallel. Each trace is loaded once and shared by all
+/// configurations on it. Rows are returned in the order of traces, geometries, set mappings, latencies and policies.
+pub fn run_sweep(config: &SweepConfig) -> SweepReport {
+    let latencies = config
+        .latencies
+        .iter()
+        .map(|l| {
+            proj_cache_sim::parse_time_unit(l)
+                .unwrap_or_else(|e| panic!("Invalid latency {:?}: {}", l, e))
+        })
+        .collect::<Vec<_>>();
+    let set_mappings = config
+        .set_mappings
+        .iter()
+        .map(|m| m.parse::<SetMapping>().unwrap())
+        .collect::<Vec<_>>();
+    // fail early instead of after loading the traces
+    for policy in &config.policies {
+        parse_policy_name(policy).unwrap();
+    }
+
+    let mut rows = Vec::new();
+    for trace in &config.traces {
+        let requests = load_events_file(trace)
+            .take(config.max_requests.unwrap_or(usize::MAX))
+            .collect::<Vec<_>>();
+        let (set_mappings, latencies) = (&set_mappings, &latencies);
+        let configurations = config
+            .geometries
+            .iter()
+            .flat_map(|&geometry| {
+                set_mappings.iter().flat_map(move |&set_mapping| {
+                    latencies.iter().flat_map(move |&miss_latency| {
+                        config
+                            .policies
+                            .iter()
+                            .map(move |policy| (geometry, set_mapping, miss_latency, policy))
+                    })
+                })
+            })
+            .collect::<Vec<_>>();
+        rows.par_extend(configurations.into_par_iter().map(
+            |(geometry, set_mapping, miss_latency, policy)| {
+                run_configuration(
+                    trace,
+                    &requests,
+                    policy,
+                    geometry,
+                    set_mapping,
+                    miss_latency,
+                    config.warmup,
+                    config.max_requests,
+                )
+            },
+        ));
+    }
+    SweepReport {
+        version: VersionInfo::current(),
+        rows,
+    }
+}
+
+impl SweepReport {
+    pub fn write<W: Write>(&self, mut writer: W, format: OutputFormat) -> std::io::Result<()> {
+        match format {
+            OutputFormat::Text => {
+                writeln!(writer, "version: {}", self.version)?;
+                for row in &self.rows {
+                    writeln!(
+                        writer,
+                        "{}: {}, k: {}, c: {}, set mapping: {}, miss_latency: {}, warmup: {}, average latency: {}, hits / delayed hits / misses: {} / {} / {}",
+                        row.trace,
+                        row.stats.policy,
+                        row.config.cache_counts,
+                        row.config.cache_capacity,
+                        row.config.set_mapping,
+                        row.config.miss_latency,
+                        row.config.warmup,
+                        row.stats.average_latency,
+                        row.stats.num_hits,
+                        row.stats.num_delayed_hits,
+                        row.stats.num_misses
+                    )?;
+                }
+            }
+            OutputFormat::Csv => {
+                writeln!(
+                    writer,
+                    "trace,version,k,c,set_mapping,miss_latency,warmup,{}",
+                    POLICY_STATS_CSV_HEADER
+                )?;
+                for row in &self.rows {
+                    writeln!(
+                        writer,
+                        "{},{},{},{},{},{},{},{}",
+                        row.trace,
+                        self.version,
+                        row.config.cache_counts,
+                        row.config.cache_capacity,
+                        row.config.set_mapping,
+                        row.config.miss_latency,
+                        row.config.warmup,
+                        row.stats.to_csv()
+                    )?;
+                }
+            }
+            OutputFormat::Json => {
+                serde_json::to_writer_pretty(&mut writer, self)?;
+                writeln!(writer)?;
+            }
+        }
+        Ok(())
+    }
+}
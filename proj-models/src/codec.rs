@@ -179,6 +179,26 @@ impl_codec_for_primitive!(u8);
 impl_codec_for_primitive!(u16);
 impl_codec_for_primitive!(u32);
 impl_codec_for_primitive!(u64);
+impl_codec_for_primitive!(f64);
+
+/// No bytes, e.g. for caches storing no values.
+impl Codec for () {
+    type Deserialized = ();
+
+    const SIZE_IN_BYTES: CodecSize = CodecSize::Static(0);
+
+    fn size_in_bytes(&self) -> usize {
+        0
+    }
+
+    fn to_bytes<W: Write>(&self, _writer: W) -> std::io::Result<()> {
+        Ok(())
+    }
+
+    fn from_bytes<R: Read>(_reader: R) -> std::io::Result<Self::Deserialized> {
+        Ok(())
+    }
+}
 
 impl<T: Codec> Codec for [T] {
     type Deserialized = Vec<T::Deserialized>;
@@ -304,6 +324,7 @@ mod tests {
     impl_codec!(TestStruct4, a, u64, b, u64, c, Vec<u64>, d, u64);
 
     #[test]
+    #[allow(clippy::unnecessary_cast)]
     fn test_repeat_read_write() {
         let lst = (0..100)
             .map(|i| TestStruct { a: i, b: i as u32 })
@@ -11,6 +11,161 @@ pub struct RequestEvent {
 
 impl_codec!(RequestEvent, key, RequestId, timestamp, TimeUnit);
 
+/// The operation of a request. Traces without operations (`.events` files) only contain reads.
+#[derive(Debug, Eq, PartialEq, Clone, Copy, Hash, Default)]
+pub enum Operation {
+    #[default]
+    Read,
+    Write,
+    /// Delete or invalidate the object.
+    Delete,
+}
+
+impl Operation {
+    fn to_u8(self) -> u8 {
+        match self {
+            Self::Read => 0,
+            Self::Write => 1,
+            Self::Delete => 2,
+        }
+    }
+
+    fn from_u8(v: u8) -> std::io::Result<Self> {
+        match v {
+            0 => Ok(Self::Read),
+            1 => Ok(Self::Write),
+            2 => Ok(Self::Delete),
+            _ => Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                format!("invalid operation: {}", v),
+            )),
+        }
+    }
+}
+
+impl codec::Codec for Operation {
+    type Deserialized = Self;
+
+    const SIZE_IN_BYTES: codec::CodecSize = u8::SIZE_IN_BYTES;
+
+    fn size_in_bytes(&self) -> usize {
+        self.to_u8().size_in_bytes()
+    }
+
+    fn to_bytes<W: std::io::Write>(&self, writer: W) -> std::io::Result<()> {
+        self.to_u8().to_bytes(writer)
+    }
+
+    fn from_bytes<R: std::io::Read>(reader: R) -> std::io::Result<Self::Deserialized> {
+        Self::from_u8(u8::from_bytes(reader)?)
+    }
+}
+
+/// A request with its operation, stored in `.opevents` files.
+#[derive(Debug, Eq, PartialEq, Clone)]
+pub struct OpRequestEvent {
+    pub key: RequestId,
+    pub timestamp: TimeUnit,
+    pub op: Operation,
+    /// Time to live of the object fetched or written by this request, overriding the default TTL of the simulation.
+    /// It is stored as `0` when there is none, so a TTL of `0` is read back as `None`.
+    pub ttl: Option<TimeUnit>,
+}
+
+/// Same record as `impl_codec!(OpRequestEvent, key, RequestId, timestamp, TimeUnit, op, Operation, ttl, TimeUnit)`,
+/// with `0` for no TTL.
+impl codec::Codec for OpRequestEvent {
+    type Deserialized = Self;
+
+    const SIZE_IN_BYTES: codec::CodecSize = RequestId::SIZE_IN_BYTES
+        .add_const(TimeUnit::SIZE_IN_BYTES)
+        .add_const(Operation::SIZE_IN_BYTES)
+        .add_const(TimeUnit::SIZE_IN_BYTES);
+
+    fn size_in_bytes(&self) -> usize {
+        self.key.size_in_bytes()
+            + self.timestamp.size_in_bytes()
+            + self.op.size_in_bytes()
+            + self.ttl.unwrap_or(0).size_in_bytes()
+    }
+
+    fn to_bytes<W: std::io::Write>(&self, mut writer: W) -> std::io::Result<()> {
+        self.key.to_bytes(&mut writer)?;
+        self.timestamp.to_bytes(&mut writer)?;
+        self.op.to_bytes(&mut writer)?;
+        self.ttl.unwrap_or(0).to_bytes(&mut writer)
+    }
+
+    fn from_bytes<R: std::io::Read>(mut reader: R) -> std::io::Result<Self::Deserialized> {
+        Ok(Self {
+            key: RequestId::from_bytes(&mut reader)?,
+            timestamp: TimeUnit::from_bytes(&mut reader)?,
+            op: Operation::from_bytes(&mut reader)?,
+            ttl: Some(TimeUnit::from_bytes(&mut reader)?).filter(|&ttl| ttl > 0),
+        })
+    }
+}
+
+impl From<RequestEvent> for OpRequestEvent {
+    fn from(RequestEvent { key, timestamp }: RequestEvent) -> Self {
+        Self {
+            key,
+            timestamp,
+            op: Operation::Read,
+            ttl: None,
+        }
+    }
+}
+
+impl From<&RequestEvent> for OpRequestEvent {
+    fn from(event: &RequestEvent) -> Self {
+        event.clone().into()
+    }
+}
+
 pub type RequestId = u64;
 /// timestamp in specified unit (ns)
 pub type TimeUnit = u64;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use codec::Codec;
+
+    #[test]
+    fn test_op_request_event_codec() {
+        let events = [Operation::Read, Operation::Write, Operation::Delete]
+            .iter()
+            .enumerate()
+            .map(|(i, &op)| OpRequestEvent {
+                key: i as u64,
+                timestamp: 10 * i as u64,
+                op,
+                ttl: if i == 1 { Some(5) } else { None },
+            })
+            .collect::<Vec<_>>();
+        let mut buf = Vec::new();
+        OpRequestEvent::repeat_write_till_end(&mut buf, &events).unwrap();
+        assert_eq!(buf.len(), 3 * 25);
+        let decoded = OpRequestEvent::repeat_read_till_end(std::io::Cursor::new(buf))
+            .map(|r| r.unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(decoded, events);
+
+        assert!(Operation::from_bytes(&[3u8][..]).is_err());
+    }
+
+    #[test]
+    fn test_op_request_event_zero_ttl_is_none() {
+        let event = OpRequestEvent {
+            key: 1,
+            timestamp: 10,
+            op: Operation::Write,
+            ttl: Some(0),
+        };
+        let mut buf = Vec::new();
+        event.to_bytes(&mut buf).unwrap();
+        let decoded = OpRequestEvent::from_bytes(&buf[..]).unwrap();
+        assert_eq!(decoded, OpRequestEvent { ttl: None, ..event });
+    }
+}
@@ -3,6 +3,8 @@
 
 use std::{io::BufRead, time::Duration};
 
+use proj_models::Operation;
+
 use crate::models::RawRequestWithTimestamp;
 
 fn line_to_request(line: &str) -> Option<RawRequestWithTimestamp<u128>> {
@@ -18,6 +20,7 @@ fn line_to_request(line: &str) -> Option<RawRequestWithTimestamp<u128>> {
     Some(RawRequestWithTimestamp {
         request: object_id,
         timestamp,
+        op: Operation::Read,
     })
 }
 
@@ -4,6 +4,7 @@ mod ms_prod_parser;
 mod msr_storage_parser;
 mod pcap_parser;
 mod post_process;
+mod synthetic;
 use std::{
     io::{BufReader, Write},
     path::PathBuf,
@@ -11,7 +12,7 @@ use std::{
 };
 
 use clap::{Parser, Subcommand};
-use post_process::post_process_requests;
+use post_process::{post_process_requests, post_process_requests_with_ops};
 
 fn process_pcaps(path: &str) {
     let toml_str = std::fs::read_to_string(path).unwrap();
@@ -48,22 +49,39 @@ fn process_pcaps(path: &str) {
     writer.flush().unwrap();
 }
 
-fn process_msr_storage(name: &str) {
+/// Write the processed requests to `<output_base>.events`, or with the operations to `<output_base>.opevents` if
+/// `with_ops` is set.
+fn write_processed_requests<K, I>(requests: I, output_base: PathBuf, with_ops: bool)
+where
+    K: Eq + std::hash::Hash + Clone,
+    I: IntoIterator<Item = models::RawRequestWithTimestamp<K>>,
+{
+    let mut output_path = output_base.into_os_string();
+    output_path.push(if with_ops { ".opevents" } else { ".events" });
+    let output_file = std::fs::File::create(output_path).unwrap();
+    let mut writer = std::io::BufWriter::new(output_file);
+    if with_ops {
+        post_process_requests_with_ops(requests, &mut writer).unwrap();
+    } else {
+        post_process_requests(requests, &mut writer).unwrap();
+    }
+    writer.flush().unwrap();
+}
+
+fn process_msr_storage(name: &str, with_ops: bool) {
     let data_root = PathBuf::from_str("data").unwrap().join("msr_cambridge");
     let csv_path = data_root
         .join("raw")
         .join("MSR-Cambridge-2")
         .join(format!("{}.csv", name));
-    let output_path = data_root.join(format!("{}.processed.events", name));
+    let output_base = data_root.join(format!("{}.processed", name));
 
-    let reader = std::fs::File::open(&csv_path).expect(&format!("Cannot open file {:?}", csv_path));
+    let reader = std::fs::File::open(&csv_path)
+        .unwrap_or_else(|_| panic!("Cannot open file {:?}", csv_path));
     let reader = std::io::BufReader::new(reader);
     let raw_requests = msr_storage_parser::read_msr_cambridge_requests(reader);
 
-    let output_file = std::fs::File::create(output_path).unwrap();
-    let mut writer = std::io::BufWriter::new(output_file);
-    post_process_requests(raw_requests, &mut writer).unwrap();
-    writer.flush().unwrap();
+    write_processed_requests(raw_requests, output_base, with_ops);
 }
 
 fn process_cdn_traces() {
@@ -71,8 +89,8 @@ fn process_cdn_traces() {
     let trace_path = data_root.join("cdn_long.csv");
     let output_path = data_root.join("cdn_long.processed.events");
 
-    let reader =
-        std::fs::File::open(&trace_path).expect(&format!("Cannot open file {:?}", trace_path));
+    let reader = std::fs::File::open(&trace_path)
+        .unwrap_or_else(|_| panic!("Cannot open file {:?}", trace_path));
     let reader = std::io::BufReader::new(reader);
     let raw_requests = cdn_parser::read_cdn_requests(reader);
 
@@ -82,22 +100,34 @@ fn process_cdn_traces() {
     writer.flush().unwrap();
 }
 
-fn process_ms_prod_traces() {
+fn process_ms_prod_traces(with_ops: bool) {
     let data_root = PathBuf::from_str("data").unwrap().join("ms_prod");
     // enumerate all files in the directory
-    let paths = std::fs::read_dir(&data_root.join("BuildServer").join("Traces"))
+    let paths = std::fs::read_dir(data_root.join("BuildServer").join("Traces"))
         .unwrap()
         .map(|entry| entry.unwrap().path())
         .collect::<Vec<_>>();
     let raw_requests = ms_prod_parser::parse_files(paths);
 
-    let output_path = data_root.join("processed.events");
-    let output_file = std::fs::File::create(output_path).unwrap();
+    write_processed_requests(raw_requests, data_root.join("processed"), with_ops);
+}
+
+fn generate_synthetic_trace(path: &str) {
+    let toml_str = std::fs::read_to_string(path).unwrap();
+    let config: models::SyntheticTraceConfig = toml::from_str(&toml_str).unwrap();
+
+    let output_path = PathBuf::from(path.trim_end_matches(".toml").to_string() + ".events");
+    let output_file = std::fs::File::create(&output_path).unwrap();
     let mut writer = std::io::BufWriter::new(output_file);
-    post_process_requests(raw_requests, &mut writer).unwrap();
+    synthetic::write_synthetic_trace(&config, &mut writer).unwrap();
     writer.flush().unwrap();
+    println!(
+        "{} synthetic requests are written to {:?}",
+        config.num_requests, output_path
+    );
 }
 
+#[allow(clippy::enum_variant_names)]
 #[derive(Debug, Clone, Subcommand)]
 enum SubArgs {
     NetTraces {
@@ -109,9 +139,22 @@ enum SubArgs {
         // positional command
         #[clap(required = true, help = "the trace name")]
         name: String,
+        #[clap(long, help = "keep reads and writes, in a .opevents file")]
+        with_ops: bool,
     },
     CdnTraces,
-    MsProdTraces,
+    MsProdTraces {
+        #[clap(long, help = "keep reads and writes, in a .opevents file")]
+        with_ops: bool,
+    },
+    SyntheticTraces {
+        // positional command
+        #[clap(
+            required = true,
+            help = "Path to the toml file of the synthetic trace model"
+        )]
+        path: String,
+    },
 }
 
 #[derive(Debug, Clone, Parser)]
@@ -126,14 +169,17 @@ fn main() {
         SubArgs::NetTraces { path } => {
             process_pcaps(&path);
         }
-        SubArgs::MsrTraces { name } => {
-            process_msr_storage(&name);
+        SubArgs::MsrTraces { name, with_ops } => {
+            process_msr_storage(&name, with_ops);
         }
         SubArgs::CdnTraces => {
             process_cdn_traces();
         }
-        SubArgs::MsProdTraces => {
-            process_ms_prod_traces();
+        SubArgs::MsProdTraces { with_ops } => {
+            process_ms_prod_traces(with_ops);
+        }
+        SubArgs::SyntheticTraces { path } => {
+            generate_synthetic_trace(&path);
         }
     }
 }
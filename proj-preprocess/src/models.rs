@@ -1,22 +1,76 @@
 use std::time::Duration;
 
+use proj_models::Operation;
 use serde_derive::Deserialize;
 
 #[derive(Deserialize, Debug, Clone)]
 pub struct NetTraceExperimentConfig {
+    #[allow(unused)]
     pub url: String,
     pub prefix: String,
     pub traces: Vec<String>,
 }
 
+#[derive(Deserialize, Debug, Clone)]
+pub struct SyntheticTraceConfig {
+    pub seed: u64,
+    pub num_requests: usize,
+    pub popularity: PopularityConfig,
+    pub arrival: ArrivalConfig,
+    pub churn: Option<ChurnConfig>,
+}
+
+/// Zipf popularity over `num_keys` keys: the key at rank `r` is requested with probability proportional to `r^-alpha`.
+#[derive(Deserialize, Debug, Clone)]
+pub struct PopularityConfig {
+    pub num_keys: u64,
+    pub alpha: f64,
+}
+
+/// Rates are in requests per second.
+#[derive(Deserialize, Debug, Clone)]
+#[serde(tag = "type", rename_all = "kebab-case")]
+pub enum ArrivalConfig {
+    Poisson {
+        rate: f64,
+    },
+    /// Poisson arrivals with `rate` during ON periods and no arrivals during OFF periods. The durations of the periods
+    /// are exponentially distributed.
+    OnOff {
+        rate: f64,
+        on_duration_ns: u64,
+        off_duration_ns: u64,
+    },
+    /// A self-exciting (Hawkes) process: each arrival triggers on average `branching_ratio` more arrivals, spread
+    /// exponentially with mean `decay_ns`. The long-run rate is `base_rate / (1 - branching_ratio)`.
+    Hawkes {
+        base_rate: f64,
+        branching_ratio: f64,
+        decay_ns: u64,
+    },
+}
+
+/// Every `interval_ns`, the keys at `fraction` of the ranks are replaced with fresh keys.
+#[derive(Deserialize, Debug, Clone)]
+pub struct ChurnConfig {
+    pub interval_ns: u64,
+    pub fraction: f64,
+}
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub struct RawRequestWithTimestamp<T> {
     pub request: T,
     pub timestamp: Duration,
+    /// Only storage traces have operations other than reads.
+    pub op: Operation,
 }
 
 impl<T> From<(T, Duration)> for RawRequestWithTimestamp<T> {
     fn from((request, timestamp): (T, Duration)) -> Self {
-        Self { request, timestamp }
+        Self {
+            request,
+            timestamp,
+            op: Operation::Read,
+        }
     }
 }
@@ -1,6 +1,7 @@
 use std::{collections::BTreeMap, io::Read, path::PathBuf, time::Duration};
 
 use lazy_static::lazy_static;
+use proj_models::Operation;
 
 use crate::models::RawRequestWithTimestamp;
 
@@ -57,23 +58,29 @@ pub fn parse_lines<R: Read>(
         .records()
         .filter_map(|record| {
             let record = record.ok()?;
-            match record.get(0) {
-                Some("DiskRead") | Some("DiskWrite") => {
+            let op = match record.get(0) {
+                Some("DiskRead") => Some(Operation::Read),
+                Some("DiskWrite") => Some(Operation::Write),
+                _ => None,
+            };
+            match op {
+                Some(op) => {
                     let mut timestamp_us = record
                         .get(1)?
                         .parse::<u64>()
                         .expect("failed to parse timestamp to u64");
                     timestamp_us += start_time_us;
-                    let timestamp = Duration::from_micros(timestamp_us as u64);
+                    let timestamp = Duration::from_micros(timestamp_us);
                     let irp_ptr =
                         u64::from_str_radix(record.get(4)?.strip_prefix("0x")?, 16).ok()?;
                     let disk_num = record.get(8)?.parse().ok()?;
                     Some(RawRequestWithTimestamp {
                         request: (irp_ptr, disk_num),
                         timestamp,
+                        op,
                     })
                 }
-                _ => None,
+                None => None,
             }
         })
         .collect()
@@ -144,7 +151,8 @@ mod tests {
             events[0],
             RawRequestWithTimestamp {
                 request: (0xfffffadf39860010, 4),
-                timestamp: Duration::from_micros(260959 + 17)
+                timestamp: Duration::from_micros(260959 + 17),
+                op: Operation::Read,
             }
         );
 
@@ -152,7 +160,8 @@ mod tests {
             events[1],
             RawRequestWithTimestamp {
                 request: (0xfffffadf3b9ca010, 4),
-                timestamp: Duration::from_micros(261233 + 17)
+                timestamp: Duration::from_micros(261233 + 17),
+                op: Operation::Read,
             }
         );
 
@@ -160,8 +169,22 @@ mod tests {
             events[2],
             RawRequestWithTimestamp {
                 request: (0xfffffadf39c0a930, 5),
-                timestamp: Duration::from_micros(263262 + 17)
+                timestamp: Duration::from_micros(263262 + 17),
+                op: Operation::Read,
+            }
+        );
+
+        let write = events
+            .iter()
+            .find(|e| e.op == Operation::Write)
+            .expect("the sample has writes");
+        assert_eq!(
+            *write,
+            RawRequestWithTimestamp {
+                request: (0xfffffadf3a2dd010, 0),
+                timestamp: Duration::from_micros(277804 + 17),
+                op: Operation::Write,
             }
-        )
+        );
     }
 }
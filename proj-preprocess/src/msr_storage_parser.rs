@@ -1,5 +1,7 @@
 use std::time::Duration;
 
+use proj_models::Operation;
+
 use crate::models::RawRequestWithTimestamp;
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
@@ -19,7 +21,11 @@ fn parse_line(line: &str) -> Option<RawRequestWithTimestamp<MsrBlockIoRequest>>
     let timestamp = Duration::new(second, nsec as u32);
     let _host_name = it.next()?; // host name
     let disk_num = it.next()?.parse::<u32>().ok()?;
-    let _op = it.next()?; // operation
+    let op = match it.next()? {
+        "Read" => Operation::Read,
+        "Write" => Operation::Write,
+        _ => return None,
+    };
     let offset = it.next()?.parse::<u64>().ok()?;
     let _size = it.next()?;
     let _latency = it.next()?;
@@ -27,14 +33,12 @@ fn parse_line(line: &str) -> Option<RawRequestWithTimestamp<MsrBlockIoRequest>>
     Some(RawRequestWithTimestamp {
         request: MsrBlockIoRequest { offset, disk_num },
         timestamp,
+        op,
     })
 }
 
 pub fn read_msr_cambridge_requests<R: std::io::BufRead>(
     reader: R,
 ) -> impl Iterator<Item = RawRequestWithTimestamp<MsrBlockIoRequest>> {
-    reader
-        .lines()
-        .map(|line| parse_line(&line.unwrap()))
-        .filter_map(|x| x)
+    reader.lines().filter_map(|line| parse_line(&line.unwrap()))
 }
@@ -1,49 +1,80 @@
 use std::{hash::Hash, io::Write};
 
 use ahash::AHashMap;
-use proj_models::{codec::Codec, RequestEvent};
+use proj_models::{codec::Codec, OpRequestEvent, RequestEvent};
 
 use crate::models::RawRequestWithTimestamp;
 
-/// remap the requests objects to [`proj_models::RequestEvent`] objects, and represent the time in nanoseconds, and serialize them to a byte stream.
-pub fn post_process_requests<K, I, W>(requests: I, mut writer: W) -> std::io::Result<()>
+/// remap the request objects to consecutive ids, and represent the time in nanoseconds relative to the first request.
+/// Requests not in order are dropped.
+fn remap_requests<K, I>(requests: I) -> impl Iterator<Item = OpRequestEvent>
 where
     K: Eq + Hash + Clone,
     I: IntoIterator<Item = RawRequestWithTimestamp<K>>,
-    W: Write,
 {
     let mut map = AHashMap::new();
     let mut next_object_id: u64 = 0;
     let mut relative_time: u64 = 0;
     let mut last_timestamp: Option<u128> = None;
-    for RawRequestWithTimestamp { request, timestamp } in requests {
-        let timestamp = timestamp.as_nanos();
-        if let Some(last) = last_timestamp {
-            if timestamp < last {
-                eprintln!(
-                    "Warning: event not in order is ignored: the event at timestamp {} is earlier than the last request at timestamp {}",
-                    timestamp, last
-                );
-                continue;
-            } else {
-                relative_time += (timestamp - last as u128) as u64;
+    requests.into_iter().filter_map(
+        move |RawRequestWithTimestamp {
+                  request,
+                  timestamp,
+                  op,
+              }| {
+            let timestamp = timestamp.as_nanos();
+            if let Some(last) = last_timestamp {
+                if timestamp < last {
+                    eprintln!(
+                        "Warning: event not in order is ignored: the event at timestamp {} is earlier than the last request at timestamp {}",
+                        timestamp, last
+                    );
+                    return None;
+                } else {
+                    relative_time += (timestamp - last) as u64;
+                }
             }
-        }
-        last_timestamp = Some(timestamp);
-        let remapped = map
-            .entry(request)
-            .or_insert_with(|| {
+            last_timestamp = Some(timestamp);
+            let remapped = *map.entry(request).or_insert_with(|| {
                 let id = next_object_id;
                 next_object_id += 1;
                 id
+            });
+
+            Some(OpRequestEvent {
+                key: remapped,
+                timestamp: relative_time,
+                op,
+                ttl: None,
             })
-            .clone();
+        },
+    )
+}
 
-        let request_event = RequestEvent {
-            key: remapped,
-            timestamp: relative_time,
-        };
+/// remap the requests objects to [`proj_models::RequestEvent`] objects, and represent the time in nanoseconds, and serialize them to a byte stream.
+/// The operations are dropped, so every request is a read.
+pub fn post_process_requests<K, I, W>(requests: I, mut writer: W) -> std::io::Result<()>
+where
+    K: Eq + Hash + Clone,
+    I: IntoIterator<Item = RawRequestWithTimestamp<K>>,
+    W: Write,
+{
+    for OpRequestEvent { key, timestamp, .. } in remap_requests(requests) {
+        RequestEvent { key, timestamp }.to_bytes(&mut writer)?;
+    }
+
+    Ok(())
+}
 
+/// Same as [`post_process_requests`], but keep the operations and serialize [`proj_models::OpRequestEvent`] objects
+/// (a `.opevents` file).
+pub fn post_process_requests_with_ops<K, I, W>(requests: I, mut writer: W) -> std::io::Result<()>
+where
+    K: Eq + Hash + Clone,
+    I: IntoIterator<Item = RawRequestWithTimestamp<K>>,
+    W: Write,
+{
+    for request_event in remap_requests(requests) {
         request_event.to_bytes(&mut writer)?;
     }
 
@@ -54,6 +85,8 @@ where
 mod tests {
     use std::{io::Cursor, time::Duration};
 
+    use proj_models::Operation;
+
     use super::*;
 
     #[test]
@@ -68,10 +101,7 @@ mod tests {
             ("a", 7),
         ]
         .iter()
-        .map(|(k, t)| RawRequestWithTimestamp {
-            request: k.to_string(),
-            timestamp: Duration::from_secs(*t),
-        });
+        .map(|(k, t)| RawRequestWithTimestamp::from((k.to_string(), Duration::from_secs(*t))));
 
         let mut buffer = Vec::new();
         post_process_requests(requests, &mut buffer).unwrap();
@@ -92,4 +122,46 @@ mod tests {
                 .collect::<Vec<_>>()
         )
     }
+
+    #[test]
+    fn test_post_process_requests_with_ops() {
+        let requests = [
+            ("a", 1, Operation::Read),
+            ("b", 2, Operation::Write),
+            ("a", 3, Operation::Delete),
+            ("c", 2, Operation::Write),
+            ("b", 4, Operation::Read),
+        ]
+        .iter()
+        .map(|&(k, t, op)| RawRequestWithTimestamp {
+            request: k.to_string(),
+            timestamp: Duration::from_secs(t),
+            op,
+        });
+
+        let mut buffer = Vec::new();
+        post_process_requests_with_ops(requests, &mut buffer).unwrap();
+
+        let processed = OpRequestEvent::repeat_read_till_end(Cursor::new(buffer))
+            .map(|x| x.unwrap())
+            .collect::<Vec<_>>();
+
+        assert_eq!(
+            processed,
+            [
+                (0, 0, Operation::Read),
+                (1, 1, Operation::Write),
+                (0, 2, Operation::Delete),
+                (1, 3, Operation::Read)
+            ]
+            .iter()
+            .map(|&(key, t, op)| OpRequestEvent {
+                key,
+                timestamp: t * 1_000_000_000,
+                op,
+                ttl: None
+            })
+            .collect::<Vec<_>>()
+        )
+    }
 }
@@ -0,0 +1,306 @@
+//! Generate synthetic traces from configurable popularity and arrival models.
+
+use std::io::Write;
+
+use proj_models::{codec::Codec, RequestEvent};
+use rand::{seq::SliceRandom as _, Rng as _, SeedableRng as _};
+use rand_xorshift::XorShiftRng;
+
+use crate::models::{ArrivalConfig, ChurnConfig, SyntheticTraceConfig};
+
+/// Sample an exponential inter-arrival time (in ns) with the given rate (per ns).
+fn exponential(rng: &mut XorShiftRng, rate: f64) -> f64 {
+    -(1. - rng.gen::<f64>()).ln() / rate
+}
+
+/// State of the arrival process. Rates are in requests per nanosecond.
+enum ArrivalProcess {
+    Poisson {
+        rate: f64,
+    },
+    OnOff {
+        rate: f64,
+        mean_on: f64,
+        mean_off: f64,
+        /// The end of the current ON period.
+        on_until: f64,
+    },
+    /// A self-exciting process with intensity `base_rate + sum(branching_ratio * decay * exp(-decay * (t - t_i)))`,
+    /// simulated by Ogata's thinning.
+    Hawkes {
+        base_rate: f64,
+        branching_ratio: f64,
+        decay: f64,
+        /// The excited intensity at the current time.
+        excitation: f64,
+    },
+}
+
+impl ArrivalProcess {
+    fn new(config: &ArrivalConfig, rng: &mut XorShiftRng) -> Self {
+        match *config {
+            ArrivalConfig::Poisson { rate } => Self::Poisson { rate: rate / 1e9 },
+            ArrivalConfig::OnOff {
+                rate,
+                on_duration_ns,
+                off_duration_ns,
+            } => Self::OnOff {
+                rate: rate / 1e9,
+                mean_on: on_duration_ns as f64,
+                mean_off: off_duration_ns as f64,
+                on_until: exponential(rng, 1. / on_duration_ns as f64),
+            },
+            ArrivalConfig::Hawkes {
+                base_rate,
+                branching_ratio,
+                decay_ns,
+            } => {
+                assert!(
+                    (0. ..1.).contains(&branching_ratio),
+                    "branching ratio should be in [0, 1) for a stationary Hawkes process"
+                );
+                Self::Hawkes {
+                    base_rate: base_rate / 1e9,
+                    branching_ratio,
+                    decay: 1. / decay_ns as f64,
+                    excitation: 0.,
+                }
+            }
+        }
+    }
+
+    /// Get the time of the next arrival after `time`.
+    fn next_arrival(&mut self, time: f64, rng: &mut XorShiftRng) -> f64 {
+        match self {
+            Self::Poisson { rate } => time + exponential(rng, *rate),
+            Self::OnOff {
+                rate,
+                mean_on,
+                mean_off,
+                on_until,
+            } => {
+                let mut time = time;
+                loop {
+                    let next = time + exponential(rng, *rate);
+                    if next < *on_until {
+                        return next;
+                    }
+                    // skip the OFF period and start a new ON period. This is exact as arrivals are memoryless.
+                    time = *on_until + exponential(rng, 1. / *mean_off);
+                    *on_until = time + exponential(rng, 1. / *mean_on);
+                }
+            }
+            Self::Hawkes {
+                base_rate,
+                branching_ratio,
+                decay,
+                excitation,
+            } => {
+                let mut time = time;
+                loop {
+                    // the intensity only decays until the next arrival, so the current intensity is an upper bound.
+                    let upper = *base_rate + *excitation;
+                    let wait = exponential(rng, upper);
+                    time += wait;
+                    *excitation *= (-*decay * wait).exp();
+                    if rng.gen::<f64>() * upper <= *base_rate + *excitation {
+                        *excitation += *branching_ratio * *decay;
+                        return time;
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// An infinite stream of synthetic requests. Keys are drawn from a Zipf distribution over ranks, and the key at each
+/// rank can be replaced by a fresh key over time to model the churn of the popular set.
+pub struct SyntheticTrace {
+    rng: XorShiftRng,
+    /// cumulative probability of each rank
+    popularity_cdf: Vec<f64>,
+    rank_to_key: Vec<u64>,
+    next_fresh_key: u64,
+    arrival: ArrivalProcess,
+    churn: Option<ChurnConfig>,
+    next_churn: f64,
+    time: f64,
+}
+
+impl SyntheticTrace {
+    pub fn new(config: &SyntheticTraceConfig) -> Self {
+        let mut rng = XorShiftRng::seed_from_u64(config.seed);
+        let num_keys = config.popularity.num_keys;
+        assert!(num_keys > 0, "num_keys should be greater than 0");
+
+        let mut popularity_cdf = (1..=num_keys)
+            .scan(0., |sum, r| {
+                *sum += (r as f64).powf(-config.popularity.alpha);
+                Some(*sum)
+            })
+            .collect::<Vec<_>>();
+        let total = *popularity_cdf.last().unwrap();
+        popularity_cdf.iter_mut().for_each(|p| *p /= total);
+
+        // shuffle so that key ids are not correlated with popularity
+        let mut rank_to_key = (0..num_keys).collect::<Vec<_>>();
+        rank_to_key.shuffle(&mut rng);
+
+        let arrival = ArrivalProcess::new(&config.arrival, &mut rng);
+        let next_churn = config
+            .churn
+            .as_ref()
+            .map_or(f64::INFINITY, |c| c.interval_ns as f64);
+
+        Self {
+            rng,
+            popularity_cdf,
+            rank_to_key,
+            next_fresh_key: num_keys,
+            arrival,
+            churn: config.churn.clone(),
+            next_churn,
+            time: 0.,
+        }
+    }
+
+    /// Replace the keys at randomly chosen ranks with fresh keys.
+    fn churn(&mut self, fraction: f64) {
+        let count = (fraction * self.rank_to_key.len() as f64).round() as usize;
+        for _ in 0..count {
+            let rank = self.rng.gen_range(0..self.rank_to_key.len());
+            self.rank_to_key[rank] = self.next_fresh_key;
+            self.next_fresh_key += 1;
+        }
+    }
+}
+
+impl Iterator for SyntheticTrace {
+    type Item = RequestEvent;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.time = self.arrival.next_arrival(self.time, &mut self.rng);
+        while self.time >= self.next_churn {
+            let churn = self.churn.as_ref().unwrap();
+            let fraction = churn.fraction;
+            self.next_churn += churn.interval_ns as f64;
+            self.churn(fraction);
+        }
+
+        let u = self.rng.gen::<f64>();
+        let rank = self
+            .popularity_cdf
+            .partition_point(|&p| p < u)
+            .min(self.popularity_cdf.len() - 1);
+        Some(RequestEvent {
+            key: self.rank_to_key[rank],
+            timestamp: self.time as u64,
+        })
+    }
+}
+
+/// Generate `config.num_requests` synthetic requests and serialize them to a byte stream.
+pub fn write_synthetic_trace<W: Write>(
+    config: &SyntheticTraceConfig,
+    mut writer: W,
+) -> std::io::Result<()> {
+    for event in SyntheticTrace::new(config).take(config.num_requests) {
+        event.to_bytes(&mut writer)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use ahash::{AHashMap, AHashSet};
+
+    use super::*;
+    use crate::models::PopularityConfig;
+
+    fn config(arrival: ArrivalConfig, churn: Option<ChurnConfig>) -> SyntheticTraceConfig {
+        SyntheticTraceConfig {
+            seed: 7,
+            num_requests: 100_000,
+            popularity: PopularityConfig {
+                num_keys: 1000,
+                alpha: 1.0,
+            },
+            arrival,
+            churn,
+        }
+    }
+
+    #[test]
+    fn test_poisson_zipf() {
+        let config = config(ArrivalConfig::Poisson { rate: 1e6 }, None);
+        let mut buffer = Vec::new();
+        write_synthetic_trace(&config, &mut buffer).unwrap();
+        let events = RequestEvent::repeat_read_till_end(Cursor::new(buffer))
+            .map(|r| r.unwrap())
+            .collect::<Vec<_>>();
+
+        assert_eq!(events.len(), config.num_requests);
+        assert!(events.windows(2).all(|w| w[0].timestamp <= w[1].timestamp));
+        // 1M requests per second: mean inter-request time is 1us
+        let mean_irt = events.last().unwrap().timestamp as f64 / events.len() as f64;
+        assert!((mean_irt - 1000.).abs() < 20., "mean irt: {mean_irt}");
+
+        // the most popular key takes 1 / H(1000) ~ 13.4% of requests
+        let mut counts = AHashMap::new();
+        events
+            .iter()
+            .for_each(|e| *counts.entry(e.key).or_insert(0) += 1);
+        let top = *counts.values().max().unwrap() as f64 / events.len() as f64;
+        assert!((top - 0.134).abs() < 0.01, "top share: {top}");
+
+        // the same seed gives the same trace
+        let again = SyntheticTrace::new(&config)
+            .take(config.num_requests)
+            .collect::<Vec<_>>();
+        assert_eq!(events, again);
+    }
+
+    #[test]
+    fn test_bursty_arrivals() {
+        for arrival in [
+            ArrivalConfig::OnOff {
+                rate: 1e6,
+                on_duration_ns: 100_000,
+                off_duration_ns: 100_000,
+            },
+            ArrivalConfig::Hawkes {
+                base_rate: 2.5e5,
+                branching_ratio: 0.5,
+                decay_ns: 10_000,
+            },
+        ] {
+            let events = SyntheticTrace::new(&config(arrival, None))
+                .take(100_000)
+                .collect::<Vec<_>>();
+            assert!(events.windows(2).all(|w| w[0].timestamp <= w[1].timestamp));
+            // both processes have a long-run rate of 0.5M requests per second
+            let mean_irt = events.last().unwrap().timestamp as f64 / events.len() as f64;
+            assert!((mean_irt - 2000.).abs() < 200., "mean irt: {mean_irt}");
+        }
+    }
+
+    #[test]
+    fn test_churn() {
+        let config = config(
+            ArrivalConfig::Poisson { rate: 1e6 },
+            Some(ChurnConfig {
+                interval_ns: 10_000_000,
+                fraction: 0.1,
+            }),
+        );
+        let keys = SyntheticTrace::new(&config)
+            .take(config.num_requests)
+            .map(|e| e.key)
+            .collect::<AHashSet<_>>();
+        // 100ms of requests: 9 churns, each introducing 100 fresh keys
+        assert!(keys.iter().any(|&k| k >= 1000));
+        assert!(keys.iter().all(|&k| k < 1000 + 900));
+    }
+}
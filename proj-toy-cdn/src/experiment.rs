@@ -1,13 +1,11 @@
 use std::{
+    iter::Peekable,
     sync::{Arc, Mutex},
     time::{Duration, Instant},
 };
 
 use ahash::AHashMap;
-use proj_cache_sim::{
-    cache::Cache,
-    simulator::{run_simulation, RequestResult},
-};
+use proj_cache_sim::{cache::Cache, simulator::RequestResult, warmup::WarmupConfig};
 use proj_models::{RequestEvent, RequestId, TimeUnit};
 use proj_net::{
     msg::{CdnRequestMessage, OriginResponseMessage},
@@ -15,11 +13,67 @@ use proj_net::{
 };
 use tracing::{error, info, trace};
 
-struct LocalState<C> {
+/// What the CDN does with a request.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RequestAction {
+    /// The object is cached, so the request is completed right away.
+    Complete,
+    /// The object is neither cached nor being fetched, so it is fetched from the origin.
+    Fetch,
+    /// The object is being fetched, and the request waits for it.
+    Wait,
+}
+
+/// The cache of the CDN and its requests waiting for completions. Both the experiment and its replay
+/// ([`mod@crate::replay`]) drive the CDN through it.
+pub struct LocalState<C> {
     cache: C,
     requests_in_progress: AHashMap<u64, Vec<TimeUnit>>,
 }
 
+impl<C: Cache<RequestId, ()>> LocalState<C> {
+    pub fn new(cache: C) -> Self {
+        Self {
+            cache,
+            requests_in_progress: AHashMap::new(),
+        }
+    }
+
+    /// Handle a request for `key` at `timestamp`. The request waits until a completion of `key`.
+    pub fn on_request(&mut self, key: RequestId, timestamp: TimeUnit) -> RequestAction {
+        // add the request to the in-progress list
+        let requests_in_progress = self.requests_in_progress.entry(key).or_default();
+        requests_in_progress.push(timestamp);
+        let first_request = requests_in_progress.len() == 1;
+        if self.cache.get(&key, timestamp).is_some() {
+            RequestAction::Complete
+        } else if first_request {
+            // the request is not in the cache, and is not in transit, so we need to send it
+            RequestAction::Fetch
+        } else {
+            RequestAction::Wait
+        }
+    }
+
+    /// Handle a completion of `key` at `timestamp`: complete the requests waiting for it, and update the cache.
+    pub fn on_completion(
+        &mut self,
+        key: RequestId,
+        timestamp: TimeUnit,
+    ) -> impl Iterator<Item = RequestResult> {
+        // if there are no pending requests, it's ok. probably just pending requests from warmup
+        let pending_requests = self.requests_in_progress.remove(&key).unwrap_or_default();
+        self.cache.write(key, (), timestamp);
+        pending_requests
+            .into_iter()
+            .map(move |req_timestamp| RequestResult {
+                key,
+                request_timestamp: req_timestamp,
+                completion_timestamp: timestamp,
+            })
+    }
+}
+
 pub struct Clock {
     start_time: Instant,
 }
@@ -42,7 +96,72 @@ impl Clock {
     }
 }
 
-/// - `cache`: An empty cache to use.
+/// How the cache is warmed before the requests are sent to the origin.
+pub enum Warmup {
+    /// Simulate the warmup requests in process, `irt_ns` apart, with the miss latency `miss_latency`. A warmup duration
+    /// is in this replay time.
+    Simulate {
+        config: WarmupConfig,
+        miss_latency: TimeUnit,
+    },
+    /// The cache is restored from a checkpoint taken after `num_requests` requests, which are skipped.
+    Restored {
+        num_requests: usize,
+        last_event_timestamp: TimeUnit,
+    },
+}
+
+/// The requests of a replay, `irt_ns` apart from 0.
+pub fn timed_requests<I>(requests: I, irt_ns: u64) -> Peekable<impl Iterator<Item = RequestEvent>>
+where
+    I: IntoIterator<Item = RequestId>,
+{
+    requests
+        .into_iter()
+        .enumerate()
+        .map(move |(i, r)| RequestEvent {
+            key: r,
+            timestamp: i as u64 * irt_ns,
+        })
+        .peekable()
+}
+
+/// Warm `cache` with the warmup requests at the front of `requests`. Return the timestamp of the last event of the
+/// warmup, after which the trace resumes.
+pub fn warm_up<C, I>(cache: &mut C, requests: &mut Peekable<I>, warmup: Warmup) -> TimeUnit
+where
+    C: Cache<RequestId, ()>,
+    I: Iterator<Item = RequestEvent>,
+{
+    match warmup {
+        Warmup::Simulate {
+            config,
+            miss_latency,
+        } => {
+            info!("Running warmup requests ({})", config);
+            let (boundary, last_event) = config.simulate(cache, requests, miss_latency);
+            info!(
+                "{} warmup requests completed, starting the main simulation",
+                boundary.num_requests
+            );
+            last_event
+        }
+        Warmup::Restored {
+            num_requests,
+            last_event_timestamp,
+        } => {
+            info!(
+                "Skipping {} warmup requests of the restored cache",
+                num_requests
+            );
+            requests.by_ref().take(num_requests).for_each(drop);
+            last_event_timestamp
+        }
+    }
+}
+
+/// - `cache`: An empty cache to use, or the restored cache with [`Warmup::Restored`].
+/// - `requests`: The requests, including the warmup requests. `num_requests` requests after the warmup are replayed.
 ///
 /// We have two tasks running simultaneously:
 /// - The request sending task:
@@ -58,8 +177,8 @@ pub async fn run_cdn_experiment<C, I>(
     mut cache: C,
     requests: I,
     origin: &RemoteChannel<CdnRequestMessage, OriginResponseMessage>,
-    warmup: usize,
-    miss_latency_in_warmup: TimeUnit,
+    warmup: Warmup,
+    num_requests: usize,
     irt_ns: u64,
 ) -> (Vec<RequestResult>, Vec<TimeUnit>, Vec<TimeUnit>)
 where
@@ -67,29 +186,16 @@ where
     I: IntoIterator<Item = RequestId>,
 {
     // warmup requests are not actually sent to the origin and is only used to warm up the cache.
-    let mut requests = requests.into_iter();
-    info!("Running {} warmup requests", warmup);
-    let last_event = tokio::task::block_in_place(|| {
-        let warmup_requests =
-            requests
-                .by_ref()
-                .take(warmup)
-                .enumerate()
-                .map(|(i, r)| RequestEvent {
-                    key: r,
-                    timestamp: i as u64 * irt_ns,
-                });
-        run_simulation(&mut cache, warmup_requests, miss_latency_in_warmup).last_event_timestamp
-    });
-    info!("Warmup requests completed, starting the main simulation");
+    let mut requests = timed_requests(requests, irt_ns);
+    let last_event = tokio::task::block_in_place(|| warm_up(&mut cache, &mut requests, warmup));
 
     // Requests that are currently in fetching state.
-    let state = Arc::new(Mutex::new(LocalState {
-        cache,
-        requests_in_progress: AHashMap::new(),
-    }));
+    let state = Arc::new(Mutex::new(LocalState::new(cache)));
     // just store the requests in memory for better simulation
-    let requests = requests.into_iter().collect::<Vec<_>>();
+    let requests = requests
+        .take(num_requests)
+        .map(|r| r.key)
+        .collect::<Vec<_>>();
     let requests_count = requests.len();
 
     // for simplicity, we assume the trace resumes after all warmup requests are fulfilled.
@@ -112,24 +218,7 @@ where
                     .lock()
                     .expect("state lock is poisoned for completion handling");
                 // trace!("R: {}", request);
-                let pending_requests = if let Some(r) = state.requests_in_progress.remove(&request)
-                {
-                    r
-                } else {
-                    // it's ok. probably just pending requests from warmup
-                    // trace!("Request {} is already fulfilled", request);
-                    Vec::new()
-                };
-                let results = pending_requests
-                    .into_iter()
-                    .map(|req_timestamp| RequestResult {
-                        key: request,
-                        request_timestamp: req_timestamp,
-                        completion_timestamp: timestamp,
-                    });
-                request_results.extend(results);
-
-                state.cache.write(request, (), timestamp);
+                request_results.extend(state.on_completion(request, timestamp));
 
                 // report progress
                 if last_progress_timestamp.elapsed() > Duration::from_secs(3) {
@@ -163,31 +252,24 @@ where
                 // let timestamp = start_of_time.elapsed().as_nanos() as TimeUnit;
                 let timestamp = (clock.start_time() - start_of_time).as_nanos() as TimeUnit;
 
-                let (first_request, cache_hit) = {
-                    let mut state = state
-                        .lock()
-                        .expect("state lock is poisoned for request sending");
-                    // add the request to the in-progress list
-                    let requests_in_progress =
-                        state.requests_in_progress.entry(request).or_default();
-                    requests_in_progress.push(timestamp);
-                    let first_request = requests_in_progress.len() == 1;
-                    let cache_hit = state.cache.get(&request, timestamp).is_some();
-                    (first_request, cache_hit)
-                };
-                // trace!("S: {} {}", request, if cache_hit { "" } else { "(miss)" });
-                if cache_hit {
-                    // the request is immediately fulfilled.
-                    completion_sender
-                        .send(request)
-                        .expect("completion receiver is completed but got a message");
-                } else {
-                    if first_request {
-                        // the request is not in the cache, and is not in transit, so we need to send it
+                let action = state
+                    .lock()
+                    .expect("state lock is poisoned for request sending")
+                    .on_request(request, timestamp);
+                // trace!("S: {} {:?}", request, action);
+                match action {
+                    RequestAction::Complete => {
+                        // the request is immediately fulfilled.
+                        completion_sender
+                            .send(request)
+                            .expect("completion receiver is completed but got a message");
+                    }
+                    RequestAction::Fetch => {
                         origin_request_timestamps.push(timestamp);
                         let handle = origin.send(request.into()).await;
                         send_handles.push(handle);
                     }
+                    RequestAction::Wait => {}
                 }
                 clock.wait_until_next_available(irt_ns).await;
             }
@@ -1,42 +1,32 @@
 #[allow(unused)]
 mod experiment;
+mod replay;
 
-use std::{str::FromStr, time::Instant};
+use std::time::Instant;
 
 use clap::{Parser as _, Subcommand};
 use clap_derive::Parser;
-use experiment::{run_cdn_experiment, Clock};
+use experiment::{run_cdn_experiment, Clock, Warmup};
 use proj_cache_sim::{
-    cache::{construct_k_way_cache, lru::LRU, lru_mad::LRUMinAD, Cache},
+    cache::{
+        mapping::SetMapping,
+        registry::{construct_policy, parse_policy_name, PolicyParams, DEFAULT_COST_COEFFICIENT},
+        BoxedCache,
+    },
+    checkpoint::load_checkpoint,
     get_time_string,
     io::load_events_file,
-    simulator::compute_statistics,
+    simulator::{compute_statistics, RequestResult},
+    warmup::WarmupConfig,
 };
 use proj_models::{RequestId, TimeUnit};
 use proj_net::{
     msg::{CdnRequestMessage, OriginResponseMessage},
     ConnectionMode, RemoteChannel,
 };
+use replay::{replay_cdn_experiment, simulate_replay, LatencyModel, VirtualOrigin};
 use tracing::info;
 
-#[derive(Clone, Copy, PartialEq, Eq, Debug)]
-enum CacheType {
-    LRU,
-    LRUMinAD,
-}
-
-impl FromStr for CacheType {
-    type Err = String;
-
-    fn from_str(s: &str) -> Result<Self, Self::Err> {
-        match s {
-            "lru" => Ok(Self::LRU),
-            "lru-mad" => Ok(Self::LRUMinAD),
-            _ => Err(format!("Unknown cache type: {}", s)),
-        }
-    }
-}
-
 #[derive(Subcommand, Debug)]
 enum Commands {
     Bench {
@@ -49,49 +39,85 @@ enum Commands {
         num_measure_rounds: usize,
     },
     Experiment {
-        #[clap(required = true, help = "Path to the processed events file")]
-        event_path: String,
-        #[clap(
-            long,
-            short = 'k',
-            help = "number of caches (k for k-way set-associative cache)"
-        )]
-        cache_count: usize,
-        #[clap(long, short = 'c', help = "cache capacity in each cache")]
-        cache_capacity: usize,
-        #[clap(
-            long,
-            short = 'w',
-            help = "number of warmup requests to warm the cache before sending actual requests. Those requests are not sent to the internet and are not counted."
-        )]
-        warmup: usize,
+        #[clap(flatten)]
+        args: ExperimentArgs,
+    },
+    /// Replay the experiment in virtual time against an in-process origin, on one machine and without a network.
+    Replay {
+        #[clap(flatten)]
+        args: ExperimentArgs,
         #[clap(
             long,
-            short = 'm',
-            help = "number of actual requests to process after the warmup"
+            short = 'o',
+            help = "latency of the in-process origin, with unit: a constant (e.g. 5ms), or uniform in <min>-<max> (e.g. 4ms-6ms)"
         )]
-        num_requests: usize,
-        #[clap(long, short = 't', default_value = "lru", help = "cache type")]
-        cache_type: CacheType,
-        #[clap(long, short = 'l', help = "estimated miss latency for warmup, with unit (e.g. 300ns, 2ms)", value_parser = proj_cache_sim::parse_time_unit)]
-        miss_latency: u64,
+        origin_latency: LatencyModel,
+        #[clap(long, default_value = "0", help = "seed of the origin latencies")]
+        seed: u64,
         #[clap(
             long,
-            short = 'i',
-            help = "inter-request time, with unit (e.g. 300ns, 2ms)",
-            value_parser = proj_cache_sim::parse_time_unit,
-            default_value = "1us"
+            help = "also simulate the replayed requests with `run_simulation`, using the mean origin latency as the miss latency, and compare the results"
         )]
-        irt: TimeUnit,
+        compare: bool,
     },
 }
 
+#[derive(clap_derive::Args, Debug)]
+struct ExperimentArgs {
+    #[clap(required = true, help = "Path to the processed events file")]
+    event_path: String,
+    #[clap(
+        long,
+        short = 'k',
+        help = "number of caches (k for k-way set-associative cache)"
+    )]
+    cache_count: usize,
+    #[clap(long, short = 'c', help = "cache capacity in each cache")]
+    cache_capacity: usize,
+    #[clap(
+        long,
+        short = 'w',
+        help = "warmup to warm the cache before sending actual requests: a number of requests, a duration with unit (e.g. 2s, in replay time with requests `irt` apart), or auto[:<window>[:<tolerance>]] to end when the hit ratio is stable. Those requests are not sent to the internet and are not counted."
+    )]
+    warmup: WarmupConfig,
+    #[clap(
+        long,
+        short = 'm',
+        help = "number of actual requests to process after the warmup"
+    )]
+    num_requests: usize,
+    #[clap(
+        long = "policy",
+        short = 't',
+        alias = "cache-type",
+        default_value = "lru",
+        value_parser = parse_policy_name,
+        help = "cache policy (e.g. lru, lru-mad). Only one policy per run, as the origin stops after the experiment"
+    )]
+    policy: &'static str,
+    #[clap(long, short = 'l', help = "estimated miss latency for warmup, with unit (e.g. 300ns, 2ms)", value_parser = proj_cache_sim::parse_time_unit)]
+    miss_latency: u64,
+    #[clap(
+        long,
+        short = 'i',
+        help = "inter-request time, with unit (e.g. 300ns, 2ms)",
+        value_parser = proj_cache_sim::parse_time_unit,
+        default_value = "1us"
+    )]
+    irt: TimeUnit,
+    #[clap(
+        long,
+        help = "restore the warmed cache from a checkpoint (e.g. saved by `proj-experiments trace --save-warm-state`) instead of simulating the warmup"
+    )]
+    warm_state: Option<String>,
+}
+
 #[derive(Parser, Debug)]
 struct Args {
     #[command(subcommand)]
     command: Commands,
-    #[clap(long, short = 'c', value_parser = proj_net::parse_connection_mode, help = "use <ip_addr>:<port> for client, <port> for server")]
-    conn: ConnectionMode,
+    #[clap(long, short = 'c', value_parser = proj_net::parse_connection_mode, help = "use <ip_addr>:<port> for client, <port> for server. Not needed to replay")]
+    conn: Option<ConnectionMode>,
     #[clap(long, short = 'n', default_value = "8", help = "number of connections")]
     num_connections: usize,
 
@@ -177,24 +203,36 @@ async fn measurement(
     npy::to_file("response_timestamps.npy", ends_ns).unwrap();
 }
 
-async fn experiment_on_cache<C>(
-    cache: C,
+async fn experiment_on_cache(
+    cache: BoxedCache<RequestId, ()>,
     chan: RemoteChannel<CdnRequestMessage, OriginResponseMessage>,
     event_path: String,
-    warmup: usize,
+    warmup: Warmup,
     num_requests: usize,
-    estimated_miss_latency: u64,
     irt_ns: u64,
-) where
-    C: Cache<RequestId, ()> + Send + 'static,
-{
-    let events = load_events_file(&event_path)
-        .take(warmup + num_requests)
-        .map(|r| r.key);
+) {
+    let policy = cache.name();
+    let events = load_events_file(&event_path).map(|r| r.key);
     let (results, origin_send_timestamps, origin_response_timestamps) =
-        run_cdn_experiment(cache, events, &chan, warmup, estimated_miss_latency, irt_ns).await;
+        run_cdn_experiment(cache, events, &chan, warmup, num_requests, irt_ns).await;
+    report_results(
+        policy,
+        "",
+        &results,
+        origin_send_timestamps,
+        origin_response_timestamps,
+    );
+}
 
-    let stats = compute_statistics(&results);
+/// Log the statistics of the results, and save the timestamps to `.npy` files whose names start with `prefix`.
+fn report_results(
+    policy: &str,
+    prefix: &str,
+    results: &[RequestResult],
+    origin_send_timestamps: Vec<TimeUnit>,
+    origin_response_timestamps: Vec<TimeUnit>,
+) {
+    let stats = compute_statistics(results);
     info!(
         "Average latency: {} ({:.2} us)",
         get_time_string(stats.average_latency as u128),
@@ -209,15 +247,23 @@ async fn experiment_on_cache<C>(
         .iter()
         .map(|r| r.completion_timestamp)
         .collect::<Vec<_>>();
-    npy::to_file(format!("request_starts_{}.npy", C::NAME), request_starts).unwrap();
-    npy::to_file(format!("request_ends_{}.npy", C::NAME), request_ends).unwrap();
     npy::to_file(
-        format!("origin_send_timestamps_{}.npy", C::NAME),
+        format!("{}request_starts_{}.npy", prefix, policy),
+        request_starts,
+    )
+    .unwrap();
+    npy::to_file(
+        format!("{}request_ends_{}.npy", prefix, policy),
+        request_ends,
+    )
+    .unwrap();
+    npy::to_file(
+        format!("{}origin_send_timestamps_{}.npy", prefix, policy),
         origin_send_timestamps,
     )
     .unwrap();
     npy::to_file(
-        format!("origin_response_timestamps_{}.npy", C::NAME),
+        format!("{}origin_response_timestamps_{}.npy", prefix, policy),
         origin_response_timestamps,
     )
     .unwrap();
@@ -231,47 +277,105 @@ async fn experiment_on_cache<C>(
     info!("P99 Latency: {:.2} ms", p99 as f64 / 1_000_000.0);
 }
 
+/// Construct the cache of the experiment, and how it is warmed.
+fn prepare_cache(args: &ExperimentArgs) -> (BoxedCache<RequestId, ()>, Warmup) {
+    let params = PolicyParams {
+        cache_counts: args.cache_count,
+        cache_capacity: args.cache_capacity,
+        estimated_miss_latency: args.miss_latency,
+        set_mapping: SetMapping::default(),
+        cost_coefficient: DEFAULT_COST_COEFFICIENT,
+    };
+    let mut cache = construct_policy(args.policy, &params).unwrap();
+    let warmup = match &args.warm_state {
+        Some(path) => {
+            let header = load_checkpoint(path, &mut cache)
+                .unwrap_or_else(|e| panic!("failed to load {}: {}", path, e));
+            assert_eq!(
+                args.warmup.num_requests(),
+                Some(header.num_requests as usize),
+                "{} is saved after {} warmup requests",
+                path,
+                header.num_requests
+            );
+            info!("Restored the cache from {}", path);
+            Warmup::Restored {
+                num_requests: header.num_requests as usize,
+                last_event_timestamp: header.last_event_timestamp,
+            }
+        }
+        None => Warmup::Simulate {
+            config: args.warmup,
+            miss_latency: args.miss_latency,
+        },
+    };
+    (cache, warmup)
+}
+
 async fn experiment(
     chan: RemoteChannel<CdnRequestMessage, OriginResponseMessage>,
-    cache_type: CacheType,
-    event_path: String,
-    cache_count: usize,
-    cache_capacity: usize,
-    warmup: usize,
-    num_requests: usize,
-    estimated_miss_latency_ns: TimeUnit,
-    irt_ns: TimeUnit,
+    args: ExperimentArgs,
 ) {
-    match cache_type {
-        CacheType::LRU => {
-            let cache = construct_k_way_cache(cache_count, |_| LRU::new(cache_capacity));
-            experiment_on_cache(
-                cache,
-                chan,
-                event_path,
-                warmup,
-                num_requests,
-                estimated_miss_latency_ns,
-                irt_ns,
-            )
-            .await
-        }
-        CacheType::LRUMinAD => {
-            let cache = construct_k_way_cache(cache_count, |_| {
-                LRUMinAD::new(cache_capacity, estimated_miss_latency_ns)
-            });
-            experiment_on_cache(
-                cache,
-                chan,
-                event_path,
-                warmup,
-                num_requests,
-                estimated_miss_latency_ns,
-                irt_ns,
-            )
-            .await
-        }
-    };
+    let (cache, warmup) = prepare_cache(&args);
+    experiment_on_cache(
+        cache,
+        chan,
+        args.event_path,
+        warmup,
+        args.num_requests,
+        args.irt,
+    )
+    .await
+}
+
+/// Replay the experiment against an in-process origin, and compare it with the simulator if `compare` is set.
+fn replay(args: ExperimentArgs, origin_latency: LatencyModel, seed: u64, compare: bool) {
+    let (cache, warmup) = prepare_cache(&args);
+    let policy = cache.name();
+    let events = load_events_file(&args.event_path).map(|r| r.key);
+    info!("Replaying with origin latency {}", origin_latency);
+    let mut origin = VirtualOrigin::new(origin_latency, seed);
+    let (results, origin_send_timestamps, origin_response_timestamps) = replay_cdn_experiment(
+        cache,
+        events,
+        &mut origin,
+        warmup,
+        args.num_requests,
+        args.irt,
+    );
+    let stats = compute_statistics(&results);
+    report_results(
+        policy,
+        "replay_",
+        &results,
+        origin_send_timestamps,
+        origin_response_timestamps,
+    );
+    if !compare {
+        return;
+    }
+
+    let (cache, warmup) = prepare_cache(&args);
+    let events = load_events_file(&args.event_path).map(|r| r.key);
+    let simulated = simulate_replay(
+        cache,
+        events,
+        warmup,
+        args.num_requests,
+        args.irt,
+        origin_latency.mean(),
+    );
+    let simulated_stats = compute_statistics(&simulated.results);
+    for (name, stats) in [("replay", &stats), ("simulation", &simulated_stats)] {
+        info!(
+            "{}: average latency {}, {} hits, {} delayed hits, {} misses",
+            name,
+            get_time_string(stats.average_latency as u128),
+            stats.num_hits,
+            stats.num_delayed_hits,
+            stats.num_misses
+        );
+    }
 }
 
 fn main() {
@@ -283,39 +387,30 @@ fn main() {
             .init();
         info!("{:?}", args);
 
-        let chan = RemoteChannel::<CdnRequestMessage, OriginResponseMessage>::new(
-            args.conn,
-            args.num_connections,
-            args.num_msg_buffered,
-        )
-        .await;
-        match args.command {
+        let Args {
+            command,
+            conn,
+            num_connections,
+            num_msg_buffered,
+        } = args;
+        let connect = || {
+            RemoteChannel::<CdnRequestMessage, OriginResponseMessage>::new(
+                conn.expect("--conn is required to connect to the origin"),
+                num_connections,
+                num_msg_buffered,
+            )
+        };
+        match command {
             Commands::Bench { num_measure_rounds } => {
-                measurement(chan, num_measure_rounds).await;
-            }
-            Commands::Experiment {
-                event_path,
-                cache_count,
-                cache_capacity,
-                cache_type,
-                warmup,
-                num_requests,
-                miss_latency,
-                irt,
-            } => {
-                experiment(
-                    chan,
-                    cache_type,
-                    event_path,
-                    cache_count,
-                    cache_capacity,
-                    warmup,
-                    num_requests,
-                    miss_latency,
-                    irt,
-                )
-                .await
+                measurement(connect().await, num_measure_rounds).await;
             }
+            Commands::Experiment { args } => experiment(connect().await, args).await,
+            Commands::Replay {
+                args,
+                origin_latency,
+                seed,
+                compare,
+            } => replay(args, origin_latency, seed, compare),
         }
     })
 }
@@ -0,0 +1,279 @@
+//! A discrete-event replay of the CDN experiment on one machine: the CDN logic of [`run_cdn_experiment`] is driven
+//! in virtual time against an in-process origin, instead of the wall clock and a remote origin. It is deterministic,
+//! so the experiment code path can be debugged, compared to [`run_simulation`], and run in CI.
+//!
+//! [`run_cdn_experiment`]: crate::experiment::run_cdn_experiment
+//! [`run_simulation`]: proj_cache_sim::simulator::run_simulation
+
+use std::{cmp::Reverse, collections::BinaryHeap};
+
+use proj_cache_sim::{
+    cache::Cache,
+    simulator::{run_simulation, RequestResult, SimulationResult},
+};
+use proj_models::{RequestEvent, RequestId, TimeUnit};
+use rand::{Rng, SeedableRng};
+use rand_xorshift::XorShiftRng;
+use tracing::info;
+
+use crate::experiment::{timed_requests, warm_up, LocalState, RequestAction, Warmup};
+
+/// The time the origin takes to respond to a fetch.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LatencyModel {
+    /// Every fetch takes the same time.
+    Constant(TimeUnit),
+    /// Fetches take a time drawn uniformly in `[min, max]`, e.g. to model the jitter of the network.
+    Uniform { min: TimeUnit, max: TimeUnit },
+}
+
+impl LatencyModel {
+    pub fn mean(&self) -> TimeUnit {
+        match *self {
+            Self::Constant(latency) => latency,
+            Self::Uniform { min, max } => min + (max - min) / 2,
+        }
+    }
+}
+
+impl std::fmt::Display for LatencyModel {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Constant(latency) => write!(f, "{}", latency),
+            Self::Uniform { min, max } => write!(f, "{}-{}", min, max),
+        }
+    }
+}
+
+impl std::str::FromStr for LatencyModel {
+    type Err = String;
+
+    /// Parse `<latency>` for a constant latency, or `<min>-<max>` for a uniform one, with time units (e.g. `4ms-6ms`).
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let parse = |t: &str| {
+            proj_cache_sim::parse_time_unit(t)
+                .map_err(|e| format!("Invalid origin latency {:?}: {}", s, e))
+        };
+        match s.split_once('-') {
+            Some((min, max)) => {
+                let (min, max) = (parse(min)?, parse(max)?);
+                if min > max {
+                    return Err(format!(
+                        "Invalid origin latency {:?}: min should not exceed max",
+                        s
+                    ));
+                }
+                Ok(Self::Uniform { min, max })
+            }
+            None => parse(s).map(Self::Constant),
+        }
+    }
+}
+
+/// An origin in the same process, responding to each fetch after a latency drawn from its [`LatencyModel`].
+pub struct VirtualOrigin {
+    latency: LatencyModel,
+    rng: XorShiftRng,
+    /// Fetches in flight, as `(response timestamp, sequence number, key)`, so that responses at the same timestamp
+    /// arrive in the order of the fetches.
+    in_flight: BinaryHeap<Reverse<(TimeUnit, usize, RequestId)>>,
+    num_sent: usize,
+}
+
+impl VirtualOrigin {
+    /// - `seed`: seed of the random latencies.
+    pub fn new(latency: LatencyModel, seed: u64) -> Self {
+        Self {
+            latency,
+            rng: XorShiftRng::seed_from_u64(seed),
+            in_flight: BinaryHeap::new(),
+            num_sent: 0,
+        }
+    }
+
+    /// Fetch `key` at `timestamp`.
+    pub fn send(&mut self, key: RequestId, timestamp: TimeUnit) {
+        let latency = match self.latency {
+            LatencyModel::Constant(latency) => latency,
+            LatencyModel::Uniform { min, max } => self.rng.gen_range(min..=max),
+        };
+        self.in_flight
+            .push(Reverse((timestamp + latency, self.num_sent, key)));
+        self.num_sent += 1;
+    }
+
+    /// The next response before `timestamp`, as `(timestamp, key)`.
+    pub fn recv_before(&mut self, timestamp: TimeUnit) -> Option<(TimeUnit, RequestId)> {
+        let Reverse((response_timestamp, _, key)) = *self.in_flight.peek()?;
+        if response_timestamp >= timestamp {
+            return None;
+        }
+        self.in_flight.pop();
+        Some((response_timestamp, key))
+    }
+}
+
+/// Warm up `cache`, and return the `num_requests` requests after the warmup with the timestamps they are sent at in
+/// the experiment: the trace resumes `irt_ns` after the last event of the warmup.
+fn schedule_requests<C, I>(
+    cache: &mut C,
+    requests: I,
+    warmup: Warmup,
+    num_requests: usize,
+    irt_ns: u64,
+) -> Vec<RequestEvent>
+where
+    C: Cache<RequestId, ()>,
+    I: IntoIterator<Item = RequestId>,
+{
+    let mut requests = timed_requests(requests, irt_ns);
+    let last_event = warm_up(cache, &mut requests, warmup);
+    requests
+        .take(num_requests)
+        .enumerate()
+        .map(|(i, r)| RequestEvent {
+            key: r.key,
+            timestamp: last_event + (i as u64 + 1) * irt_ns,
+        })
+        .collect()
+}
+
+/// Same as [`run_cdn_experiment`], in virtual time against `origin`. Hits are completed at their request timestamp,
+/// and a response at the same timestamp as a request is handled after the request, as in [`run_simulation`].
+///
+/// Return the request results in the order of completion, the timestamps of the fetches and the timestamps of the
+/// responses.
+///
+/// [`run_cdn_experiment`]: crate::experiment::run_cdn_experiment
+pub fn replay_cdn_experiment<C, I>(
+    mut cache: C,
+    requests: I,
+    origin: &mut VirtualOrigin,
+    warmup: Warmup,
+    num_requests: usize,
+    irt_ns: u64,
+) -> (Vec<RequestResult>, Vec<TimeUnit>, Vec<TimeUnit>)
+where
+    C: Cache<RequestId, ()>,
+    I: IntoIterator<Item = RequestId>,
+{
+    let requests = schedule_requests(&mut cache, requests, warmup, num_requests, irt_ns);
+    let mut state = LocalState::new(cache);
+    let mut request_results = Vec::with_capacity(requests.len());
+    let mut origin_request_timestamps = Vec::new();
+    let mut origin_response_timestamps = Vec::new();
+
+    for request in requests {
+        // responses at the timestamp of the request are handled after it
+        while let Some((timestamp, key)) = origin.recv_before(request.timestamp) {
+            origin_response_timestamps.push(timestamp);
+            request_results.extend(state.on_completion(key, timestamp));
+        }
+        match state.on_request(request.key, request.timestamp) {
+            RequestAction::Complete => {
+                request_results.extend(state.on_completion(request.key, request.timestamp))
+            }
+            RequestAction::Fetch => {
+                origin_request_timestamps.push(request.timestamp);
+                origin.send(request.key, request.timestamp);
+            }
+            RequestAction::Wait => {}
+        }
+    }
+    while let Some((timestamp, key)) = origin.recv_before(TimeUnit::MAX) {
+        origin_response_timestamps.push(timestamp);
+        request_results.extend(state.on_completion(key, timestamp));
+    }
+    info!("All requests fulfilled. Replay completed.");
+    (
+        request_results,
+        origin_request_timestamps,
+        origin_response_timestamps,
+    )
+}
+
+/// Run [`run_simulation`] on the requests of [`replay_cdn_experiment`], with the same warmup and timestamps, to compare
+/// the replay with the simulator.
+pub fn simulate_replay<C, I>(
+    mut cache: C,
+    requests: I,
+    warmup: Warmup,
+    num_requests: usize,
+    irt_ns: u64,
+    miss_latency: TimeUnit,
+) -> SimulationResult
+where
+    C: Cache<RequestId, ()>,
+    I: IntoIterator<Item = RequestId>,
+{
+    let requests = schedule_requests(&mut cache, requests, warmup, num_requests, irt_ns);
+    run_simulation(&mut cache, requests, miss_latency)
+}
+
+#[cfg(test)]
+mod tests {
+    use proj_cache_sim::{
+        cache::{construct_k_way_cache, lru_mad::LRUMinAD},
+        warmup::WarmupConfig,
+    };
+
+    use super::*;
+
+    fn trace() -> impl Iterator<Item = RequestId> {
+        // bursts of the same key, over a working set larger than the cache
+        (0..3000u64).map(|i| (i / 3 * 7919) % 61)
+    }
+
+    fn warmup() -> Warmup {
+        Warmup::Simulate {
+            config: WarmupConfig::Requests(500),
+            miss_latency: 10,
+        }
+    }
+
+    fn sorted(mut results: Vec<RequestResult>) -> Vec<RequestResult> {
+        results.sort_by_key(|r| (r.request_timestamp, r.completion_timestamp, r.key));
+        results
+    }
+
+    #[test]
+    fn test_replay_matches_simulation_with_constant_latency() {
+        let cache = || construct_k_way_cache(4, |_| LRUMinAD::new(5, 10));
+        let mut origin = VirtualOrigin::new(LatencyModel::Constant(10), 0);
+        let (results, sends, responses) =
+            replay_cdn_experiment(cache(), trace(), &mut origin, warmup(), 2000, 3);
+        assert_eq!(results.len(), 2000);
+        assert_eq!(sends.len(), responses.len());
+
+        let simulated = simulate_replay(cache(), trace(), warmup(), 2000, 3, 10);
+        assert_eq!(sends.len(), simulated.num_of_loads);
+        assert_eq!(sorted(results), sorted(simulated.results));
+    }
+
+    #[test]
+    fn test_replay_with_uniform_latency() {
+        let latency = "8-12".parse::<LatencyModel>().unwrap();
+        assert_eq!(latency, LatencyModel::Uniform { min: 8, max: 12 });
+        assert_eq!(latency.mean(), 10);
+        assert!("12-8".parse::<LatencyModel>().is_err());
+
+        let replay = |seed| {
+            let mut origin = VirtualOrigin::new(latency, seed);
+            let cache = construct_k_way_cache(4, |_| LRUMinAD::new(5, 10));
+            replay_cdn_experiment(cache, trace(), &mut origin, warmup(), 2000, 3)
+        };
+        let (results, sends, responses) = replay(1);
+        assert_eq!(results.len(), 2000);
+        assert_eq!(sends.len(), responses.len());
+        for r in &results {
+            let latency = r.completion_timestamp - r.request_timestamp;
+            assert!(latency <= 12);
+            // requests are 3 apart, so a fetch is identified by the timestamp of its request
+            if sends.contains(&r.request_timestamp) {
+                assert!(latency >= 8);
+            }
+        }
+        // the replay is deterministic for a seed
+        assert_eq!(replay(1).0, results);
+    }
+}
@@ -62,6 +62,39 @@ plt.savefig(f'{sim_dir}storage.png')
 draw_latency('results/cdn.csv', 'CDN', [1e-3, 1e-2, 1e-1, 1, 1e1, 1e2, 1e3, 1e4], ['1us', '10us', '100us', '1ms', '10ms', '100ms', '1s', '10s'], 60)
 plt.savefig(f'{sim_dir}cdn.png')
 
+# %%
+# miss-ratio and latency curves over cache sizes, generated by `proj-experiments curve`
+def draw_curve(data_path, label: str):
+    df = pd.read_csv(data_path)
+    way = df['k'][0]
+    latency = df['miss_latency'][0] / 1e6
+
+    fig, (ax1, ax2) = plt.subplots(1, 2, figsize=(10, 3))
+    ax1.plot(df['c'], df['lru_miss_ratio'] * 100, marker='v', label='LRU (zero-latency miss)')
+    ax1.plot(df['c'], df['lru_delayed_miss_ratio'] * 100, marker='v', label='LRU (miss + delayed hit)')
+    ax1.plot(df['c'], df['lru_mad_delayed_miss_ratio'] * 100, marker='v', label='LRU-MAD (miss + delayed hit)')
+    ax1.set_xscale('log', base=2)
+    ax1.set_xlabel('Lines per Set')
+    ax1.set_ylabel('\% Requests')
+    ax1.legend()
+    ax1.grid(True, which='major', axis='both', linestyle='--', linewidth=0.5)
+
+    ax2.plot(df['c'], df['lru_average_latency'] / 1e6, marker='v', label='LRU')
+    ax2.plot(df['c'], df['lru_mad_average_latency'] / 1e6, marker='v', label='LRU-MAD')
+    ax2.set_xscale('log', base=2)
+    ax2.set_xlabel('Lines per Set')
+    ax2.set_ylabel('Average Latency (ms)')
+    ax2.legend()
+    ax2.grid(True, which='major', axis='both', linestyle='--', linewidth=0.5)
+
+    fig.suptitle(f"{label}: {way}-way associative, {latency}ms miss latency")
+    plt.tight_layout()
+
+# %%
+if os.path.exists('results/curve.csv'):
+    draw_curve('results/curve.csv', 'Cache Size Sweep')
+    plt.savefig(f'{sim_dir}curve.png')
+
 # %% [markdown]
 # ## CDN Experiment
 